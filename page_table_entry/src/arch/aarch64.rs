@@ -5,7 +5,7 @@ use core::fmt;
 use aarch64_cpu::registers::MAIR_EL1;
 use memory_addr::PhysAddr;
 
-use crate::{GenericPTE, MappingFlags};
+use crate::{AccessState, GenericPTE, MappingFlags, MemType};
 
 bitflags::bitflags! {
     /// Memory attribute fields in the VMSAv8-64 translation table format descriptors.
@@ -15,8 +15,8 @@ bitflags::bitflags! {
 
         /// Whether the descriptor is valid.
         const VALID =       1 << 0;
-        /// The descriptor gives the address of the next level of translation table or 4KB page.
-        /// (not a 2M, 1G block)
+        /// The descriptor gives the address of the next level of translation table or a page at
+        /// the last level (not a block).
         const NON_BLOCK =   1 << 1;
         /// Memory attributes index field.
         const ATTR_INDX =   0b111 << 2;
@@ -35,6 +35,9 @@ bitflags::bitflags! {
         const AF =          1 << 10;
         /// The not global bit.
         const NG =          1 << 11;
+        /// Dirty Bit Modifier. With `FEAT_HAFDBS`, a write to a read-only page
+        /// clears `AP_RO` instead of generating a permission fault.
+        const DBM =         1 << 51;
         /// Indicates that 16 adjacent translation table entries point to contiguous memory regions.
         const CONTIGUOUS =  1 <<  52;
         /// The Privileged execute-never field.
@@ -69,6 +72,10 @@ pub enum MemAttr {
     Normal             = 1,
     /// Normal non-cacheable memory
     NormalNonCacheable = 2,
+    /// Device-nGnRnE memory
+    DeviceStronglyOrdered = 3,
+    /// Normal write-through cacheable memory
+    NormalWriteThrough = 4,
 }
 
 impl DescriptorAttr {
@@ -79,7 +86,10 @@ impl DescriptorAttr {
     /// empty.
     pub const fn from_mem_attr(idx: MemAttr) -> Self {
         let mut bits = (idx as u64) << 2;
-        if matches!(idx, MemAttr::Normal | MemAttr::NormalNonCacheable) {
+        if matches!(
+            idx,
+            MemAttr::Normal | MemAttr::NormalNonCacheable | MemAttr::NormalWriteThrough
+        ) {
             bits |= Self::INNER.bits() | Self::SHAREABLE.bits();
         }
         Self::from_bits_retain(bits)
@@ -92,11 +102,36 @@ impl DescriptorAttr {
             0 => MemAttr::Device,
             1 => MemAttr::Normal,
             2 => MemAttr::NormalNonCacheable,
+            3 => MemAttr::DeviceStronglyOrdered,
+            4 => MemAttr::NormalWriteThrough,
             _ => return None,
         })
     }
 }
 
+impl DescriptorAttr {
+    // A writable page is dirty unless it has been made read-only with the
+    // dirty bit modifier set.
+    fn access_state(bits: u64) -> AccessState {
+        let mut state = AccessState::from_pte_bits(bits, Self::AF.bits(), 0);
+        if bits & (Self::DBM.bits() | Self::AP_RO.bits()) == Self::DBM.bits() {
+            state |= AccessState::DIRTY;
+        }
+        state
+    }
+
+    fn clear_access_state(bits: &mut u64, state: AccessState) -> AccessState {
+        let old = crate::update_bits_u64(bits, |bits| {
+            let mut bits = bits & !state.pte_bits(Self::AF.bits(), 0);
+            if state.contains(AccessState::DIRTY) && bits & Self::DBM.bits() != 0 {
+                bits |= Self::AP_RO.bits();
+            }
+            bits
+        });
+        Self::access_state(old)
+    }
+}
+
 impl MemAttr {
     /// The MAIR_ELx register should be set to this value to match the memory
     /// attributes in the descriptors.
@@ -108,17 +143,58 @@ impl MemAttr {
             | MAIR_EL1::Attr1_Normal_Outer::WriteBack_NonTransient_ReadWriteAlloc.value;
         let attr2 = MAIR_EL1::Attr2_Normal_Inner::NonCacheable.value
             + MAIR_EL1::Attr2_Normal_Outer::NonCacheable.value;
-        attr0 | attr1 | attr2 // 0x44_ff_04
+        // Device-nGnRnE memory
+        let attr3 = MAIR_EL1::Attr3_Device::nonGathering_nonReordering_noEarlyWriteAck.value;
+        // Normal write-through memory
+        let attr4 = MAIR_EL1::Attr4_Normal_Inner::WriteThrough_NonTransient_ReadWriteAlloc.value
+            | MAIR_EL1::Attr4_Normal_Outer::WriteThrough_NonTransient_ReadWriteAlloc.value;
+        attr0 | attr1 | attr2 | attr3 | attr4 // 0xbb_00_44_ff_04
     };
+
+    /// Returns the memory attribute that the portable memory type is mapped
+    /// to.
+    ///
+    /// [`MemType::WriteCombining`] falls back to normal non-cacheable memory,
+    /// which allows gathering of writes.
+    pub const fn from_mem_type(mem_type: MemType) -> Self {
+        match mem_type.fallback(MEM_TYPES) {
+            MemType::Normal => Self::Normal,
+            MemType::WriteThrough => Self::NormalWriteThrough,
+            MemType::Device => Self::Device,
+            MemType::StronglyOrdered => Self::DeviceStronglyOrdered,
+            _ => Self::NormalNonCacheable,
+        }
+    }
+
+    /// Returns the portable memory type of this memory attribute.
+    pub const fn mem_type(self) -> MemType {
+        match self {
+            Self::Device => MemType::Device,
+            Self::Normal => MemType::Normal,
+            Self::NormalNonCacheable => MemType::Uncached,
+            Self::DeviceStronglyOrdered => MemType::StronglyOrdered,
+            Self::NormalWriteThrough => MemType::WriteThrough,
+        }
+    }
 }
 
+// The portable memory types honoured by stage 1 descriptors.
+const MEM_TYPES: &[MemType] = &[
+    MemType::Normal,
+    MemType::WriteThrough,
+    MemType::Uncached,
+    MemType::Device,
+    MemType::StronglyOrdered,
+];
+
 impl From<DescriptorAttr> for MappingFlags {
     fn from(attr: DescriptorAttr) -> Self {
         if !attr.contains(DescriptorAttr::VALID) {
             return Self::empty();
         }
         let mut flags = Self::READ;
-        if !attr.contains(DescriptorAttr::AP_RO) {
+        // A clean page is writable with the dirty bit modifier.
+        if !attr.contains(DescriptorAttr::AP_RO) || attr.contains(DescriptorAttr::DBM) {
             flags |= Self::WRITE;
         }
         #[cfg(not(feature = "arm-el2"))]
@@ -138,10 +214,12 @@ impl From<DescriptorAttr> for MappingFlags {
                 flags |= Self::EXECUTE;
             }
         }
-        match attr.mem_attr() {
-            Some(MemAttr::Device) => flags |= Self::DEVICE,
-            Some(MemAttr::NormalNonCacheable) => flags |= Self::UNCACHED,
-            _ => {}
+        #[cfg(not(feature = "arm-el2"))]
+        if !attr.contains(DescriptorAttr::NG) {
+            flags |= Self::GLOBAL;
+        }
+        if let Some(mem_attr) = attr.mem_attr() {
+            flags |= mem_attr.mem_type().into();
         }
         flags
     }
@@ -152,17 +230,13 @@ impl From<MappingFlags> for DescriptorAttr {
         if flags.is_empty() {
             return Self::empty();
         }
-        let mut attr = if flags.contains(MappingFlags::DEVICE) {
-            Self::from_mem_attr(MemAttr::Device)
-        } else if flags.contains(MappingFlags::UNCACHED) {
-            Self::from_mem_attr(MemAttr::NormalNonCacheable)
-        } else {
-            Self::from_mem_attr(MemAttr::Normal)
-        };
+        let mut attr = Self::from_mem_attr(MemAttr::from_mem_type(flags.mem_type()));
         if flags.contains(MappingFlags::READ) {
             attr |= Self::VALID;
         }
-        if !flags.contains(MappingFlags::WRITE) {
+        if flags.contains(MappingFlags::WRITE) {
+            attr |= Self::DBM;
+        } else {
             attr |= Self::AP_RO;
         }
         #[cfg(not(feature = "arm-el2"))]
@@ -178,6 +252,9 @@ impl From<MappingFlags> for DescriptorAttr {
                     attr |= Self::PXN;
                 }
             }
+            if !flags.contains(MappingFlags::GLOBAL) {
+                attr |= Self::NG;
+            }
         }
         #[cfg(feature = "arm-el2")]
         {
@@ -194,6 +271,15 @@ impl From<MappingFlags> for DescriptorAttr {
 /// Note that the **AttrIndx\[2:0\]** (bit\[4:2\]) field is set to `0` for
 /// device memory, and `1` for normal memory. The system must configure the
 /// MAIR_ELx system register accordingly.
+///
+/// The not global (nG) bit is set unless [`MappingFlags::GLOBAL`] is given.
+/// It is never set with the `arm-el2` feature, as the bit is `RES0` in the
+/// EL2 translation regime.
+///
+/// Writable pages are mapped with the dirty bit modifier (DBM) set, so that
+/// a page is made clean by setting it read-only. Without `FEAT_HAFDBS`
+/// enabled in `TCR_ELx`, the accesses after clearing the accessed or dirty
+/// state generate access flag or permission faults instead.
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct A64PTE(u64);
@@ -209,6 +295,9 @@ impl A64PTE {
 }
 
 impl GenericPTE for A64PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
     fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
         let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
         if !is_huge {
@@ -217,6 +306,11 @@ impl GenericPTE for A64PTE {
         Self(attr.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
     }
 
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let pte = Self::new_page(paddr, flags, is_huge);
+        Self(pte.0 | DescriptorAttr::CONTIGUOUS.bits())
+    }
+
     fn new_table(paddr: PhysAddr) -> Self {
         let attr = DescriptorAttr::NON_BLOCK | DescriptorAttr::VALID;
         Self(attr.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
@@ -239,7 +333,16 @@ impl GenericPTE for A64PTE {
         if !is_huge {
             attr |= DescriptorAttr::NON_BLOCK;
         }
-        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | attr.bits();
+        let kept = Self::PHYS_ADDR_MASK | DescriptorAttr::CONTIGUOUS.bits();
+        self.0 = (self.0 & kept) | attr.bits();
+    }
+
+    fn access_state(&self) -> AccessState {
+        DescriptorAttr::access_state(self.0)
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        DescriptorAttr::clear_access_state(&mut self.0, state)
     }
 
     fn bits(self) -> usize {
@@ -258,6 +361,10 @@ impl GenericPTE for A64PTE {
         !DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::NON_BLOCK)
     }
 
+    fn is_contiguous(&self, _is_huge: bool) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::CONTIGUOUS)
+    }
+
     fn clear(&mut self) {
         self.0 = 0
     }
@@ -273,3 +380,720 @@ impl fmt::Debug for A64PTE {
             .finish()
     }
 }
+
+/// A VMSAv8-64 translation table descriptor with 52-bit output addresses
+/// (`FEAT_LPA2`), for the 4K and 16K granules.
+///
+/// It is used when `TCR_ELx.DS` is set, where the bits\[9:8\] hold the
+/// OA\[51:50\] instead of the shareability field. The shareability of
+/// normal memory is then determined by `TCR_ELx.SH0` and `TCR_ELx.SH1`.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct A64Lpa2PTE(u64);
+
+impl A64Lpa2PTE {
+    // bits 12..50
+    const PHYS_ADDR_LOW_MASK: u64 = 0x0003_ffff_ffff_f000;
+    // bits 8..10, the OA[51:50]
+    const PHYS_ADDR_HIGH_MASK: u64 = 0x300;
+    const PHYS_ADDR_MASK: u64 = Self::PHYS_ADDR_LOW_MASK | Self::PHYS_ADDR_HIGH_MASK;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    const fn encode_paddr(paddr: usize) -> u64 {
+        let paddr = paddr as u64;
+        (paddr & Self::PHYS_ADDR_LOW_MASK) | ((paddr >> 42) & Self::PHYS_ADDR_HIGH_MASK)
+    }
+
+    const fn attr(attr: DescriptorAttr) -> u64 {
+        attr.bits() & !(DescriptorAttr::INNER.bits() | DescriptorAttr::SHAREABLE.bits())
+    }
+}
+
+impl GenericPTE for A64Lpa2PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
+        if !is_huge {
+            attr |= DescriptorAttr::NON_BLOCK;
+        }
+        Self(Self::attr(attr) | Self::encode_paddr(paddr.as_usize()))
+    }
+
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let pte = Self::new_page(paddr, flags, is_huge);
+        Self(pte.0 | DescriptorAttr::CONTIGUOUS.bits())
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let attr = DescriptorAttr::NON_BLOCK | DescriptorAttr::VALID;
+        Self(attr.bits() | Self::encode_paddr(paddr.as_usize()))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        let low = self.0 & Self::PHYS_ADDR_LOW_MASK;
+        let high = (self.0 & Self::PHYS_ADDR_HIGH_MASK) << 42;
+        PhysAddr::from((low | high) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        DescriptorAttr::from_bits_truncate(self.0 & !Self::PHYS_ADDR_MASK).into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | Self::encode_paddr(paddr.as_usize())
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
+        if !is_huge {
+            attr |= DescriptorAttr::NON_BLOCK;
+        }
+        let kept = Self::PHYS_ADDR_MASK | DescriptorAttr::CONTIGUOUS.bits();
+        self.0 = (self.0 & kept) | Self::attr(attr);
+    }
+
+    fn access_state(&self) -> AccessState {
+        DescriptorAttr::access_state(self.0)
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        DescriptorAttr::clear_access_state(&mut self.0, state)
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::VALID)
+    }
+
+    fn is_huge(&self) -> bool {
+        !DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::NON_BLOCK)
+    }
+
+    fn is_contiguous(&self, _is_huge: bool) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::CONTIGUOUS)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl fmt::Debug for A64Lpa2PTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("A64Lpa2PTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field(
+                "attr",
+                &DescriptorAttr::from_bits_truncate(self.0 & !Self::PHYS_ADDR_MASK),
+            )
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+/// A VMSAv8-64 translation table descriptor with 52-bit output addresses
+/// (`FEAT_LPA`), for the 64K granule.
+///
+/// The bits\[15:12\] hold the OA\[51:48\], since the low 16 bits of the
+/// output address are always zero with the 64K granule.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct A64LpaPTE(u64);
+
+impl A64LpaPTE {
+    // bits 16..48
+    const PHYS_ADDR_LOW_MASK: u64 = 0x0000_ffff_ffff_0000;
+    // bits 12..16, the OA[51:48]
+    const PHYS_ADDR_HIGH_MASK: u64 = 0xf000;
+    const PHYS_ADDR_MASK: u64 = Self::PHYS_ADDR_LOW_MASK | Self::PHYS_ADDR_HIGH_MASK;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    const fn encode_paddr(paddr: usize) -> u64 {
+        let paddr = paddr as u64;
+        (paddr & Self::PHYS_ADDR_LOW_MASK) | ((paddr >> 36) & Self::PHYS_ADDR_HIGH_MASK)
+    }
+}
+
+impl GenericPTE for A64LpaPTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
+        if !is_huge {
+            attr |= DescriptorAttr::NON_BLOCK;
+        }
+        Self(attr.bits() | Self::encode_paddr(paddr.as_usize()))
+    }
+
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let pte = Self::new_page(paddr, flags, is_huge);
+        Self(pte.0 | DescriptorAttr::CONTIGUOUS.bits())
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let attr = DescriptorAttr::NON_BLOCK | DescriptorAttr::VALID;
+        Self(attr.bits() | Self::encode_paddr(paddr.as_usize()))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        let low = self.0 & Self::PHYS_ADDR_LOW_MASK;
+        let high = (self.0 & Self::PHYS_ADDR_HIGH_MASK) << 36;
+        PhysAddr::from((low | high) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        DescriptorAttr::from_bits_truncate(self.0).into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | Self::encode_paddr(paddr.as_usize())
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut attr = DescriptorAttr::from(flags) | DescriptorAttr::AF;
+        if !is_huge {
+            attr |= DescriptorAttr::NON_BLOCK;
+        }
+        let kept = Self::PHYS_ADDR_MASK | DescriptorAttr::CONTIGUOUS.bits();
+        self.0 = (self.0 & kept) | attr.bits();
+    }
+
+    fn access_state(&self) -> AccessState {
+        DescriptorAttr::access_state(self.0)
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        DescriptorAttr::clear_access_state(&mut self.0, state)
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::VALID)
+    }
+
+    fn is_huge(&self) -> bool {
+        !DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::NON_BLOCK)
+    }
+
+    fn is_contiguous(&self, _is_huge: bool) -> bool {
+        DescriptorAttr::from_bits_truncate(self.0).contains(DescriptorAttr::CONTIGUOUS)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl fmt::Debug for A64LpaPTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("A64LpaPTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("attr", &DescriptorAttr::from_bits_truncate(self.0))
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+bitflags::bitflags! {
+    /// Attribute fields in the VMSAv8-64 stage 2 translation table descriptors.
+    #[derive(Debug)]
+    pub struct S2DescriptorAttr: u64 {
+        /// Whether the descriptor is valid.
+        const VALID =       1 << 0;
+        /// The descriptor gives the address of the next level of translation table or a page at
+        /// the last level (not a block).
+        const NON_BLOCK =   1 << 1;
+        /// Stage 2 memory attributes field (MemAttr\[3:0\]).
+        const MEM_ATTR =    0b1111 << 2;
+        /// Stage 2 access permission: readable.
+        const S2AP_R =      1 << 6;
+        /// Stage 2 access permission: writable.
+        const S2AP_W =      1 << 7;
+        /// Shareability: Inner Shareable (otherwise Outer Shareable).
+        const INNER =       1 << 8;
+        /// Shareability: Inner or Outer Shareable (otherwise Non-shareable).
+        const SHAREABLE =   1 << 9;
+        /// The Access flag.
+        const AF =          1 << 10;
+        /// Dirty Bit Modifier. With `FEAT_HAFDBS`, a write to a read-only page
+        /// sets `S2AP_W` instead of generating a permission fault.
+        const DBM =         1 << 51;
+        /// Indicates that 16 adjacent translation table entries point to contiguous memory regions.
+        const CONTIGUOUS =  1 << 52;
+        /// Execute-never field, XN\[0\] (`FEAT_XNX`).
+        const XN0 =         1 << 53;
+        /// Execute-never field, XN\[1\].
+        const XN1 =         1 << 54;
+    }
+}
+
+/// The memory types in the MemAttr\[3:0\] field of stage 2 descriptors.
+///
+/// Unlike stage 1, the memory type is encoded in the descriptor directly,
+/// without the indirection of MAIR. The encoding depends on whether
+/// `HCR_EL2.FWB` (`FEAT_S2FWB`) is set, see [`A64S2PTE`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum S2MemAttr {
+    /// Device-nGnRE memory
+    Device,
+    /// Normal memory, Inner and Outer Write-Back Cacheable
+    Normal,
+    /// Normal memory, Inner and Outer Non-cacheable
+    NormalNonCacheable,
+    /// Device-nGnRnE memory
+    DeviceStronglyOrdered,
+    /// Normal memory, Inner and Outer Write-Through Cacheable, which is not
+    /// available when `fwb` is true
+    NormalWriteThrough,
+}
+
+impl S2MemAttr {
+    /// Returns the MemAttr\[3:0\] field of this memory type.
+    ///
+    /// When `fwb` is true, the memory type overrides the stage 1 attributes.
+    /// Otherwise, it is combined with the stage 1 attributes, and the
+    /// stronger one is used.
+    pub const fn bits(self, fwb: bool) -> u64 {
+        match (self, fwb) {
+            (Self::Device, _) => 0b0001,
+            (Self::Normal, false) => 0b1111,
+            (Self::Normal, true) => 0b0110,
+            (Self::NormalNonCacheable, _) => 0b0101,
+            (Self::DeviceStronglyOrdered, _) => 0b0000,
+            (Self::NormalWriteThrough, false) => 0b1010,
+            // no write-through encoding with FWB
+            (Self::NormalWriteThrough, true) => 0b0101,
+        }
+    }
+
+    /// Decodes the MemAttr\[3:0\] field, see [`bits`](Self::bits).
+    pub const fn from_bits(bits: u64, fwb: bool) -> Option<Self> {
+        Some(match (bits, fwb) {
+            (0b0001, _) => Self::Device,
+            (0b1111, false) | (0b0110, true) => Self::Normal,
+            (0b0101, _) => Self::NormalNonCacheable,
+            (0b0000, _) => Self::DeviceStronglyOrdered,
+            (0b1010, false) => Self::NormalWriteThrough,
+            _ => return None,
+        })
+    }
+
+    /// Returns the portable memory types honoured by stage 2 descriptors.
+    pub const fn mem_types(fwb: bool) -> &'static [MemType] {
+        if fwb {
+            &[
+                MemType::Normal,
+                MemType::Uncached,
+                MemType::Device,
+                MemType::StronglyOrdered,
+            ]
+        } else {
+            MEM_TYPES
+        }
+    }
+
+    /// Returns the memory type that the portable memory type is mapped to.
+    pub const fn from_mem_type(mem_type: MemType, fwb: bool) -> Self {
+        match mem_type.fallback(Self::mem_types(fwb)) {
+            MemType::Normal => Self::Normal,
+            MemType::WriteThrough => Self::NormalWriteThrough,
+            MemType::Device => Self::Device,
+            MemType::StronglyOrdered => Self::DeviceStronglyOrdered,
+            _ => Self::NormalNonCacheable,
+        }
+    }
+
+    /// Returns the portable memory type of this memory type.
+    pub const fn mem_type(self) -> MemType {
+        match self {
+            Self::Device => MemType::Device,
+            Self::Normal => MemType::Normal,
+            Self::NormalNonCacheable => MemType::Uncached,
+            Self::DeviceStronglyOrdered => MemType::StronglyOrdered,
+            Self::NormalWriteThrough => MemType::WriteThrough,
+        }
+    }
+}
+
+impl S2DescriptorAttr {
+    /// Constructs a descriptor from the memory type, leaving the other fields
+    /// empty.
+    pub const fn from_mem_attr(attr: S2MemAttr, fwb: bool) -> Self {
+        let mut bits = attr.bits(fwb) << 2;
+        if matches!(
+            attr,
+            S2MemAttr::Normal | S2MemAttr::NormalNonCacheable | S2MemAttr::NormalWriteThrough
+        ) {
+            bits |= Self::INNER.bits() | Self::SHAREABLE.bits();
+        }
+        Self::from_bits_retain(bits)
+    }
+
+    /// Returns the memory type of the MemAttr\[3:0\] field.
+    pub const fn mem_attr(&self, fwb: bool) -> Option<S2MemAttr> {
+        S2MemAttr::from_bits((self.bits() & Self::MEM_ATTR.bits()) >> 2, fwb)
+    }
+
+    // A writable page is dirty unless it has been made read-only with the
+    // dirty bit modifier set.
+    fn access_state(bits: u64) -> AccessState {
+        let mut state = AccessState::from_pte_bits(bits, Self::AF.bits(), 0);
+        let mask = Self::DBM.bits() | Self::S2AP_W.bits();
+        if bits & mask == mask {
+            state |= AccessState::DIRTY;
+        }
+        state
+    }
+
+    fn clear_access_state(bits: &mut u64, state: AccessState) -> AccessState {
+        let old = crate::update_bits_u64(bits, |bits| {
+            let mut bits = bits & !state.pte_bits(Self::AF.bits(), 0);
+            if state.contains(AccessState::DIRTY) && bits & Self::DBM.bits() != 0 {
+                bits &= !Self::S2AP_W.bits();
+            }
+            bits
+        });
+        Self::access_state(old)
+    }
+
+    fn to_mapping_flags(&self, fwb: bool) -> MappingFlags {
+        if !self.contains(Self::VALID) {
+            return MappingFlags::empty();
+        }
+        let mut flags = MappingFlags::empty();
+        if self.contains(Self::S2AP_R) {
+            flags |= MappingFlags::READ;
+        }
+        // A clean page is writable with the dirty bit modifier.
+        if self.intersects(Self::S2AP_W | Self::DBM) {
+            flags |= MappingFlags::WRITE;
+        }
+        if !self.intersects(Self::XN0 | Self::XN1) {
+            flags |= MappingFlags::EXECUTE;
+        }
+        if let Some(mem_attr) = self.mem_attr(fwb) {
+            flags |= mem_attr.mem_type().into();
+        }
+        flags
+    }
+
+    fn from_mapping_flags(flags: MappingFlags, fwb: bool) -> Self {
+        if flags.is_empty() {
+            return Self::empty();
+        }
+        let mut attr = Self::from_mem_attr(S2MemAttr::from_mem_type(flags.mem_type(), fwb), fwb);
+        attr |= Self::VALID;
+        if flags.contains(MappingFlags::READ) {
+            attr |= Self::S2AP_R;
+        }
+        if flags.contains(MappingFlags::WRITE) {
+            attr |= Self::S2AP_W | Self::DBM;
+        }
+        if !flags.contains(MappingFlags::EXECUTE) {
+            // not executable at EL1 and EL0
+            attr |= Self::XN1;
+        }
+        attr
+    }
+}
+
+/// A VMSAv8-64 stage 2 translation table descriptor, which translates
+/// intermediate physical addresses (IPA) of guests to physical addresses.
+///
+/// The `FWB` parameter selects the encoding of the memory types (see
+/// [`S2MemAttr`]), and must match `HCR_EL2.FWB`. The `USER` mapping flag is
+/// ignored, as there is no EL0 permission at stage 2, and so is the `GLOBAL`
+/// flag, as the translations are tagged by the VMID.
+///
+/// Like [`A64PTE`], writable pages are mapped with the dirty bit modifier
+/// set, which requires `FEAT_HAFDBS` enabled in `VTCR_EL2` to record the
+/// accessed and dirty state.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct A64S2PTE<const FWB: bool>(u64);
+
+impl<const FWB: bool> A64S2PTE<FWB> {
+    // bits 12..48
+    const PHYS_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+}
+
+impl<const FWB: bool> GenericPTE for A64S2PTE<FWB> {
+    const MEM_TYPES: &'static [MemType] = S2MemAttr::mem_types(FWB);
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut attr = S2DescriptorAttr::from_mapping_flags(flags, FWB) | S2DescriptorAttr::AF;
+        if !is_huge {
+            attr |= S2DescriptorAttr::NON_BLOCK;
+        }
+        Self(attr.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let pte = Self::new_page(paddr, flags, is_huge);
+        Self(pte.0 | S2DescriptorAttr::CONTIGUOUS.bits())
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let attr = S2DescriptorAttr::NON_BLOCK | S2DescriptorAttr::VALID;
+        Self(attr.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        PhysAddr::from((self.0 & Self::PHYS_ADDR_MASK) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        S2DescriptorAttr::from_bits_truncate(self.0).to_mapping_flags(FWB)
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK)
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut attr = S2DescriptorAttr::from_mapping_flags(flags, FWB) | S2DescriptorAttr::AF;
+        if !is_huge {
+            attr |= S2DescriptorAttr::NON_BLOCK;
+        }
+        let kept = Self::PHYS_ADDR_MASK | S2DescriptorAttr::CONTIGUOUS.bits();
+        self.0 = (self.0 & kept) | attr.bits();
+    }
+
+    fn access_state(&self) -> AccessState {
+        S2DescriptorAttr::access_state(self.0)
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        S2DescriptorAttr::clear_access_state(&mut self.0, state)
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        S2DescriptorAttr::from_bits_truncate(self.0).contains(S2DescriptorAttr::VALID)
+    }
+
+    fn is_huge(&self) -> bool {
+        !S2DescriptorAttr::from_bits_truncate(self.0).contains(S2DescriptorAttr::NON_BLOCK)
+    }
+
+    fn is_contiguous(&self, _is_huge: bool) -> bool {
+        S2DescriptorAttr::from_bits_truncate(self.0).contains(S2DescriptorAttr::CONTIGUOUS)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl<const FWB: bool> fmt::Debug for A64S2PTE<FWB> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("A64S2PTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("attr", &S2DescriptorAttr::from_bits_truncate(self.0))
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lpa2_descriptor() {
+        let paddr = PhysAddr::from(0x000f_1234_5678_9000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = A64Lpa2PTE::new_page(paddr, flags, false);
+
+        assert_eq!(pte.bits() & 0x300, 0x300); // OA[51:50]
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+
+        pte.set_flags(MappingFlags::READ | MappingFlags::UNCACHED, false);
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), MappingFlags::READ | MappingFlags::UNCACHED);
+    }
+
+    #[test]
+    fn test_lpa_descriptor() {
+        let paddr = PhysAddr::from(0x000f_1234_5678_0000);
+        let flags = MappingFlags::READ | MappingFlags::EXECUTE;
+        let mut pte = A64LpaPTE::new_page(paddr, flags, true);
+
+        assert_eq!(pte.bits() & 0xf000, 0xf000); // OA[51:48]
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+
+        let table = PhysAddr::from(0x0008_0000_0001_0000);
+        pte.set_paddr(table);
+        assert_eq!(pte.paddr(), table);
+        assert_eq!(pte.flags(), flags);
+    }
+
+    #[test]
+    fn test_stage2_descriptor() {
+        let paddr = PhysAddr::from(0x8020_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let pte = A64S2PTE::<false>::new_page(paddr, flags, true);
+
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!((pte.bits() >> 2) & 0xf, 0b1111); // Normal WB
+        assert_eq!((pte.bits() >> 6) & 0b11, 0b11); // S2AP: RW
+
+        let flags = MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::DEVICE;
+        let pte = A64S2PTE::<true>::new_page(paddr, flags, false);
+        assert!(!pte.is_huge());
+        assert_eq!(pte.flags(), flags);
+        assert_eq!((pte.bits() >> 2) & 0xf, 0b0001); // Device-nGnRE
+
+        let pte = A64S2PTE::<true>::new_page(paddr, MappingFlags::READ, false);
+        assert_eq!((pte.bits() >> 2) & 0xf, 0b0110); // forced Normal WB
+        assert_eq!((pte.bits() >> 53) & 0b11, 0b10); // XN: not executable
+    }
+
+    #[test]
+    fn test_memory_types() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        for mem_type in A64PTE::MEM_TYPES {
+            let flags = flags.with_mem_type(*mem_type);
+            assert_eq!(A64PTE::new_page(paddr, flags, false).flags(), flags);
+            assert_eq!(
+                A64S2PTE::<false>::new_page(paddr, flags, false).flags(),
+                flags
+            );
+        }
+
+        // The device memory is the strongest one of the flags.
+        let pte = A64PTE::new_page(
+            paddr,
+            flags | MappingFlags::DEVICE | MappingFlags::UNCACHED,
+            false,
+        );
+        assert_eq!((pte.bits() >> 2) & 0b111, MemAttr::Device as usize);
+
+        let flags = flags | MappingFlags::WRITE_THROUGH;
+        let pte = A64PTE::new_page(paddr, flags, false);
+        assert_eq!(
+            (pte.bits() >> 2) & 0b111,
+            MemAttr::NormalWriteThrough as usize
+        );
+        // No write-through memory with `FEAT_S2FWB`, falls back to non-cacheable.
+        let pte = A64S2PTE::<true>::new_page(paddr, flags, false);
+        assert_eq!(pte.flags(), flags.with_mem_type(MemType::Uncached));
+    }
+
+    #[test]
+    fn test_contiguous_descriptor() {
+        let paddr = PhysAddr::from(0x4000_3000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = A64PTE::new_contiguous_page(paddr, flags, false);
+
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+
+        pte.set_flags(MappingFlags::READ, false);
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.flags(), MappingFlags::READ);
+        assert!(!A64PTE::new_page(paddr, flags, false).is_contiguous(false));
+    }
+
+    #[test]
+    #[cfg(not(feature = "arm-el2"))]
+    fn test_global_descriptor() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = A64PTE::new_page(paddr, flags, false);
+        assert_eq!(pte.bits() & (1 << 11), 1 << 11); // nG
+        assert_eq!(pte.flags(), flags);
+
+        pte.set_flags(flags | MappingFlags::GLOBAL, false);
+        assert_eq!(pte.bits() & (1 << 11), 0);
+        assert_eq!(pte.flags(), flags | MappingFlags::GLOBAL);
+    }
+
+    #[test]
+    fn test_access_state() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = A64PTE::new_page(paddr, flags, false);
+        assert_eq!(pte.access_state(), AccessState::all());
+
+        // a clean page is read-only with DBM set, but still writable
+        assert_eq!(
+            pte.clear_access_state(AccessState::DIRTY),
+            AccessState::all()
+        );
+        assert_eq!(pte.bits() as u64 & (1 << 51 | 1 << 7), 1 << 51 | 1 << 7); // DBM, AP_RO
+        assert_eq!(pte.access_state(), AccessState::ACCESSED);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(
+            pte.clear_access_state(AccessState::all()),
+            AccessState::ACCESSED
+        );
+        assert_eq!(pte.access_state(), AccessState::empty());
+
+        // a read-only page is never dirty
+        let mut pte = A64PTE::new_page(paddr, MappingFlags::READ, false);
+        assert_eq!(pte.access_state(), AccessState::ACCESSED);
+        assert_eq!(
+            pte.clear_access_state(AccessState::DIRTY),
+            AccessState::ACCESSED
+        );
+        assert_eq!(pte.flags(), MappingFlags::READ);
+
+        let mut pte = A64S2PTE::<false>::new_page(paddr, flags, true);
+        assert_eq!(
+            pte.clear_access_state(AccessState::DIRTY),
+            AccessState::all()
+        );
+        assert_eq!((pte.bits() >> 6) & 0b11, 0b01); // S2AP: RO
+        assert_eq!(pte.access_state(), AccessState::ACCESSED);
+        assert_eq!(pte.flags(), flags);
+    }
+}
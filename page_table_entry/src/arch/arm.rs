@@ -1,15 +1,31 @@
-//! ARMv7-A Short-descriptor translation table format.
+//! ARMv7-A Short-descriptor and Long-descriptor translation table formats.
 //!
 //! This module implements page table entries for ARMv7-A architecture using
 //! the Short-descriptor format, which supports 2-level page tables:
 //! - L1 (Translation Table): 4096 entries, each mapping 1MB or pointing to L2
 //! - L2 (Page Table): 256 entries, each mapping 4KB (Small Page)
+//!
+//! And the Long-descriptor format of the Large Physical Address Extension
+//! (LPAE), which supports 3-level page tables with 64-bit descriptors and
+//! 40-bit output addresses:
+//! - L1: 4 entries, each mapping 1GB (Block) or pointing to L2
+//! - L2: 512 entries, each mapping 2MB (Block) or pointing to L3
+//! - L3: 512 entries, each mapping 4KB (Page)
 
 use core::fmt;
 
 use memory_addr::PhysAddr;
 
-use crate::{GenericPTE, MappingFlags};
+use crate::{GenericLargePTE, GenericPTE, MappingFlags, MemType, PhysAddr64};
+
+// The portable memory types honoured by both descriptor formats.
+const MEM_TYPES: &[MemType] = &[
+    MemType::Normal,
+    MemType::WriteThrough,
+    MemType::Uncached,
+    MemType::Device,
+    MemType::StronglyOrdered,
+];
 
 bitflags::bitflags! {
     /// ARMv7-A Short-descriptor page table entry flags.
@@ -77,9 +93,15 @@ bitflags::bitflags! {
         /// Bit[17]: Not Global (nG)
         const NG = 1 << 17;
 
-        /// Bit[18]: For Section: Not Secure (NS)
+        /// Bit[18]: For Section: Supersection (16MB block)
+        const SUPERSECTION = 1 << 18;
+
+        /// Bit[19]: For Section: Not Secure (NS)
         const NS = 1 << 19;
 
+        /// Bit[15]: Execute Never (XN) for Large Pages
+        const XN_LARGE = 1 << 15;
+
         // Combined flags for common use cases
 
         /// Section descriptor type (1MB block)
@@ -124,14 +146,16 @@ impl DescriptorAttr {
         let mut bits = 0;
 
         // Memory type
-        if flags.contains(MappingFlags::DEVICE) {
-            bits |= Self::DEVICE_MEMORY.bits();
-        } else if flags.contains(MappingFlags::UNCACHED) {
+        match flags.mem_type().fallback(MEM_TYPES) {
+            // Strongly-ordered memory: TEX=000, C=0, B=0
+            MemType::StronglyOrdered => {}
+            MemType::Device => bits |= Self::DEVICE_MEMORY.bits(),
             // Uncached normal memory: TEX=001, C=0, B=0
-            bits |= Self::TEX0.bits();
-        } else {
+            MemType::Uncached => bits |= Self::TEX0.bits(),
+            // Write-through normal memory with shareable: TEX=000, C=1, B=0
+            MemType::WriteThrough => bits |= Self::C.bits() | Self::S.bits(),
             // Normal cacheable memory with shareable
-            bits |= Self::NORMAL_SHAREABLE.bits();
+            _ => bits |= Self::NORMAL_SHAREABLE.bits(),
         }
 
         // Access permissions
@@ -150,6 +174,11 @@ impl DescriptorAttr {
             bits |= Self::AP_PRIV_RO.bits();
         }
 
+        // Non-global mappings are tagged with the current ASID
+        if !flags.contains(MappingFlags::GLOBAL) {
+            bits |= Self::NG.bits();
+        }
+
         bits
     }
 
@@ -216,6 +245,82 @@ impl DescriptorAttr {
         Self::from_bits_retain(bits)
     }
 
+    /// Creates descriptor attributes from MappingFlags for a Large Page (64KB).
+    #[inline]
+    pub const fn from_mapping_flags_large_page(flags: MappingFlags) -> Self {
+        let mut bits = Self::PAGE_TABLE.bits();
+
+        if flags.is_empty() {
+            return Self::from_bits_retain(0);
+        }
+
+        let attr = Self::common_flags(flags);
+
+        // B(2), C(3) and TEX[2:0](14:12) are at the same positions for L1 and
+        // L2 Large Page
+        bits |= attr
+            & (Self::B.bits()
+                | Self::C.bits()
+                | Self::TEX0.bits()
+                | Self::TEX1.bits()
+                | Self::TEX2.bits());
+
+        // Other attributes are shifted right by 6 bits like the Small Page:
+        // - AP[1:0]: 11:10 -> 5:4
+        // - AP[2]: 15 -> 9
+        // - S: 16 -> 10
+        // - nG: 17 -> 11
+        let shift_mask = Self::AP0.bits()
+            | Self::AP1.bits()
+            | Self::AP2.bits()
+            | Self::S.bits()
+            | Self::NG.bits();
+
+        bits |= (attr & shift_mask) >> 6;
+
+        // Execute Never for Large Pages (XN is bit 15 in L2)
+        if !flags.contains(MappingFlags::EXECUTE) {
+            bits |= Self::XN_LARGE.bits();
+        }
+
+        Self::from_bits_retain(bits)
+    }
+
+    /// Converts the attributes of a Large Page (64KB) to the layout of a
+    /// Section, the inverse of [`from_mapping_flags_large_page`].
+    ///
+    /// [`from_mapping_flags_large_page`]: Self::from_mapping_flags_large_page
+    const fn large_page_to_section(bits: u32) -> Self {
+        let mut ret = Self::SECTION.bits();
+        ret |= bits
+            & (Self::B.bits()
+                | Self::C.bits()
+                | Self::TEX0.bits()
+                | Self::TEX1.bits()
+                | Self::TEX2.bits());
+        // AP[1:0], AP[2], S and nG
+        ret |= (bits & (0b11 << 4 | 0b111 << 9)) << 6;
+        if bits & Self::XN_LARGE.bits() != 0 {
+            ret |= Self::XN_SMALL.bits();
+        }
+        Self::from_bits_retain(ret)
+    }
+
+    /// Converts the attributes of a Small Page (4KB) to the layout of a
+    /// Section, the inverse of [`from_mapping_flags_small_page`].
+    ///
+    /// [`from_mapping_flags_small_page`]: Self::from_mapping_flags_small_page
+    const fn small_page_to_section(bits: u32) -> Self {
+        let mut ret = Self::SECTION.bits();
+        ret |= bits & (Self::B.bits() | Self::C.bits());
+        // AP[1:0], TEX[2:0], AP[2], S and nG
+        ret |= (bits & (0xff << 4)) << 6;
+        if bits & Self::TYPE_BIT0.bits() != 0 {
+            ret |= Self::XN_SMALL.bits();
+        }
+        Self::from_bits_retain(ret)
+    }
+
     /// Returns the descriptor type.
     pub const fn descriptor_type(&self) -> u32 {
         self.bits() & 0b11
@@ -268,16 +373,23 @@ impl From<DescriptorAttr> for MappingFlags {
             flags |= Self::EXECUTE;
         }
 
+        if !attr.contains(DescriptorAttr::NG) {
+            flags |= Self::GLOBAL;
+        }
+
         // Check memory type
         let tex = (attr.bits() >> 12) & 0b111;
         let c = (attr.bits() >> 3) & 1;
         let b = (attr.bits() >> 2) & 1;
 
-        if tex == 0 && c == 0 && b == 1 {
-            flags |= Self::DEVICE;
-        } else if tex == 1 && c == 0 && b == 0 {
-            flags |= Self::UNCACHED;
+        flags |= match (tex, c, b) {
+            (0, 0, 0) => MemType::StronglyOrdered,
+            (0, 0, 1) => MemType::Device,
+            (1, 0, 0) => MemType::Uncached,
+            (0, 1, 0) => MemType::WriteThrough,
+            _ => MemType::Normal,
         }
+        .into();
 
         flags
     }
@@ -287,16 +399,28 @@ impl From<DescriptorAttr> for MappingFlags {
 ///
 /// This can represent:
 /// - L1 Section descriptor (1MB mapping)
+/// - L1 Supersection descriptor (16MB mapping, replicated in 16 entries)
 /// - L1 Page Table descriptor (points to L2 table)
 /// - L2 Small Page descriptor (4KB mapping)
+/// - L2 Large Page descriptor (64KB mapping, replicated in 16 entries)
+///
+/// Large Pages share the descriptor type of Page Tables, they are told apart
+/// by bit 4, which is `AP[0]` (always set) for Large Pages and should be zero
+/// for Page Tables.
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct A32PTE(u32);
 
 impl A32PTE {
+    /// Physical address mask for Supersection (bits [31:24] for 16MB alignment)
+    const SUPERSECTION_ADDR_MASK: u32 = 0xff00_0000;
+
     /// Physical address mask for Section (bits [31:20] for 1MB alignment)
     const SECTION_ADDR_MASK: u32 = 0xfff0_0000;
 
+    /// Physical address mask for Large Page (bits [31:16] for 64KB alignment)
+    const LARGE_PAGE_ADDR_MASK: u32 = 0xffff_0000;
+
     /// Physical address mask for Page Table (bits [31:10] for 1KB alignment)
     const PAGE_TABLE_ADDR_MASK: u32 = 0xffff_fc00;
 
@@ -322,6 +446,32 @@ impl A32PTE {
         Self(attr.bits() | (paddr.as_usize() as u32 & Self::SMALL_PAGE_ADDR_MASK))
     }
 
+    /// Creates a Supersection descriptor (16MB block).
+    ///
+    /// It should be replicated in 16 consecutive L1 entries. The extended
+    /// address bits (`PA[39:32]`) are set to zero.
+    #[inline]
+    pub const fn new_supersection(paddr: PhysAddr, flags: MappingFlags) -> Self {
+        let attr = DescriptorAttr::from_mapping_flags_section(flags);
+        if attr.is_empty() {
+            return Self(0);
+        }
+        Self(
+            attr.bits()
+                | DescriptorAttr::SUPERSECTION.bits()
+                | (paddr.as_usize() as u32 & Self::SUPERSECTION_ADDR_MASK),
+        )
+    }
+
+    /// Creates a Large Page descriptor (64KB page).
+    ///
+    /// It should be replicated in 16 consecutive L2 entries.
+    #[inline]
+    pub const fn new_large_page(paddr: PhysAddr, flags: MappingFlags) -> Self {
+        let attr = DescriptorAttr::from_mapping_flags_large_page(flags);
+        Self(attr.bits() | (paddr.as_usize() as u32 & Self::LARGE_PAGE_ADDR_MASK))
+    }
+
     /// Returns the descriptor type field.
     pub const fn descriptor_type(&self) -> u32 {
         self.0 & 0b11
@@ -331,9 +481,22 @@ impl A32PTE {
     pub const fn is_section(&self) -> bool {
         (self.0 & 0b11) == 0b10
     }
+
+    /// Checks if this is a Supersection descriptor, only meaningful for L1
+    /// entries.
+    pub const fn is_supersection(&self) -> bool {
+        self.is_section() && (self.0 & DescriptorAttr::SUPERSECTION.bits()) != 0
+    }
+
+    /// Checks if this is a Large Page descriptor.
+    pub const fn is_large_page(&self) -> bool {
+        self.descriptor_type() == 0b01 && (self.0 & (DescriptorAttr::AP0.bits() >> 6)) != 0
+    }
 }
 
 impl GenericPTE for A32PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+
     #[inline]
     fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
         if is_huge {
@@ -345,6 +508,17 @@ impl GenericPTE for A32PTE {
         }
     }
 
+    #[inline]
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        if is_huge {
+            // 16MB Supersection
+            Self::new_supersection(paddr, flags)
+        } else {
+            // 64KB Large Page
+            Self::new_large_page(paddr, flags)
+        }
+    }
+
     #[inline]
     fn new_table(paddr: PhysAddr) -> Self {
         // Page Table descriptor (L1 -> L2)
@@ -355,6 +529,7 @@ impl GenericPTE for A32PTE {
     fn paddr(&self) -> PhysAddr {
         let desc_type = self.descriptor_type();
         let addr = match desc_type {
+            0b01 if self.is_large_page() => self.0 & Self::LARGE_PAGE_ADDR_MASK,
             0b01 => self.0 & Self::PAGE_TABLE_ADDR_MASK, // Page Table
             0b10 => {
                 if (self.0 & Self::SECTION_ADDR_MASK) >= 0x10_0000 {
@@ -370,12 +545,48 @@ impl GenericPTE for A32PTE {
     }
 
     fn flags(&self) -> MappingFlags {
-        DescriptorAttr::from_bits_truncate(self.0).into()
+        if self.is_large_page() {
+            DescriptorAttr::large_page_to_section(self.0).into()
+        } else if self.descriptor_type() == 0b11 {
+            // Small Page (XN=1), sections are never created with PXN
+            DescriptorAttr::small_page_to_section(self.0).into()
+        } else {
+            DescriptorAttr::from_bits_truncate(self.0).into()
+        }
+    }
+
+    fn leaf_paddr(&self, is_huge: bool) -> PhysAddr {
+        let addr = if !self.is_present() {
+            0
+        } else if is_huge {
+            self.0 & Self::SECTION_ADDR_MASK
+        } else if self.is_large_page() {
+            self.0 & Self::LARGE_PAGE_ADDR_MASK
+        } else {
+            self.0 & Self::SMALL_PAGE_ADDR_MASK
+        };
+        PhysAddr::from(addr as usize)
+    }
+
+    fn leaf_flags(&self, is_huge: bool) -> MappingFlags {
+        if !self.is_present() {
+            MappingFlags::empty()
+        } else if is_huge {
+            DescriptorAttr::from_bits_truncate(self.0).into()
+        } else if self.is_large_page() {
+            DescriptorAttr::large_page_to_section(self.0).into()
+        } else {
+            DescriptorAttr::small_page_to_section(self.0).into()
+        }
     }
 
     fn set_paddr(&mut self, paddr: PhysAddr) {
         let desc_type = self.descriptor_type();
         match desc_type {
+            0b01 if self.is_large_page() => {
+                self.0 = (self.0 & !Self::LARGE_PAGE_ADDR_MASK)
+                    | (paddr.as_usize() as u32 & Self::LARGE_PAGE_ADDR_MASK);
+            }
             0b01 => {
                 // Page Table
                 self.0 = (self.0 & !Self::PAGE_TABLE_ADDR_MASK)
@@ -396,8 +607,10 @@ impl GenericPTE for A32PTE {
     }
 
     fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
-        let paddr = self.paddr();
-        *self = if is_huge {
+        let paddr = self.leaf_paddr(is_huge);
+        *self = if self.is_contiguous(is_huge) {
+            Self::new_contiguous_page(paddr, flags, is_huge)
+        } else if is_huge {
             Self::new_section(paddr, flags)
         } else {
             Self::new_small_page(paddr, flags)
@@ -420,6 +633,14 @@ impl GenericPTE for A32PTE {
         self.is_section()
     }
 
+    fn is_contiguous(&self, is_huge: bool) -> bool {
+        if is_huge {
+            self.is_supersection()
+        } else {
+            self.is_large_page()
+        }
+    }
+
     fn clear(&mut self) {
         self.0 = 0;
     }
@@ -433,9 +654,17 @@ impl fmt::Debug for A32PTE {
                 "type",
                 &match self.descriptor_type() {
                     0b00 => "Invalid",
-                    0b01 => "PageTable",
+                    0b01 => {
+                        if self.is_large_page() {
+                            "LargePage"
+                        } else {
+                            "PageTable"
+                        }
+                    }
                     0b10 => {
-                        if self.is_section() {
+                        if self.is_supersection() {
+                            "Supersection"
+                        } else if self.is_section() {
                             "Section"
                         } else {
                             "SmallPage"
@@ -451,6 +680,307 @@ impl fmt::Debug for A32PTE {
     }
 }
 
+bitflags::bitflags! {
+    /// ARMv7-A Long-descriptor (LPAE) page table entry flags.
+    ///
+    /// Reference: ARM Architecture Reference Manual ARMv7-A/R Edition
+    /// Section B3.6: Long-descriptor translation table format
+    #[derive(Debug, Clone, Copy)]
+    pub struct LpaeDescriptorAttr: u64 {
+        /// Bit[0]: Whether the descriptor is valid.
+        const VALID =       1 << 0;
+        /// Bit[1]: The descriptor points to the next level table or a page at
+        /// L3 (not a block).
+        const NON_BLOCK =   1 << 1;
+        /// Bits[4:2]: Memory attributes index into MAIR0/MAIR1.
+        const ATTR_INDX =   0b111 << 2;
+        /// Bit[5]: Non-secure bit.
+        const NS =          1 << 5;
+        /// Bit[6]: Access permission AP[1], accessible at PL0.
+        const AP_USER =     1 << 6;
+        /// Bit[7]: Access permission AP[2], read-only.
+        const AP_RO =       1 << 7;
+        /// Bit[8]: Shareability: Inner Shareable (otherwise Outer Shareable).
+        const INNER =       1 << 8;
+        /// Bit[9]: Shareability: Inner or Outer Shareable (otherwise
+        /// Non-shareable).
+        const SHAREABLE =   1 << 9;
+        /// Bit[10]: The Access flag.
+        const AF =          1 << 10;
+        /// Bit[11]: Not Global (nG).
+        const NG =          1 << 11;
+        /// Bit[52]: 16 adjacent entries point to contiguous memory regions.
+        const CONTIGUOUS =  1 << 52;
+        /// Bit[53]: Privileged Execute Never (PXN).
+        const PXN =         1 << 53;
+        /// Bit[54]: Execute Never (XN).
+        const XN =          1 << 54;
+    }
+}
+
+/// The memory attributes index field in the Long-descriptor, which is used
+/// to index into the MAIR0 and MAIR1 registers.
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LpaeMemAttr {
+    /// Device memory
+    Device             = 0,
+    /// Normal memory, Inner/Outer Write-Back Write-Allocate
+    Normal             = 1,
+    /// Normal non-cacheable memory
+    NormalNonCacheable = 2,
+    /// Strongly-ordered memory
+    DeviceStronglyOrdered = 3,
+    /// Normal memory, Inner/Outer Write-Through Write-Allocate
+    NormalWriteThrough = 4,
+}
+
+impl LpaeMemAttr {
+    /// The MAIR0 register should be set to this value to match the memory
+    /// attributes in the descriptors (Attr0 to Attr3).
+    ///
+    /// - Attr0: `0x04`, Device memory
+    /// - Attr1: `0xff`, Normal memory, Inner/Outer Write-Back Write-Allocate
+    /// - Attr2: `0x44`, Normal memory, Inner/Outer Non-cacheable
+    /// - Attr3: `0x00`, Strongly-ordered memory
+    pub const MAIR0_VALUE: u32 = 0x0044_ff04;
+
+    /// The MAIR1 register should be set to this value (Attr4 to Attr7).
+    ///
+    /// - Attr4: `0xbb`, Normal memory, Inner/Outer Write-Through Write-Allocate
+    /// - Attr5 to Attr7: unused
+    pub const MAIR1_VALUE: u32 = 0xbb;
+
+    /// Returns the memory attribute that the portable memory type is mapped
+    /// to.
+    pub const fn from_mem_type(mem_type: MemType) -> Self {
+        match mem_type.fallback(MEM_TYPES) {
+            MemType::Normal => Self::Normal,
+            MemType::WriteThrough => Self::NormalWriteThrough,
+            MemType::Device => Self::Device,
+            MemType::StronglyOrdered => Self::DeviceStronglyOrdered,
+            _ => Self::NormalNonCacheable,
+        }
+    }
+
+    /// Returns the portable memory type of this memory attribute.
+    pub const fn mem_type(self) -> MemType {
+        match self {
+            Self::Device => MemType::Device,
+            Self::Normal => MemType::Normal,
+            Self::NormalNonCacheable => MemType::Uncached,
+            Self::DeviceStronglyOrdered => MemType::StronglyOrdered,
+            Self::NormalWriteThrough => MemType::WriteThrough,
+        }
+    }
+}
+
+impl LpaeDescriptorAttr {
+    #[allow(clippy::unusual_byte_groupings)]
+    const ATTR_INDEX_MASK: u64 = 0b111_00;
+
+    /// Constructs a descriptor from the memory index, leaving the other fields
+    /// empty.
+    pub const fn from_mem_attr(idx: LpaeMemAttr) -> Self {
+        let mut bits = (idx as u64) << 2;
+        if matches!(
+            idx,
+            LpaeMemAttr::Normal | LpaeMemAttr::NormalNonCacheable | LpaeMemAttr::NormalWriteThrough
+        ) {
+            bits |= Self::INNER.bits() | Self::SHAREABLE.bits();
+        }
+        Self::from_bits_retain(bits)
+    }
+
+    /// Returns the memory attribute index field.
+    pub const fn mem_attr(&self) -> Option<LpaeMemAttr> {
+        let idx = (self.bits() & Self::ATTR_INDEX_MASK) >> 2;
+        Some(match idx {
+            0 => LpaeMemAttr::Device,
+            1 => LpaeMemAttr::Normal,
+            2 => LpaeMemAttr::NormalNonCacheable,
+            3 => LpaeMemAttr::DeviceStronglyOrdered,
+            4 => LpaeMemAttr::NormalWriteThrough,
+            _ => return None,
+        })
+    }
+}
+
+impl From<LpaeDescriptorAttr> for MappingFlags {
+    fn from(attr: LpaeDescriptorAttr) -> Self {
+        if !attr.contains(LpaeDescriptorAttr::VALID) {
+            return Self::empty();
+        }
+        let mut flags = Self::READ;
+        if !attr.contains(LpaeDescriptorAttr::AP_RO) {
+            flags |= Self::WRITE;
+        }
+        if attr.contains(LpaeDescriptorAttr::AP_USER) {
+            flags |= Self::USER;
+            if !attr.contains(LpaeDescriptorAttr::XN) {
+                flags |= Self::EXECUTE;
+            }
+        } else if !attr.intersects(LpaeDescriptorAttr::XN | LpaeDescriptorAttr::PXN) {
+            flags |= Self::EXECUTE;
+        }
+        if !attr.contains(LpaeDescriptorAttr::NG) {
+            flags |= Self::GLOBAL;
+        }
+        if let Some(mem_attr) = attr.mem_attr() {
+            flags |= mem_attr.mem_type().into();
+        }
+        flags
+    }
+}
+
+impl From<MappingFlags> for LpaeDescriptorAttr {
+    fn from(flags: MappingFlags) -> Self {
+        if flags.is_empty() {
+            return Self::empty();
+        }
+        let mut attr = Self::from_mem_attr(LpaeMemAttr::from_mem_type(flags.mem_type()));
+        if flags.contains(MappingFlags::READ) {
+            attr |= Self::VALID;
+        }
+        if !flags.contains(MappingFlags::WRITE) {
+            attr |= Self::AP_RO;
+        }
+        if flags.contains(MappingFlags::USER) {
+            // Never executable at PL1 when accessible at PL0
+            attr |= Self::AP_USER | Self::PXN;
+            if !flags.contains(MappingFlags::EXECUTE) {
+                attr |= Self::XN;
+            }
+        } else if !flags.contains(MappingFlags::EXECUTE) {
+            attr |= Self::XN | Self::PXN;
+        }
+        if !flags.contains(MappingFlags::GLOBAL) {
+            attr |= Self::NG;
+        }
+        attr
+    }
+}
+
+/// An ARMv7-A Long-descriptor (LPAE) page table entry (64-bit).
+///
+/// This can represent:
+/// - L1/L2 Block descriptor (1GB/2MB mapping)
+/// - L1/L2 Table descriptor (points to the next level table)
+/// - L3 Page descriptor (4KB mapping)
+///
+/// The output address has 40 bits, use the [`GenericLargePTE`] methods to
+/// access the addresses above 4GB on 32-bit systems, the [`GenericPTE`]
+/// methods truncate them to `usize`.
+///
+/// Note that the **AttrIndx\[2:0\]** (bit\[4:2\]) field is set according to
+/// [`LpaeMemAttr`]. The system must configure the MAIR0 and MAIR1 registers
+/// accordingly.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct A32LpaePTE(u64);
+
+impl A32LpaePTE {
+    /// Physical address mask (bits [39:12])
+    const PHYS_ADDR_MASK: u64 = 0x0000_00ff_ffff_f000;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    const fn attr(&self) -> LpaeDescriptorAttr {
+        LpaeDescriptorAttr::from_bits_truncate(self.0)
+    }
+}
+
+impl GenericPTE for A32LpaePTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        Self::new_large_page(paddr.into(), flags, is_huge)
+    }
+
+    fn new_page_at(&self, offset: usize, _was_huge: bool, is_huge: bool) -> Self {
+        Self::new_large_page(self.large_paddr().add(offset), self.flags(), is_huge)
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let attr = LpaeDescriptorAttr::NON_BLOCK | LpaeDescriptorAttr::VALID;
+        Self(attr.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        PhysAddr::from((self.0 & Self::PHYS_ADDR_MASK) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        self.attr().into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.set_large_paddr(paddr.into())
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut attr = LpaeDescriptorAttr::from(flags) | LpaeDescriptorAttr::AF;
+        if !is_huge {
+            attr |= LpaeDescriptorAttr::NON_BLOCK;
+        }
+        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | attr.bits();
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        self.attr().contains(LpaeDescriptorAttr::VALID)
+    }
+
+    fn is_huge(&self) -> bool {
+        !self.attr().contains(LpaeDescriptorAttr::NON_BLOCK)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl GenericLargePTE for A32LpaePTE {
+    const PA_MAX_BITS: usize = 40;
+
+    fn new_large_page(paddr: PhysAddr64, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut attr = LpaeDescriptorAttr::from(flags) | LpaeDescriptorAttr::AF;
+        if !is_huge {
+            attr |= LpaeDescriptorAttr::NON_BLOCK;
+        }
+        Self(attr.bits() | (paddr.as_u64() & Self::PHYS_ADDR_MASK))
+    }
+
+    fn large_paddr(&self) -> PhysAddr64 {
+        PhysAddr64::from_u64(self.0 & Self::PHYS_ADDR_MASK)
+    }
+
+    fn set_large_paddr(&mut self, paddr: PhysAddr64) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | (paddr.as_u64() & Self::PHYS_ADDR_MASK)
+    }
+}
+
+impl fmt::Debug for A32LpaePTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("A32LpaePTE");
+        f.field("raw", &format_args!("{:#018x}", self.0))
+            .field("paddr", &self.large_paddr())
+            .field("attr", &self.attr())
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,7 +1006,72 @@ mod tests {
 
         assert!(pte.is_present());
         assert_eq!(pte.paddr(), paddr);
-        assert!(pte.flags().contains(MappingFlags::READ));
+        assert_eq!(pte.flags(), flags);
+
+        // Executable small pages have the same descriptor type as sections
+        let flags = flags | MappingFlags::EXECUTE;
+        let mut pte = A32PTE::new_small_page(paddr, flags);
+        assert_eq!(pte.leaf_paddr(false), paddr);
+        assert_eq!(pte.leaf_flags(false), flags);
+        pte.set_flags(MappingFlags::READ, false);
+        assert_eq!(pte.leaf_paddr(false), paddr);
+        assert_eq!(pte.leaf_flags(false), MappingFlags::READ);
+    }
+
+    #[test]
+    fn test_supersection_descriptor() {
+        let paddr = PhysAddr::from(0x4100_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE;
+        let pte = A32PTE::new_contiguous_page(paddr, flags, true);
+
+        assert!(pte.is_huge());
+        assert!(pte.is_contiguous(true));
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+    }
+
+    #[test]
+    fn test_large_page_descriptor() {
+        let paddr = PhysAddr::from(0x4001_0000);
+        let flags = MappingFlags::READ | MappingFlags::USER | MappingFlags::DEVICE;
+        let mut pte = A32PTE::new_contiguous_page(paddr, flags, false);
+
+        assert!(pte.is_present());
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+
+        pte.set_flags(MappingFlags::READ | MappingFlags::WRITE, false);
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.paddr(), paddr);
+        assert!(!A32PTE::new_table(paddr).is_contiguous(false));
+    }
+
+    #[test]
+    fn test_memory_types() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        for mem_type in [
+            MemType::Normal,
+            MemType::WriteThrough,
+            MemType::Uncached,
+            MemType::Device,
+            MemType::StronglyOrdered,
+        ] {
+            let flags = flags.with_mem_type(mem_type);
+            assert_eq!(A32PTE::new_section(paddr, flags).flags(), flags);
+            assert_eq!(A32LpaePTE::new_page(paddr, flags, false).flags(), flags);
+        }
+
+        // Write-combining falls back to normal non-cacheable memory.
+        let pte = A32PTE::new_section(paddr, flags | MappingFlags::WRITE_COMBINE);
+        assert_eq!(pte.flags(), flags | MappingFlags::UNCACHED);
+        let pte = A32LpaePTE::new_page(paddr, flags | MappingFlags::WRITE_COMBINE, false);
+        assert_eq!(pte.flags(), flags | MappingFlags::UNCACHED);
+        assert_eq!(
+            (pte.bits() >> 2) & 0b111,
+            LpaeMemAttr::NormalNonCacheable as usize
+        );
     }
 
     #[test]
@@ -488,4 +1083,32 @@ mod tests {
         assert!(!pte.is_huge());
         assert_eq!(pte.paddr(), paddr);
     }
+
+    #[test]
+    fn test_lpae_block_descriptor() {
+        let paddr = PhysAddr64::from_u64(0xab_4020_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+        let pte = A32LpaePTE::new_large_page(paddr, flags, true);
+
+        assert!(pte.is_present());
+        assert!(pte.is_huge());
+        assert_eq!(pte.large_paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits() as u64 >> 54, 1); // XN
+    }
+
+    #[test]
+    fn test_lpae_page_descriptor() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::DEVICE;
+        let mut pte = A32LpaePTE::new_page(paddr, flags, false);
+
+        assert!(!pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+
+        pte.set_large_paddr(PhysAddr64::from_u64(0x80_0000_2000));
+        assert_eq!(pte.large_paddr().as_u64(), 0x80_0000_2000);
+        assert_eq!(pte.flags(), flags);
+    }
 }
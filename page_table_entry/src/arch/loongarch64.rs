@@ -8,7 +8,10 @@ use core::fmt;
 
 use memory_addr::PhysAddr;
 
-use crate::{GenericPTE, MappingFlags};
+use crate::{AccessState, GenericPTE, MappingFlags, MemType};
+
+// The portable memory types honoured by the MAT field: CC, WUC and SUC.
+const MEM_TYPES: &[MemType] = &[MemType::Normal, MemType::Uncached, MemType::Device];
 
 bitflags::bitflags! {
     /// Page-table entry flags.
@@ -48,6 +51,9 @@ bitflags::bitflags! {
         const P = 1 << 7;
         /// Whether the page is writable.
         const W = 1 << 8;
+        /// Software bit, set along with `GH` in a global base page to tell it
+        /// apart from a huge page.
+        const SG = 1 << 9;
         /// Designates a global mapping when using huge page.
         const G = 1 << 12;
         /// Whether the page is not readable.
@@ -78,6 +84,9 @@ impl From<PTEFlags> for MappingFlags {
         if f.contains(PTEFlags::PLVL | PTEFlags::PLVH) {
             ret |= Self::USER;
         }
+        if f.contains(PTEFlags::GH) && f.intersects(PTEFlags::SG | PTEFlags::G) {
+            ret |= Self::GLOBAL;
+        }
         if !f.contains(PTEFlags::MATL) {
             if f.contains(PTEFlags::MATH) {
                 ret |= Self::UNCACHED;
@@ -107,20 +116,32 @@ impl From<MappingFlags> for PTEFlags {
         if f.contains(MappingFlags::USER) {
             ret |= Self::PLVH | Self::PLVL;
         }
-        if !f.contains(MappingFlags::DEVICE) {
-            if f.contains(MappingFlags::UNCACHED) {
-                // weakly-ordered uncached
-                ret |= Self::MATH;
-            } else {
-                // coherent cached,
-                ret |= Self::MATL;
-            }
+        if f.contains(MappingFlags::GLOBAL) {
+            // the encoding of base pages, see `LA64PTE::pte_flags`
+            ret |= Self::GH | Self::SG;
+        }
+        match f.mem_type().fallback(MEM_TYPES) {
+            // coherent cached
+            MemType::Normal => ret |= Self::MATL,
+            // weakly-ordered uncached
+            MemType::Uncached => ret |= Self::MATH,
+            // strongly-ordered uncached
+            _ => {}
         }
         ret
     }
 }
 
 /// page table entry for loongarch64 system
+///
+/// Bit 6 is the global bit of base pages, and the huge page bit of huge pages
+/// whose global bit is bit 12. The software bit [`PTEFlags::SG`] is set in
+/// global base pages, so that the entries can be told apart without the
+/// level.
+///
+/// Only the dirty state is recorded, and writable pages are mapped dirty.
+/// The `D` bit is not set by the hardware: once it is cleared, a write raises
+/// a page modification exception, whose handler must set it again.
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct LA64PTE(u64);
@@ -128,20 +149,42 @@ pub struct LA64PTE(u64);
 impl LA64PTE {
     // bits 12..48
     const PHYS_ADDR_MASK: u64 = 0x0000_ffff_ffff_f000;
+    // bits 13..48, bit 12 is the global bit of huge pages.
+    const HUGE_PHYS_ADDR_MASK: u64 = Self::PHYS_ADDR_MASK & !PTEFlags::G.bits();
 
     /// Creates an empty descriptor with all bits set to zero.
     pub const fn empty() -> Self {
         Self(0)
     }
-}
 
-impl GenericPTE for LA64PTE {
-    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+    const fn addr_mask(is_huge: bool) -> u64 {
+        if is_huge {
+            Self::HUGE_PHYS_ADDR_MASK
+        } else {
+            Self::PHYS_ADDR_MASK
+        }
+    }
+
+    fn pte_flags(flags: MappingFlags, is_huge: bool) -> PTEFlags {
         let mut flags = PTEFlags::from(flags);
         if is_huge {
-            flags |= PTEFlags::GH;
+            if flags.contains(PTEFlags::SG) {
+                flags.remove(PTEFlags::SG);
+                flags.insert(PTEFlags::G);
+            }
+            flags.insert(PTEFlags::GH);
         }
-        Self(flags.bits() | ((paddr.as_usize()) as u64 & Self::PHYS_ADDR_MASK))
+        flags
+    }
+}
+
+impl GenericPTE for LA64PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::DIRTY;
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let flags = Self::pte_flags(flags, is_huge);
+        Self(flags.bits() | ((paddr.as_usize()) as u64 & Self::addr_mask(is_huge)))
     }
 
     fn new_table(paddr: PhysAddr) -> Self {
@@ -149,7 +192,7 @@ impl GenericPTE for LA64PTE {
     }
 
     fn paddr(&self) -> PhysAddr {
-        PhysAddr::from((self.0 & Self::PHYS_ADDR_MASK) as usize)
+        PhysAddr::from((self.0 & Self::addr_mask(self.is_huge())) as usize)
     }
 
     fn flags(&self) -> MappingFlags {
@@ -157,15 +200,23 @@ impl GenericPTE for LA64PTE {
     }
 
     fn set_paddr(&mut self, paddr: PhysAddr) {
-        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK)
+        let mask = Self::addr_mask(self.is_huge());
+        self.0 = (self.0 & !mask) | (paddr.as_usize() as u64 & mask)
     }
 
     fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
-        let mut flags = PTEFlags::from(flags);
-        if is_huge {
-            flags |= PTEFlags::GH;
-        }
-        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits();
+        let flags = Self::pte_flags(flags, is_huge);
+        self.0 = (self.0 & Self::addr_mask(is_huge)) | flags.bits();
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(self.0, 0, PTEFlags::D.bits())
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(0, PTEFlags::D.bits());
+        let old = crate::update_bits_u64(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(old, 0, PTEFlags::D.bits())
     }
 
     fn bits(self) -> usize {
@@ -181,7 +232,8 @@ impl GenericPTE for LA64PTE {
     }
 
     fn is_huge(&self) -> bool {
-        PTEFlags::from_bits_truncate(self.0).contains(PTEFlags::GH)
+        let flags = PTEFlags::from_bits_truncate(self.0);
+        flags.contains(PTEFlags::GH) && !flags.contains(PTEFlags::SG)
     }
 
     fn clear(&mut self) {
@@ -198,3 +250,51 @@ impl fmt::Debug for LA64PTE {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_descriptor() {
+        let paddr = PhysAddr::from(0x9000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::GLOBAL;
+        let mut pte = LA64PTE::new_page(paddr, flags, false);
+        assert!(!pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits() & 0x1240, 0x1240); // GH, SG, and bit 12 of paddr
+
+        pte.set_flags(MappingFlags::READ, false);
+        assert!(!pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), MappingFlags::READ);
+
+        let paddr = PhysAddr::from(0x9020_0000);
+        let mut pte = LA64PTE::new_page(paddr, flags, true);
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits() & 0x1240, 0x1040); // GH (huge), G
+
+        pte.set_flags(MappingFlags::READ, true);
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), MappingFlags::READ);
+    }
+
+    #[test]
+    fn test_access_state() {
+        let paddr = PhysAddr::from(0x9000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = LA64PTE::new_page(paddr, flags, false);
+        assert_eq!(pte.access_state(), AccessState::DIRTY);
+        assert_eq!(
+            pte.clear_access_state(AccessState::all()),
+            AccessState::DIRTY
+        );
+        assert_eq!(pte.bits() & 0x2, 0); // D
+        assert_eq!(pte.access_state(), AccessState::empty());
+        assert_eq!(pte.flags(), flags);
+    }
+}
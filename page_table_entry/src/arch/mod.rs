@@ -1,3 +1,7 @@
+#[cfg(any(target_arch = "x86", doc, docsrs))]
+#[cfg_attr(doc, doc(cfg(target_arch = "x86")))]
+pub mod x86;
+
 #[cfg(any(target_arch = "x86_64", doc, docsrs))]
 #[cfg_attr(doc, doc(cfg(target_arch = "x86_64")))]
 pub mod x86_64;
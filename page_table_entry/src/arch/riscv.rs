@@ -1,13 +1,24 @@
 //! RISC-V page table entries.
+//!
+//! The RV64 entries can encode the memory type in the high bits, by the
+//! standard Svpbmt extension or by the XuanTie C9xx extension. The encoding
+//! is selected by the type parameter of [`Rv64PTEWith`], see [`Rv64MemType`].
+//!
+//! The entries are created with the A and D bits set. Once they are cleared,
+//! the hardware sets them again only with the Svadu extension, otherwise an
+//! access raises a page fault.
 
-use core::fmt;
+use core::{fmt, marker::PhantomData};
 
 use memory_addr::PhysAddr;
 
-use crate::{GenericPTE, MappingFlags};
+use crate::{AccessState, GenericPTE, MappingFlags, MemType};
 
 bitflags::bitflags! {
     /// Page-table entry flags.
+    ///
+    /// The Svnapot, Svpbmt and XuanTie flags share the high bits of RV64
+    /// entries, only one of Svpbmt and XuanTie can be used in a page table.
     #[derive(Debug)]
     pub struct PTEFlags: usize {
         /// Whether the PTE is valid.
@@ -28,53 +39,86 @@ bitflags::bitflags! {
         /// Indicates the virtual page has been written since the last time the
         /// D bit was cleared.
         const D =   1 << 7;
-        // xuantie-c9xx specific flags
+        /// Svpbmt: non-cacheable, idempotent, weakly-ordered main memory
+        /// (PBMT = 1, RV64 only).
+        #[cfg(target_pointer_width = "64")]
+        const PBMT_NC = 1 << 61;
+        /// Svpbmt: non-cacheable, non-idempotent, strongly-ordered I/O memory
+        /// (PBMT = 2, RV64 only).
+        #[cfg(target_pointer_width = "64")]
+        const PBMT_IO = 1 << 62;
+        /// Svpbmt: the PBMT field (bits 62:61, RV64 only). The memory type is
+        /// given by the PMAs if it is zero.
+        #[cfg(target_pointer_width = "64")]
+        const PBMT = Self::PBMT_NC.bits() | Self::PBMT_IO.bits();
+        /// Svnapot: the leaf PTE is one of the 16 replicated entries of a 64K
+        /// naturally aligned power-of-2 (NAPOT) page (RV64 only).
+        #[cfg(target_pointer_width = "64")]
+        const N =   1 << 63;
+        // xuantie-c9xx specific flags (RV64 only)
         // SO, C, B defined both in XuanTie-Openc910 and XuanTie-C906
         // SH only defined in XuanTie-Openc910
         /// SO – Strong ordered memory (1 << 63)
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const SO =  (1 << 63);
         /// C – Cacheable  (1 << 62)
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const C =   (1 << 62);
         /// B – Bufferable (1 << 61)
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const B =   (1 << 61);
         /// SH – Shareable  (1 << 60)
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const SH =  (1 << 60);
 
         /// xuantie-c9xx device memory flags
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const XUANTIE_C9XX_DEVICE = Self::SO.bits() | Self::B.bits();
         /// xuantie-c9xx normal memory flags
-        #[cfg(feature = "xuantie-c9xx")]
+        #[cfg(target_pointer_width = "64")]
         const XUANTIE_C9XX_NORMAL = Self::C.bits() | Self::B.bits() | Self::SH.bits();
     }
 }
 
+/// Returns the mapping flags of `f` without the memory types.
+fn access_flags(f: &PTEFlags) -> MappingFlags {
+    let mut ret = MappingFlags::empty();
+    if !f.contains(PTEFlags::V) {
+        return ret;
+    }
+    if f.contains(PTEFlags::R) {
+        ret |= MappingFlags::READ;
+    }
+    if f.contains(PTEFlags::W) {
+        ret |= MappingFlags::WRITE;
+    }
+    if f.contains(PTEFlags::X) {
+        ret |= MappingFlags::EXECUTE;
+    }
+    if f.contains(PTEFlags::U) {
+        ret |= MappingFlags::USER;
+    }
+    if f.contains(PTEFlags::G) {
+        ret |= MappingFlags::GLOBAL;
+    }
+    ret
+}
+
+/// Decodes the flags with the memory types of [`Rv64PTE`] on RV64, i.e., none,
+/// or [`XuanTie`] if the `xuantie-c9xx` feature is enabled.
 impl From<PTEFlags> for MappingFlags {
     fn from(f: PTEFlags) -> Self {
-        let mut ret = Self::empty();
-        if !f.contains(PTEFlags::V) {
-            return ret;
-        }
-        if f.contains(PTEFlags::R) {
-            ret |= Self::READ;
-        }
-        if f.contains(PTEFlags::W) {
-            ret |= Self::WRITE;
-        }
-        if f.contains(PTEFlags::X) {
-            ret |= Self::EXECUTE;
-        }
-        if f.contains(PTEFlags::U) {
-            ret |= Self::USER;
+        let ret = access_flags(&f);
+        #[cfg(target_pointer_width = "64")]
+        if !ret.is_empty() {
+            return ret | RawMemType::decode(f.bits() as u64);
         }
         ret
     }
 }
 
+/// Encodes the flags with the memory types of [`Rv64PTE`] on RV64, i.e., none,
+/// or [`XuanTie`] if the `xuantie-c9xx` feature is enabled.
 impl From<MappingFlags> for PTEFlags {
     fn from(f: MappingFlags) -> Self {
         if f.is_empty() {
@@ -93,26 +137,300 @@ impl From<MappingFlags> for PTEFlags {
         if f.contains(MappingFlags::USER) {
             ret |= Self::U;
         }
+        if f.contains(MappingFlags::GLOBAL) {
+            ret |= Self::G;
+        }
+        #[cfg(target_pointer_width = "64")]
+        {
+            ret |= Self::from_bits_retain(RawMemType::encode(f) as usize);
+        }
+        ret
+    }
+}
 
-        #[cfg(feature = "xuantie-c9xx")]
-        if f.contains(MappingFlags::DEVICE) {
-            ret |= Self::XUANTIE_C9XX_DEVICE;
-        } else {
-            ret |= Self::XUANTIE_C9XX_NORMAL;
+// The memory types of the conversions between `PTEFlags` and `MappingFlags`,
+// the same as `Rv64PTE`.
+#[cfg(all(target_pointer_width = "64", not(feature = "xuantie-c9xx")))]
+type RawMemType = NoMemType;
+#[cfg(all(target_pointer_width = "64", feature = "xuantie-c9xx"))]
+type RawMemType = XuanTie;
+
+/// The encoding of memory types in RV64 page table entries.
+///
+/// Without an encoding, the memory types are given by the physical memory
+/// attributes (PMAs) of the platform, and the memory type flags are ignored.
+pub trait Rv64MemType: Send + Sync + 'static {
+    /// The entry bits used by this encoding.
+    const MASK: u64;
+
+    /// The portable memory types honoured by this encoding, see
+    /// [`GenericPTE::MEM_TYPES`].
+    const MEM_TYPES: &'static [MemType];
+
+    /// Returns the entry bits of the memory type in `flags`.
+    fn encode(flags: MappingFlags) -> u64;
+
+    /// Returns the memory type flags encoded in the entry `bits`.
+    fn decode(bits: u64) -> MappingFlags;
+}
+
+/// No memory types, for the hardware without Svpbmt.
+pub struct NoMemType;
+
+/// The standard Svpbmt extension, which uses the PBMT field (bits 62:61).
+///
+/// [`MemType::Device`] is mapped to IO, and [`MemType::Uncached`] is mapped
+/// to NC. The write-through and write-combining types fall back to NC.
+pub struct Svpbmt;
+
+/// The memory attributes of XuanTie C906 and C910 cores (bits 63:60).
+///
+/// The Svnapot 64K pages are not available, as the N bit is taken by the
+/// strong order (SO) bit.
+pub struct XuanTie;
+
+impl Rv64MemType for NoMemType {
+    const MASK: u64 = 0;
+    const MEM_TYPES: &'static [MemType] = &[MemType::Normal];
+
+    fn encode(_flags: MappingFlags) -> u64 {
+        0
+    }
+
+    fn decode(_bits: u64) -> MappingFlags {
+        MappingFlags::empty()
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl Rv64MemType for Svpbmt {
+    const MASK: u64 = PTEFlags::PBMT.bits() as u64;
+    const MEM_TYPES: &'static [MemType] = &[MemType::Normal, MemType::Uncached, MemType::Device];
+
+    fn encode(flags: MappingFlags) -> u64 {
+        let pbmt = match flags.mem_type().fallback(Self::MEM_TYPES) {
+            MemType::Normal => PTEFlags::empty(),
+            MemType::Uncached => PTEFlags::PBMT_NC,
+            _ => PTEFlags::PBMT_IO,
+        };
+        pbmt.bits() as u64
+    }
+
+    fn decode(bits: u64) -> MappingFlags {
+        match (bits & Self::MASK) >> 61 {
+            1 => MappingFlags::UNCACHED,
+            2 => MappingFlags::DEVICE,
+            _ => MappingFlags::empty(), // PMA, or reserved
         }
+    }
+}
 
-        ret
+#[cfg(target_pointer_width = "64")]
+impl Rv64MemType for XuanTie {
+    const MASK: u64 =
+        (PTEFlags::SO.bits() | PTEFlags::C.bits() | PTEFlags::B.bits() | PTEFlags::SH.bits())
+            as u64;
+    const MEM_TYPES: &'static [MemType] = &[MemType::Normal, MemType::Uncached, MemType::Device];
+
+    fn encode(flags: MappingFlags) -> u64 {
+        let attr = match flags.mem_type().fallback(Self::MEM_TYPES) {
+            MemType::Normal => PTEFlags::XUANTIE_C9XX_NORMAL,
+            MemType::Uncached => PTEFlags::B | PTEFlags::SH,
+            _ => PTEFlags::XUANTIE_C9XX_DEVICE,
+        };
+        attr.bits() as u64
+    }
+
+    fn decode(bits: u64) -> MappingFlags {
+        let f = PTEFlags::from_bits_truncate(bits as usize);
+        if f.contains(PTEFlags::SO) {
+            MappingFlags::DEVICE
+        } else if !f.contains(PTEFlags::C) {
+            MappingFlags::UNCACHED
+        } else {
+            MappingFlags::empty()
+        }
     }
 }
 
-/// Sv39 and Sv48 page table entry for RV64 systems.
-#[derive(Clone, Copy)]
+/// Sv39, Sv48 and Sv57 page table entry for RV64 systems, with the memory
+/// types encoded by `MT`.
 #[repr(transparent)]
-pub struct Rv64PTE(u64);
+pub struct Rv64PTEWith<MT: Rv64MemType>(u64, PhantomData<MT>);
+
+/// Sv39, Sv48 and Sv57 page table entry for RV64 systems.
+///
+/// The memory types are not encoded, or encoded by [`XuanTie`] if the
+/// `xuantie-c9xx` feature is enabled.
+#[cfg(not(feature = "xuantie-c9xx"))]
+pub type Rv64PTE = Rv64PTEWith<NoMemType>;
+/// Sv39, Sv48 and Sv57 page table entry for RV64 systems.
+///
+/// The memory types are not encoded, or encoded by [`XuanTie`] if the
+/// `xuantie-c9xx` feature is enabled.
+#[cfg(feature = "xuantie-c9xx")]
+pub type Rv64PTE = Rv64PTEWith<XuanTie>;
+/// RV64 page table entry with the Svpbmt memory types.
+pub type Rv64SvpbmtPTE = Rv64PTEWith<Svpbmt>;
+/// RV64 page table entry with the XuanTie C9xx memory types.
+pub type Rv64XuanTiePTE = Rv64PTEWith<XuanTie>;
 
-impl Rv64PTE {
+impl<MT: Rv64MemType> Clone for Rv64PTEWith<MT> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<MT: Rv64MemType> Copy for Rv64PTEWith<MT> {}
+
+impl<MT: Rv64MemType> Rv64PTEWith<MT> {
     // bits 10..54
     const PHYS_ADDR_MASK: u64 = (1 << 54) - (1 << 10);
+    // PPN[3:0] of a 64K NAPOT page, the only size defined by Svnapot.
+    const NAPOT_64K_PPN: u64 = 0b1000 << 10;
+    const NAPOT_PPN_MASK: u64 = 0b1111 << 10;
+    // The high bits whose meaning depends on the extensions.
+    const EXT_MASK: u64 = 0xf << 60;
+    // The N bit, unless taken by the memory type encoding.
+    const NAPOT: u64 = if MT::MASK & (1 << 63) == 0 {
+        1 << 63
+    } else {
+        0
+    };
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0, PhantomData)
+    }
+
+    const fn is_napot(&self) -> bool {
+        self.0 & Self::NAPOT != 0
+    }
+
+    /// Returns the PPN bits of `paddr`, with PPN[3:0] replaced by the NAPOT
+    /// encoding if `napot` is true.
+    const fn ppn_bits(paddr: PhysAddr, napot: bool) -> u64 {
+        let ppn = (paddr.as_usize() >> 2) as u64 & Self::PHYS_ADDR_MASK;
+        if napot {
+            (ppn & !Self::NAPOT_PPN_MASK) | Self::NAPOT_64K_PPN
+        } else {
+            ppn
+        }
+    }
+
+    /// Returns the entry bits of `mflags`, including the memory type.
+    fn flag_bits(mflags: MappingFlags) -> u64 {
+        let flags = PTEFlags::from(mflags);
+        debug_assert!(flags.intersects(PTEFlags::R | PTEFlags::X));
+        (flags.bits() as u64 & !Self::EXT_MASK) | MT::encode(mflags)
+    }
+}
+
+impl<MT: Rv64MemType> GenericPTE for Rv64PTEWith<MT> {
+    const MEM_TYPES: &'static [MemType] = MT::MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, mflags: MappingFlags, _is_huge: bool) -> Self {
+        Self(
+            Self::flag_bits(mflags) | Self::ppn_bits(paddr, false),
+            PhantomData,
+        )
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        Self(
+            PTEFlags::V.bits() as u64 | Self::ppn_bits(paddr, false),
+            PhantomData,
+        )
+    }
+
+    fn new_contiguous_page(paddr: PhysAddr, mflags: MappingFlags, is_huge: bool) -> Self {
+        // Svnapot only defines 64K pages, mapped by 16 last level entries.
+        debug_assert!(!is_huge);
+        let napot = Self::NAPOT != 0;
+        Self(
+            Self::flag_bits(mflags) | Self::NAPOT | Self::ppn_bits(paddr, napot),
+            PhantomData,
+        )
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        let mut ppn = self.0 & Self::PHYS_ADDR_MASK;
+        if self.is_napot() {
+            ppn &= !Self::NAPOT_PPN_MASK;
+        }
+        PhysAddr::from((ppn << 2) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        let flags = access_flags(&PTEFlags::from_bits_truncate(self.0 as usize));
+        if flags.is_empty() {
+            return flags;
+        }
+        flags | MT::decode(self.0)
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | Self::ppn_bits(paddr, self.is_napot());
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, _is_huge: bool) {
+        self.0 = (self.0 & (Self::PHYS_ADDR_MASK | Self::NAPOT)) | Self::flag_bits(flags);
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(self.0, PTEFlags::A.bits() as u64, PTEFlags::D.bits() as u64)
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(PTEFlags::A.bits() as u64, PTEFlags::D.bits() as u64);
+        let old = crate::update_bits_u64(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(old, PTEFlags::A.bits() as u64, PTEFlags::D.bits() as u64)
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as usize).contains(PTEFlags::V)
+    }
+
+    fn is_huge(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as usize).intersects(PTEFlags::R | PTEFlags::X)
+    }
+
+    fn is_contiguous(&self, is_huge: bool) -> bool {
+        !is_huge && self.is_napot()
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl<MT: Rv64MemType> fmt::Debug for Rv64PTEWith<MT> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("Rv64PTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+/// Sv32 page table entry for RV32 systems.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct Rv32PTE(u32);
+
+impl Rv32PTE {
+    // bits 10..32
+    const PHYS_ADDR_MASK: u32 = 0xffff_fc00;
 
     /// Creates an empty descriptor with all bits set to zero.
     pub const fn empty() -> Self {
@@ -120,34 +438,54 @@ impl Rv64PTE {
     }
 }
 
-impl GenericPTE for Rv64PTE {
+impl GenericPTE for Rv32PTE {
+    const ACCESS_STATE: AccessState = AccessState::all();
+
     fn new_page(paddr: PhysAddr, mflags: MappingFlags, _is_huge: bool) -> Self {
         let flags = PTEFlags::from(mflags);
         debug_assert!(flags.intersects(PTEFlags::R | PTEFlags::X));
-        Self(flags.bits() as u64 | ((paddr.as_usize() >> 2) as u64 & Self::PHYS_ADDR_MASK))
+        Self(flags.bits() as u32 | ((paddr.as_usize() >> 2) as u32 & Self::PHYS_ADDR_MASK))
     }
 
     fn new_table(paddr: PhysAddr) -> Self {
-        Self(PTEFlags::V.bits() as u64 | ((paddr.as_usize() >> 2) as u64 & Self::PHYS_ADDR_MASK))
+        Self(PTEFlags::V.bits() as u32 | ((paddr.as_usize() >> 2) as u32 & Self::PHYS_ADDR_MASK))
     }
 
     fn paddr(&self) -> PhysAddr {
-        PhysAddr::from(((self.0 & Self::PHYS_ADDR_MASK) << 2) as usize)
+        PhysAddr::from(((self.0 & Self::PHYS_ADDR_MASK) as usize) << 2)
     }
 
     fn flags(&self) -> MappingFlags {
-        PTEFlags::from_bits_truncate(self.0 as usize).into()
+        access_flags(&PTEFlags::from_bits_truncate(self.0 as usize))
     }
 
     fn set_paddr(&mut self, paddr: PhysAddr) {
         self.0 = (self.0 & !Self::PHYS_ADDR_MASK)
-            | ((paddr.as_usize() as u64 >> 2) & Self::PHYS_ADDR_MASK);
+            | ((paddr.as_usize() >> 2) as u32 & Self::PHYS_ADDR_MASK);
     }
 
     fn set_flags(&mut self, flags: MappingFlags, _is_huge: bool) {
         let flags = PTEFlags::from(flags);
         debug_assert!(flags.intersects(PTEFlags::R | PTEFlags::X));
-        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits() as u64;
+        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits() as u32;
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(
+            self.0 as u64,
+            PTEFlags::A.bits() as u64,
+            PTEFlags::D.bits() as u64,
+        )
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(PTEFlags::A.bits() as u64, PTEFlags::D.bits() as u64) as u32;
+        let old = crate::update_bits_u32(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(
+            old as u64,
+            PTEFlags::A.bits() as u64,
+            PTEFlags::D.bits() as u64,
+        )
     }
 
     fn bits(self) -> usize {
@@ -171,12 +509,86 @@ impl GenericPTE for Rv64PTE {
     }
 }
 
-impl fmt::Debug for Rv64PTE {
+impl fmt::Debug for Rv32PTE {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut f = f.debug_struct("Rv64PTE");
+        let mut f = f.debug_struct("Rv32PTE");
         f.field("raw", &self.0)
             .field("paddr", &self.paddr())
             .field("flags", &self.flags())
             .finish()
     }
 }
+
+#[cfg(all(test, target_pointer_width = "64"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_napot_descriptor() {
+        let paddr = PhysAddr::from(0x8001_3000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = Rv64SvpbmtPTE::new_contiguous_page(paddr, flags, false);
+
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.paddr(), PhysAddr::from(0x8001_0000));
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits() >> 10 & 0xf, 0b1000); // PPN[3:0]
+        assert_eq!(pte.bits() >> 63, 1); // N
+
+        pte.set_flags(MappingFlags::READ, false);
+        assert!(pte.is_contiguous(false));
+        assert_eq!(pte.flags(), MappingFlags::READ);
+        pte.set_paddr(PhysAddr::from(0x8002_0000));
+        assert_eq!(pte.bits() >> 10 & 0xf, 0b1000);
+        assert_eq!(pte.paddr(), PhysAddr::from(0x8002_0000));
+        assert!(!Rv64SvpbmtPTE::new_page(paddr, flags, false).is_contiguous(false));
+
+        // the N bit is taken by the XuanTie memory attributes
+        let pte = Rv64XuanTiePTE::new_contiguous_page(paddr, flags, false);
+        assert!(!pte.is_contiguous(false));
+        assert_eq!(pte.paddr(), paddr);
+    }
+
+    #[test]
+    fn test_memory_types() {
+        let paddr = PhysAddr::from(0x1000_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let device = flags | MappingFlags::DEVICE;
+        let uncached = flags | MappingFlags::UNCACHED;
+
+        let pte = Rv64PTEWith::<NoMemType>::new_page(paddr, device, false);
+        assert_eq!(pte.bits() >> 60, 0);
+        assert_eq!(pte.flags(), flags);
+
+        let mut pte = Rv64SvpbmtPTE::new_page(paddr, device, false);
+        assert_eq!(pte.bits() >> 61, 0b10); // IO
+        assert_eq!(pte.flags(), device);
+        pte.set_flags(uncached, false);
+        assert_eq!(pte.bits() >> 61, 0b01); // NC
+        assert_eq!(pte.flags(), uncached);
+        pte.set_flags(flags, false);
+        assert_eq!(pte.bits() >> 61, 0b00); // PMA
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.paddr(), paddr);
+
+        // Falls back to the next stronger memory type.
+        let pte = Rv64SvpbmtPTE::new_page(paddr, flags | MappingFlags::WRITE_THROUGH, false);
+        assert_eq!(pte.flags(), uncached);
+        let pte = Rv64SvpbmtPTE::new_page(paddr, device | MappingFlags::UNCACHED, false);
+        assert_eq!(pte.flags(), device);
+
+        let pte = Rv64XuanTiePTE::new_page(paddr, device, false);
+        assert_eq!(pte.bits() >> 60, 0b1010); // SO | B
+        assert_eq!(pte.flags(), device);
+        let pte = Rv64XuanTiePTE::new_page(paddr, flags, false);
+        assert_eq!(pte.bits() >> 60, 0b0111); // C | B | SH
+        assert_eq!(pte.flags(), flags);
+
+        // The raw flags have the memory types of `Rv64PTE`.
+        for mflags in [flags, device] {
+            let pte = Rv64PTE::new_page(paddr, mflags, false);
+            assert_eq!(PTEFlags::from(mflags).bits() >> 60, pte.bits() >> 60);
+            assert_eq!(MappingFlags::from(PTEFlags::from(mflags)), pte.flags());
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! x86 page table entries on 32-bit paging and PAE paging.
+//!
+//! - 32-bit paging: 2 levels, 1024 entries of 32 bits in each table, 4M pages
+//!   are supported when `CR4.PSE` is set.
+//! - PAE paging: 3 levels, a 4-entry PDPT, and 512 entries of 64 bits in page
+//!   directories and page tables, 2M pages are supported.
+
+use core::fmt;
+
+use memory_addr::PhysAddr;
+
+use crate::{AccessState, GenericPTE, MappingFlags, MemType};
+
+// The portable memory types honoured with the power-on default `IA32_PAT`,
+// indexed by the PCD and PWT bits: WB, WT, UC- and UC.
+const MEM_TYPES: &[MemType] = &[MemType::Normal, MemType::WriteThrough, MemType::Device];
+
+bitflags::bitflags! {
+    /// Page-table entry flags.
+    ///
+    /// The 32-bit paging entries only use the lower 32 bits.
+    #[derive(Debug, Clone, Copy)]
+    pub struct PTEFlags: u64 {
+        /// Whether the entry is present.
+        const PRESENT =         1 << 0;
+        /// Whether the memory is writable.
+        const WRITABLE =        1 << 1;
+        /// Whether the memory is accessible in user mode.
+        const USER_ACCESSIBLE = 1 << 2;
+        /// Page-level write-through.
+        const WRITE_THROUGH =   1 << 3;
+        /// Page-level cache disable.
+        const NO_CACHE =        1 << 4;
+        /// Set by the CPU when the entry is used for address translation.
+        const ACCESSED =        1 << 5;
+        /// Set by the CPU on a write to the mapped page.
+        const DIRTY =           1 << 6;
+        /// The entry maps a 4M (32-bit paging) or 2M (PAE paging) page.
+        const HUGE_PAGE =       1 << 7;
+        /// The translation is global, which is not flushed on CR3 writes.
+        const GLOBAL =          1 << 8;
+        /// Forbid code execution from the mapped page (PAE paging only).
+        const NO_EXECUTE =      1 << 63;
+    }
+}
+
+impl From<PTEFlags> for MappingFlags {
+    fn from(f: PTEFlags) -> Self {
+        if !f.contains(PTEFlags::PRESENT) {
+            return Self::empty();
+        }
+        let mut ret = Self::READ;
+        if f.contains(PTEFlags::WRITABLE) {
+            ret |= Self::WRITE;
+        }
+        if !f.contains(PTEFlags::NO_EXECUTE) {
+            ret |= Self::EXECUTE;
+        }
+        if f.contains(PTEFlags::USER_ACCESSIBLE) {
+            ret |= Self::USER;
+        }
+        if f.contains(PTEFlags::GLOBAL) {
+            ret |= Self::GLOBAL;
+        }
+        if f.contains(PTEFlags::NO_CACHE) {
+            ret |= Self::DEVICE;
+        } else if f.contains(PTEFlags::WRITE_THROUGH) {
+            ret |= Self::WRITE_THROUGH;
+        }
+        ret
+    }
+}
+
+impl From<MappingFlags> for PTEFlags {
+    fn from(f: MappingFlags) -> Self {
+        if f.is_empty() {
+            return Self::empty();
+        }
+        let mut ret = Self::PRESENT;
+        if f.contains(MappingFlags::WRITE) {
+            ret |= Self::WRITABLE;
+        }
+        if !f.contains(MappingFlags::EXECUTE) {
+            ret |= Self::NO_EXECUTE;
+        }
+        if f.contains(MappingFlags::USER) {
+            ret |= Self::USER_ACCESSIBLE;
+        }
+        if f.contains(MappingFlags::GLOBAL) {
+            ret |= Self::GLOBAL;
+        }
+        match f.mem_type().fallback(MEM_TYPES) {
+            MemType::Normal => {}
+            MemType::WriteThrough => ret |= Self::WRITE_THROUGH,
+            _ => ret |= Self::NO_CACHE | Self::WRITE_THROUGH,
+        }
+        ret
+    }
+}
+
+/// An x86 page table entry on 32-bit paging.
+///
+/// The execute-disable bit is not available, all mappings are executable.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct X86PTE(u32);
+
+impl X86PTE {
+    // bits 12..32
+    const PHYS_ADDR_MASK: u32 = 0xffff_f000;
+    // bits 22..32, bits 13..21 are for PAT and PSE-36 which are not used.
+    const HUGE_PHYS_ADDR_MASK: u32 = 0xffc0_0000;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    const fn addr_mask(is_huge: bool) -> u32 {
+        if is_huge {
+            Self::HUGE_PHYS_ADDR_MASK
+        } else {
+            Self::PHYS_ADDR_MASK
+        }
+    }
+}
+
+impl GenericPTE for X86PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut flags = PTEFlags::from(flags);
+        if is_huge {
+            flags |= PTEFlags::HUGE_PAGE;
+        }
+        Self(flags.bits() as u32 | (paddr.as_usize() as u32 & Self::addr_mask(is_huge)))
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let flags = PTEFlags::PRESENT | PTEFlags::WRITABLE | PTEFlags::USER_ACCESSIBLE;
+        Self(flags.bits() as u32 | (paddr.as_usize() as u32 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        PhysAddr::from((self.0 & Self::addr_mask(self.is_huge())) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        PTEFlags::from_bits_truncate(self.0 as u64).into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        let mask = Self::addr_mask(self.is_huge());
+        self.0 = (self.0 & !mask) | (paddr.as_usize() as u32 & mask)
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut flags = PTEFlags::from(flags);
+        if is_huge {
+            flags |= PTEFlags::HUGE_PAGE;
+        }
+        self.0 = (self.0 & Self::addr_mask(is_huge)) | flags.bits() as u32
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(
+            self.0 as u64,
+            PTEFlags::ACCESSED.bits(),
+            PTEFlags::DIRTY.bits(),
+        )
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(PTEFlags::ACCESSED.bits(), PTEFlags::DIRTY.bits()) as u32;
+        let old = crate::update_bits_u32(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(
+            old as u64,
+            PTEFlags::ACCESSED.bits(),
+            PTEFlags::DIRTY.bits(),
+        )
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as u64).contains(PTEFlags::PRESENT)
+    }
+
+    fn is_huge(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0 as u64).contains(PTEFlags::HUGE_PAGE)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl fmt::Debug for X86PTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("X86PTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+/// An x86 page table entry on PAE paging.
+///
+/// The entries that map pages without [`MappingFlags::EXECUTE`] set the
+/// execute-disable bit (bit 63), which is reserved unless `IA32_EFER.NXE` is
+/// set.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct X86PaePTE(u64);
+
+impl X86PaePTE {
+    // bits 12..52
+    const PHYS_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+    // bits 21..52, bit 12 is for PAT which is not used.
+    const HUGE_PHYS_ADDR_MASK: u64 = 0x000f_ffff_ffe0_0000;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    const fn addr_mask(is_huge: bool) -> u64 {
+        if is_huge {
+            Self::HUGE_PHYS_ADDR_MASK
+        } else {
+            Self::PHYS_ADDR_MASK
+        }
+    }
+}
+
+impl GenericPTE for X86PaePTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut flags = PTEFlags::from(flags);
+        if is_huge {
+            flags |= PTEFlags::HUGE_PAGE;
+        }
+        Self(flags.bits() | (paddr.as_usize() as u64 & Self::addr_mask(is_huge)))
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let flags = PTEFlags::PRESENT | PTEFlags::WRITABLE | PTEFlags::USER_ACCESSIBLE;
+        Self(flags.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn new_root_table(paddr: PhysAddr) -> Self {
+        // The access rights bits of PDPTEs are reserved and must be zero.
+        Self(PTEFlags::PRESENT.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        PhysAddr::from((self.0 & Self::addr_mask(self.is_huge())) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        PTEFlags::from_bits_truncate(self.0).into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        let mask = Self::addr_mask(self.is_huge());
+        self.0 = (self.0 & !mask) | (paddr.as_usize() as u64 & mask)
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut flags = PTEFlags::from(flags);
+        if is_huge {
+            flags |= PTEFlags::HUGE_PAGE;
+        }
+        self.0 = (self.0 & Self::addr_mask(is_huge)) | flags.bits()
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(self.0, PTEFlags::ACCESSED.bits(), PTEFlags::DIRTY.bits())
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(PTEFlags::ACCESSED.bits(), PTEFlags::DIRTY.bits());
+        let old = crate::update_bits_u64(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(old, PTEFlags::ACCESSED.bits(), PTEFlags::DIRTY.bits())
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0).contains(PTEFlags::PRESENT)
+    }
+
+    fn is_huge(&self) -> bool {
+        PTEFlags::from_bits_truncate(self.0).contains(PTEFlags::HUGE_PAGE)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl fmt::Debug for X86PaePTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("X86PaePTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huge_page_descriptor() {
+        let paddr = PhysAddr::from(0x4040_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let pte = X86PTE::new_page(paddr, flags, true);
+
+        assert!(pte.is_present());
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags | MappingFlags::EXECUTE);
+    }
+
+    #[test]
+    fn test_pae_page_descriptor() {
+        let paddr = PhysAddr::from(0x4020_0000);
+        let flags = MappingFlags::READ | MappingFlags::USER;
+        let pte = X86PaePTE::new_page(paddr, flags, true);
+
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits() as u64 >> 63, 1); // NO_EXECUTE
+    }
+
+    #[test]
+    fn test_pae_pdpt_descriptor() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let pte = X86PaePTE::new_root_table(paddr);
+
+        assert!(pte.is_present());
+        assert!(!pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.bits(), 0x4000_1001); // PRESENT only
+    }
+}
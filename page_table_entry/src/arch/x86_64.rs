@@ -5,7 +5,15 @@ use core::fmt;
 use memory_addr::PhysAddr;
 pub use x86_64::structures::paging::page_table::PageTableFlags as PTF;
 
-use crate::{GenericPTE, MappingFlags};
+use crate::{AccessState, GenericPTE, MappingFlags, MemType};
+
+// The portable memory types honoured by x86 paging and EPT.
+const MEM_TYPES: &[MemType] = &[
+    MemType::Normal,
+    MemType::WriteThrough,
+    MemType::WriteCombining,
+    MemType::Device,
+];
 
 impl From<PTF> for MappingFlags {
     fn from(f: PTF) -> Self {
@@ -22,10 +30,12 @@ impl From<PTF> for MappingFlags {
         if f.contains(PTF::USER_ACCESSIBLE) {
             ret |= Self::USER;
         }
-        if f.contains(PTF::NO_CACHE) {
-            ret |= Self::UNCACHED;
+        if f.contains(PTF::GLOBAL) {
+            ret |= Self::GLOBAL;
         }
-        ret
+        // the PAT bit is ambiguous without the level of the entry
+        let bits = f.bits() & (PTF::NO_CACHE | PTF::WRITE_THROUGH).bits();
+        ret | PatMemType::from_pte_bits(bits, false).mem_type().into()
     }
 }
 
@@ -44,14 +54,153 @@ impl From<MappingFlags> for PTF {
         if f.contains(MappingFlags::USER) {
             ret |= Self::USER_ACCESSIBLE;
         }
-        if f.contains(MappingFlags::DEVICE) || f.contains(MappingFlags::UNCACHED) {
-            ret |= Self::NO_CACHE | Self::WRITE_THROUGH;
+        if f.contains(MappingFlags::GLOBAL) {
+            ret |= Self::GLOBAL;
+        }
+        let mem_type = PatMemType::from_mem_type(f.mem_type());
+        ret | Self::from_bits_retain(mem_type.pte_bits(false))
+    }
+}
+
+/// The x86 memory types, which are selected by the PAT, PCD and PWT bits of
+/// the entries that map pages.
+///
+/// The 3 bits form an index into the `IA32_PAT` MSR, which should be set to
+/// [`PatMemType::PAT_VALUE`]. The discriminant of each type is its index.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PatMemType {
+    /// Write-back.
+    WriteBack      = 0,
+    /// Write-through.
+    WriteThrough   = 1,
+    /// Write-combining.
+    WriteCombining = 2,
+    /// Uncacheable.
+    Uncached       = 3,
+    /// Write-protected.
+    WriteProtected = 5,
+    /// Uncacheable, which can be overridden by write-combining MTRRs.
+    UncachedMinus  = 6,
+}
+
+impl PatMemType {
+    /// The `IA32_PAT` MSR should be set to this value to match the memory
+    /// types in the entries.
+    ///
+    /// The types of [`MappingFlags`] only use the first 4 entries, which are
+    /// selected without the PAT bit. They are the same as the power-on
+    /// default, except that entry 2 is write-combining instead of UC-.
+    pub const PAT_VALUE: u64 = {
+        const UC: u64 = 0x00;
+        const WC: u64 = 0x01;
+        const WT: u64 = 0x04;
+        const WP: u64 = 0x05;
+        const WB: u64 = 0x06;
+        const UC_MINUS: u64 = 0x07;
+        let types = [WB, WT, WC, UC, WB, WP, UC_MINUS, UC];
+        let mut value = 0;
+        let mut i = 0;
+        while i < types.len() {
+            value |= types[i] << (i * 8);
+            i += 1;
+        }
+        value // 0x0007_0506_0001_0406
+    };
+
+    /// Returns the type that the portable memory type is mapped to.
+    ///
+    /// [`MemType::Uncached`] and [`MemType::StronglyOrdered`] fall back to
+    /// [`Uncached`](Self::Uncached), which is strongly ordered.
+    pub const fn from_mem_type(mem_type: MemType) -> Self {
+        match mem_type.fallback(MEM_TYPES) {
+            MemType::Normal => Self::WriteBack,
+            MemType::WriteThrough => Self::WriteThrough,
+            MemType::WriteCombining => Self::WriteCombining,
+            _ => Self::Uncached,
+        }
+    }
+
+    /// Returns the portable memory type of this type.
+    pub const fn mem_type(self) -> MemType {
+        match self {
+            Self::WriteBack => MemType::Normal,
+            Self::WriteThrough | Self::WriteProtected => MemType::WriteThrough,
+            Self::WriteCombining => MemType::WriteCombining,
+            Self::Uncached | Self::UncachedMinus => MemType::Device,
+        }
+    }
+
+    // The PAT bit of the entries that map 4K pages.
+    const PAT_4K: u64 = 1 << 7;
+    // The PAT bit of the entries that map 2M or 1G pages.
+    const PAT_HUGE: u64 = 1 << 12;
+    const PCD: u64 = PTF::NO_CACHE.bits();
+    const PWT: u64 = PTF::WRITE_THROUGH.bits();
+
+    const fn pat_bit(is_huge: bool) -> u64 {
+        if is_huge {
+            Self::PAT_HUGE
+        } else {
+            Self::PAT_4K
+        }
+    }
+
+    /// Returns the mask of the PAT, PCD and PWT bits.
+    pub const fn pte_mask(is_huge: bool) -> u64 {
+        Self::pat_bit(is_huge) | Self::PCD | Self::PWT
+    }
+
+    /// Returns the PAT, PCD and PWT bits of the entry that selects this memory
+    /// type.
+    ///
+    /// `is_huge` indicates whether the entry maps a 2M or 1G page, whose PAT
+    /// bit is bit 12 instead of bit 7.
+    pub const fn pte_bits(self, is_huge: bool) -> u64 {
+        let idx = self as u64;
+        let mut bits = 0;
+        if idx & 0b001 != 0 {
+            bits |= Self::PWT;
+        }
+        if idx & 0b010 != 0 {
+            bits |= Self::PCD;
+        }
+        if idx & 0b100 != 0 {
+            bits |= Self::pat_bit(is_huge);
+        }
+        bits
+    }
+
+    /// Returns the memory type selected by the PAT, PCD and PWT bits of the
+    /// entry `bits`, see [`pte_bits`](Self::pte_bits).
+    pub const fn from_pte_bits(bits: u64, is_huge: bool) -> Self {
+        let mut idx = 0;
+        if bits & Self::PWT != 0 {
+            idx |= 0b001;
+        }
+        if bits & Self::PCD != 0 {
+            idx |= 0b010;
+        }
+        if bits & Self::pat_bit(is_huge) != 0 {
+            idx |= 0b100;
+        }
+        match idx {
+            0 | 4 => Self::WriteBack,
+            1 => Self::WriteThrough,
+            2 => Self::WriteCombining,
+            5 => Self::WriteProtected,
+            6 => Self::UncachedMinus,
+            _ => Self::Uncached,
         }
-        ret
     }
 }
 
 /// An x86_64 page table entry.
+///
+/// For the entries that map 2M or 1G pages, bit 12 is the PAT bit instead of
+/// an address bit. It is included in [`paddr`](GenericPTE::paddr), which
+/// should be aligned down to the page size, and overwritten by
+/// [`set_paddr`](GenericPTE::set_paddr).
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct X64PTE(u64);
@@ -64,15 +213,50 @@ impl X64PTE {
     pub const fn empty() -> Self {
         Self(0)
     }
+
+    const fn addr_mask(is_huge: bool) -> u64 {
+        if is_huge {
+            Self::PHYS_ADDR_MASK & !PatMemType::PAT_HUGE
+        } else {
+            Self::PHYS_ADDR_MASK
+        }
+    }
+
+    /// Returns the memory type of the entry that maps a page.
+    ///
+    /// `is_huge` indicates whether the entry maps a 2M or 1G page.
+    pub const fn mem_type(&self, is_huge: bool) -> PatMemType {
+        PatMemType::from_pte_bits(self.0, is_huge)
+    }
+
+    /// Sets the memory type of the entry that maps a page.
+    ///
+    /// `is_huge` indicates whether the entry maps a 2M or 1G page. Only the
+    /// types without the PAT bit are reported by [`flags`](GenericPTE::flags),
+    /// e.g., [`WriteProtected`](PatMemType::WriteProtected) as write-through.
+    /// The PAT bit is kept by [`set_flags`](GenericPTE::set_flags) unless a
+    /// different type is reported by the new flags.
+    pub const fn set_mem_type(&mut self, mem_type: PatMemType, is_huge: bool) {
+        self.0 = (self.0 & !PatMemType::pte_mask(is_huge)) | mem_type.pte_bits(is_huge);
+    }
 }
 
 impl GenericPTE for X64PTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
     fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
         let mut flags = PTF::from(flags);
         if is_huge {
             flags |= PTF::HUGE_PAGE;
         }
-        Self(flags.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+        Self(flags.bits() | (paddr.as_usize() as u64 & Self::addr_mask(is_huge)))
+    }
+
+    fn new_page_like(&self, paddr: PhysAddr, was_huge: bool, is_huge: bool) -> Self {
+        let mut pte = Self::new_page(paddr, self.flags(), is_huge);
+        pte.set_mem_type(self.mem_type(was_huge), is_huge);
+        pte
     }
 
     fn new_table(paddr: PhysAddr) -> Self {
@@ -97,7 +281,23 @@ impl GenericPTE for X64PTE {
         if is_huge {
             flags |= PTF::HUGE_PAGE;
         }
-        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits()
+        let mut bits = flags.bits();
+        // keep the PAT bit if the reported memory type is unchanged
+        let pcd_pwt = (PTF::NO_CACHE | PTF::WRITE_THROUGH).bits();
+        if flags.contains(PTF::PRESENT) && (self.0 ^ bits) & pcd_pwt == 0 {
+            bits |= self.0 & PatMemType::pat_bit(is_huge);
+        }
+        self.0 = (self.0 & Self::addr_mask(is_huge)) | bits
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(self.0, PTF::ACCESSED.bits(), PTF::DIRTY.bits())
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(PTF::ACCESSED.bits(), PTF::DIRTY.bits());
+        let old = crate::update_bits_u64(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(old, PTF::ACCESSED.bits(), PTF::DIRTY.bits())
     }
 
     fn bits(self) -> usize {
@@ -130,3 +330,323 @@ impl fmt::Debug for X64PTE {
             .finish()
     }
 }
+
+bitflags::bitflags! {
+    /// Intel EPT (Extended Page Table) entry flags.
+    #[derive(Debug, Clone, Copy)]
+    pub struct EPTFlags: u64 {
+        /// Read access.
+        const READ =                1 << 0;
+        /// Write access.
+        const WRITE =               1 << 1;
+        /// Execute access, or supervisor-mode execute access if mode-based
+        /// execute control (MBEC) is enabled.
+        const EXECUTE =             1 << 2;
+        /// EPT memory type, only for the entries that map pages.
+        const MEM_TYPE_MASK =       0b111 << 3;
+        /// Ignore the PAT memory type, only for the entries that map pages.
+        const IGNORE_PAT =          1 << 6;
+        /// The entry maps a 2M or 1G page.
+        const HUGE_PAGE =           1 << 7;
+        /// Set by the CPU when the entry is used for address translation, if
+        /// accessed and dirty flags are enabled in the EPTP.
+        const ACCESSED =            1 << 8;
+        /// Set by the CPU on a write to the mapped page, if accessed and dirty
+        /// flags are enabled in the EPTP.
+        const DIRTY =               1 << 9;
+        /// User-mode execute access, if mode-based execute control (MBEC) is
+        /// enabled.
+        const EXECUTE_FOR_USER =    1 << 10;
+    }
+}
+
+/// The EPT memory types.
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EPTMemType {
+    /// Uncacheable.
+    Uncached       = 0,
+    /// Write-combining.
+    WriteCombining = 1,
+    /// Write-through.
+    WriteThrough   = 4,
+    /// Write-protected.
+    WriteProtected = 5,
+    /// Write-back.
+    WriteBack      = 6,
+}
+
+impl EPTMemType {
+    /// Returns the type that the portable memory type is mapped to.
+    pub const fn from_mem_type(mem_type: MemType) -> Self {
+        match mem_type.fallback(MEM_TYPES) {
+            MemType::Normal => Self::WriteBack,
+            MemType::WriteThrough => Self::WriteThrough,
+            MemType::WriteCombining => Self::WriteCombining,
+            _ => Self::Uncached,
+        }
+    }
+
+    /// Returns the portable memory type of this type.
+    pub const fn mem_type(self) -> MemType {
+        match self {
+            Self::WriteBack => MemType::Normal,
+            Self::WriteThrough | Self::WriteProtected => MemType::WriteThrough,
+            Self::WriteCombining => MemType::WriteCombining,
+            Self::Uncached => MemType::Device,
+        }
+    }
+}
+
+impl EPTFlags {
+    /// Constructs the flags from the EPT memory type, leaving the other fields
+    /// empty.
+    pub const fn from_mem_type(mem_type: EPTMemType) -> Self {
+        Self::from_bits_retain((mem_type as u64) << 3)
+    }
+
+    /// Returns the EPT memory type field.
+    pub const fn mem_type(&self) -> Option<EPTMemType> {
+        Some(match (self.bits() & Self::MEM_TYPE_MASK.bits()) >> 3 {
+            0 => EPTMemType::Uncached,
+            1 => EPTMemType::WriteCombining,
+            4 => EPTMemType::WriteThrough,
+            5 => EPTMemType::WriteProtected,
+            6 => EPTMemType::WriteBack,
+            _ => return None,
+        })
+    }
+}
+
+impl From<EPTFlags> for MappingFlags {
+    fn from(f: EPTFlags) -> Self {
+        let mut ret = Self::empty();
+        if f.contains(EPTFlags::READ) {
+            ret |= Self::READ;
+        }
+        if f.contains(EPTFlags::WRITE) {
+            ret |= Self::WRITE;
+        }
+        if f.contains(EPTFlags::EXECUTE) {
+            ret |= Self::EXECUTE;
+        }
+        if f.contains(EPTFlags::EXECUTE_FOR_USER) {
+            ret |= Self::EXECUTE | Self::USER;
+        }
+        if !ret.is_empty()
+            && let Some(mem_type) = f.mem_type()
+        {
+            ret |= mem_type.mem_type().into();
+        }
+        ret
+    }
+}
+
+impl From<MappingFlags> for EPTFlags {
+    fn from(f: MappingFlags) -> Self {
+        if f.is_empty() {
+            return Self::empty();
+        }
+        let mut ret = Self::from_mem_type(EPTMemType::from_mem_type(f.mem_type()));
+        if f.contains(MappingFlags::READ) {
+            ret |= Self::READ;
+        }
+        if f.contains(MappingFlags::WRITE) {
+            ret |= Self::WRITE;
+        }
+        if f.contains(MappingFlags::EXECUTE) {
+            ret |= Self::EXECUTE;
+            if f.contains(MappingFlags::USER) {
+                ret |= Self::EXECUTE_FOR_USER;
+            }
+        }
+        ret
+    }
+}
+
+/// An Intel EPT (Extended Page Table) entry, which translates guest physical
+/// addresses to host physical addresses.
+///
+/// With mode-based execute control (MBEC), the `EXECUTE` mapping flag allows
+/// the supervisor-mode execution, and `EXECUTE | USER` also allows the
+/// user-mode execution. The memory types are mapped by
+/// [`EPTMemType::from_mem_type`]. The `GLOBAL` mapping flag is ignored, as
+/// the translations are tagged by the EPTP instead. The accessed and dirty
+/// state is only recorded if accessed and dirty flags are enabled in the EPTP.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct EptPTE(u64);
+
+impl EptPTE {
+    // bits 12..52
+    const PHYS_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    /// Creates an empty descriptor with all bits set to zero.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+}
+
+impl GenericPTE for EptPTE {
+    const MEM_TYPES: &'static [MemType] = MEM_TYPES;
+    const ACCESS_STATE: AccessState = AccessState::all();
+
+    fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        let mut flags = EPTFlags::from(flags);
+        if is_huge {
+            flags |= EPTFlags::HUGE_PAGE;
+        }
+        Self(flags.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn new_table(paddr: PhysAddr) -> Self {
+        let flags =
+            EPTFlags::READ | EPTFlags::WRITE | EPTFlags::EXECUTE | EPTFlags::EXECUTE_FOR_USER;
+        Self(flags.bits() | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK))
+    }
+
+    fn paddr(&self) -> PhysAddr {
+        PhysAddr::from((self.0 & Self::PHYS_ADDR_MASK) as usize)
+    }
+
+    fn flags(&self) -> MappingFlags {
+        EPTFlags::from_bits_truncate(self.0).into()
+    }
+
+    fn set_paddr(&mut self, paddr: PhysAddr) {
+        self.0 = (self.0 & !Self::PHYS_ADDR_MASK) | (paddr.as_usize() as u64 & Self::PHYS_ADDR_MASK)
+    }
+
+    fn set_flags(&mut self, flags: MappingFlags, is_huge: bool) {
+        let mut flags = EPTFlags::from(flags);
+        if is_huge {
+            flags |= EPTFlags::HUGE_PAGE;
+        }
+        self.0 = (self.0 & Self::PHYS_ADDR_MASK) | flags.bits()
+    }
+
+    fn access_state(&self) -> AccessState {
+        AccessState::from_pte_bits(self.0, EPTFlags::ACCESSED.bits(), EPTFlags::DIRTY.bits())
+    }
+
+    fn clear_access_state(&mut self, state: AccessState) -> AccessState {
+        let mask = state.pte_bits(EPTFlags::ACCESSED.bits(), EPTFlags::DIRTY.bits());
+        let old = crate::update_bits_u64(&mut self.0, |bits| bits & !mask);
+        AccessState::from_pte_bits(old, EPTFlags::ACCESSED.bits(), EPTFlags::DIRTY.bits())
+    }
+
+    fn bits(self) -> usize {
+        self.0 as usize
+    }
+
+    fn is_unused(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn is_present(&self) -> bool {
+        EPTFlags::from_bits_truncate(self.0).intersects(
+            EPTFlags::READ | EPTFlags::WRITE | EPTFlags::EXECUTE | EPTFlags::EXECUTE_FOR_USER,
+        )
+    }
+
+    fn is_huge(&self) -> bool {
+        EPTFlags::from_bits_truncate(self.0).contains(EPTFlags::HUGE_PAGE)
+    }
+
+    fn clear(&mut self) {
+        self.0 = 0
+    }
+}
+
+impl fmt::Debug for EptPTE {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut f = f.debug_struct("EptPTE");
+        f.field("raw", &self.0)
+            .field("paddr", &self.paddr())
+            .field("flags", &self.flags())
+            .field("mem_type", &EPTFlags::from_bits_truncate(self.0).mem_type())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pat_mem_type() {
+        assert_eq!(PatMemType::PAT_VALUE, 0x0007_0506_0001_0406);
+
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let mut pte = X64PTE::new_page(paddr, flags, false);
+        assert_eq!(pte.mem_type(false), PatMemType::WriteBack);
+        pte.set_mem_type(PatMemType::WriteCombining, false);
+        assert_eq!(pte.bits() & 0x98, 0x10); // PCD
+        assert_eq!(pte.mem_type(false), PatMemType::WriteCombining);
+        assert_eq!(pte.flags(), flags | MappingFlags::WRITE_COMBINE);
+        assert_eq!(pte.paddr(), paddr);
+
+        let paddr = PhysAddr::from(0x4020_0000);
+        let mut pte = X64PTE::new_page(paddr, flags | MappingFlags::GLOBAL, true);
+        assert_eq!(pte.bits() & 0x100, 0x100); // G
+        assert_eq!(pte.flags(), flags | MappingFlags::GLOBAL);
+        pte.set_mem_type(PatMemType::WriteProtected, true);
+        assert_eq!(pte.bits() & 0x1098, 0x1088); // PAT (bit 12), PWT, PS
+        assert_eq!(pte.mem_type(true), PatMemType::WriteProtected);
+        // reported as write-through, which keeps the PAT bit
+        let wt_flags = flags | MappingFlags::GLOBAL | MappingFlags::WRITE_THROUGH;
+        assert_eq!(pte.flags(), wt_flags);
+        pte.set_flags(wt_flags - MappingFlags::WRITE, true);
+        assert_eq!(pte.mem_type(true), PatMemType::WriteProtected);
+        let small = pte.new_page_like(paddr + 0x1000, true, false);
+        assert_eq!(small.mem_type(false), PatMemType::WriteProtected);
+        assert_eq!(small.paddr(), paddr + 0x1000);
+        pte.set_flags(flags | MappingFlags::UNCACHED, true);
+        assert_eq!(pte.mem_type(true), PatMemType::Uncached);
+        assert_eq!(pte.paddr(), paddr);
+        assert!(pte.is_huge());
+    }
+
+    #[test]
+    fn test_ept_descriptor() {
+        let paddr = PhysAddr::from(0x1_4020_0000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let pte = EptPTE::new_page(paddr, flags, true);
+
+        assert!(pte.is_present());
+        assert!(pte.is_huge());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+        assert_eq!(pte.bits(), 0x1_4020_00b3); // RW, WB, huge
+
+        let flags = MappingFlags::READ | MappingFlags::EXECUTE | MappingFlags::USER;
+        let pte = EptPTE::new_page(paddr, flags | MappingFlags::DEVICE, false);
+        assert_eq!(pte.flags(), flags | MappingFlags::DEVICE);
+        assert_eq!(pte.bits() & 0x7ff, 0x405); // RX, user-execute, UC
+    }
+
+    #[test]
+    fn test_access_state() {
+        let paddr = PhysAddr::from(0x4000_1000);
+        let flags = MappingFlags::READ | MappingFlags::WRITE;
+        let pte = X64PTE::new_page(paddr, flags, false);
+        assert_eq!(pte.access_state(), AccessState::empty());
+
+        // set by the CPU
+        let mut pte = X64PTE(pte.0 | (PTF::ACCESSED | PTF::DIRTY).bits());
+        assert_eq!(pte.access_state(), AccessState::all());
+        assert_eq!(
+            pte.clear_access_state(AccessState::DIRTY),
+            AccessState::all()
+        );
+        assert_eq!(pte.access_state(), AccessState::ACCESSED);
+        assert_eq!(
+            pte.clear_access_state(AccessState::all()),
+            AccessState::ACCESSED
+        );
+        assert_eq!(pte.access_state(), AccessState::empty());
+        assert_eq!(pte.paddr(), paddr);
+        assert_eq!(pte.flags(), flags);
+    }
+}
@@ -2,7 +2,12 @@
 #![cfg_attr(doc, feature(doc_cfg))]
 #![doc = include_str!("../README.md")]
 
-use core::fmt;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use memory_addr::PhysAddr;
 
@@ -26,6 +31,15 @@ bitflags::bitflags! {
         const DEVICE        = 1 << 4;
         /// The memory is uncached.
         const UNCACHED      = 1 << 5;
+        /// The memory is uncached, with the writes buffered and combined.
+        const WRITE_COMBINE = 1 << 6;
+        /// The memory is cached, with the writes also done to the memory.
+        const WRITE_THROUGH = 1 << 7;
+        /// The mapping is global, which is shared by all address spaces and
+        /// is not flushed on ASID or PCID switches.
+        const GLOBAL        = 1 << 8;
+        /// The memory is strongly-ordered device memory.
+        const STRONGLY_ORDERED = 1 << 9;
     }
 }
 
@@ -35,25 +49,366 @@ impl fmt::Debug for MappingFlags {
     }
 }
 
+impl MappingFlags {
+    /// The flags that select the memory type, see [`MemType`].
+    pub const MEM_TYPE_MASK: Self = Self::STRONGLY_ORDERED
+        .union(Self::DEVICE)
+        .union(Self::UNCACHED)
+        .union(Self::WRITE_COMBINE)
+        .union(Self::WRITE_THROUGH);
+
+    /// Returns the memory type selected by the flags.
+    ///
+    /// If more than one memory type flag is set, the strongest one is used.
+    pub const fn mem_type(self) -> MemType {
+        if self.contains(Self::STRONGLY_ORDERED) {
+            MemType::StronglyOrdered
+        } else if self.contains(Self::DEVICE) {
+            MemType::Device
+        } else if self.contains(Self::UNCACHED) {
+            MemType::Uncached
+        } else if self.contains(Self::WRITE_COMBINE) {
+            MemType::WriteCombining
+        } else if self.contains(Self::WRITE_THROUGH) {
+            MemType::WriteThrough
+        } else {
+            MemType::Normal
+        }
+    }
+
+    /// Returns the flags with the memory type replaced by `mem_type`.
+    pub const fn with_mem_type(self, mem_type: MemType) -> Self {
+        self.difference(Self::MEM_TYPE_MASK)
+            .union(mem_type.mapping_flags())
+    }
+}
+
+/// The portable memory types of mappings, which are selected by
+/// [`MappingFlags::mem_type`].
+///
+/// The types are ordered from the weakest to the strongest, in terms of
+/// caching and memory ordering. Each page table entry type declares the types
+/// it honours in [`GenericPTE::MEM_TYPES`], and the others fall back to one of
+/// them by [`fallback`](Self::fallback).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MemType {
+    /// Normal write-back cacheable memory, the default.
+    Normal,
+    /// Normal write-through cacheable memory.
+    WriteThrough,
+    /// Weakly-ordered non-cacheable memory, with the writes buffered and
+    /// combined, such as frame buffers.
+    WriteCombining,
+    /// Normal non-cacheable memory.
+    Uncached,
+    /// Device memory, such as MMIO registers.
+    Device,
+    /// Strongly-ordered device memory, without any gathering, reordering or
+    /// early write acknowledgement.
+    StronglyOrdered,
+}
+
+impl MemType {
+    /// Returns the mapping flags that select this memory type.
+    pub const fn mapping_flags(self) -> MappingFlags {
+        match self {
+            Self::Normal => MappingFlags::empty(),
+            Self::WriteThrough => MappingFlags::WRITE_THROUGH,
+            Self::WriteCombining => MappingFlags::WRITE_COMBINE,
+            Self::Uncached => MappingFlags::UNCACHED,
+            Self::Device => MappingFlags::DEVICE,
+            Self::StronglyOrdered => MappingFlags::STRONGLY_ORDERED,
+        }
+    }
+
+    /// Returns the memory type in `honoured` that this type falls back to.
+    ///
+    /// It is this type if honoured, or the weakest honoured type that is
+    /// stronger than this type, or the strongest honoured type if there is
+    /// none, e.g., [`StronglyOrdered`](Self::StronglyOrdered) falls back to
+    /// [`Device`](Self::Device) if it is the strongest one. An empty
+    /// `honoured` means that all types are [`Normal`](Self::Normal).
+    pub const fn fallback(self, honoured: &[MemType]) -> MemType {
+        let mut stronger: Option<MemType> = None;
+        let mut strongest = MemType::Normal;
+        let mut i = 0;
+        while i < honoured.len() {
+            let ty = honoured[i];
+            if ty as u8 >= self as u8 {
+                match stronger {
+                    Some(s) if s as u8 <= ty as u8 => {}
+                    _ => stronger = Some(ty),
+                }
+            }
+            if ty as u8 > strongest as u8 {
+                strongest = ty;
+            }
+            i += 1;
+        }
+        match stronger {
+            Some(ty) => ty,
+            None => strongest,
+        }
+    }
+}
+
+impl From<MemType> for MappingFlags {
+    fn from(mem_type: MemType) -> Self {
+        mem_type.mapping_flags()
+    }
+}
+
+bitflags::bitflags! {
+    /// The accessed and dirty state of a leaf page table entry, which is
+    /// recorded by the hardware.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AccessState: u8 {
+        /// The page has been accessed since the state was last cleared.
+        const ACCESSED = 1 << 0;
+        /// The page has been written since the state was last cleared.
+        const DIRTY    = 1 << 1;
+    }
+}
+
+impl AccessState {
+    /// Decodes the state from the raw bits of an entry, where the hardware
+    /// sets the `accessed` and `dirty` bits.
+    #[allow(dead_code)] // not used by all architectures
+    const fn from_pte_bits(bits: u64, accessed: u64, dirty: u64) -> Self {
+        let mut state = Self::empty();
+        if bits & accessed != 0 {
+            state = state.union(Self::ACCESSED);
+        }
+        if bits & dirty != 0 {
+            state = state.union(Self::DIRTY);
+        }
+        state
+    }
+
+    /// Encodes the state into the `accessed` and `dirty` bits of an entry.
+    #[allow(dead_code)] // not used by all architectures
+    const fn pte_bits(self, accessed: u64, dirty: u64) -> u64 {
+        let mut bits = 0;
+        if self.contains(Self::ACCESSED) {
+            bits |= accessed;
+        }
+        if self.contains(Self::DIRTY) {
+            bits |= dirty;
+        }
+        bits
+    }
+}
+
+/// Atomically updates the raw bits of an entry with `f`, which may be set by
+/// the hardware at the same time (e.g., the accessed and dirty bits), returns
+/// the old bits.
+///
+/// The entries that are not aligned, which can not be in a page table, are
+/// updated non-atomically.
+#[allow(dead_code)] // not used by all architectures
+fn update_bits_u32(bits: &mut u32, f: impl Fn(u32) -> u32) -> u32 {
+    let ptr: *mut u32 = bits;
+    if ptr.cast::<AtomicU32>().is_aligned() {
+        // SAFETY: the pointer is valid and aligned, and the entry is not
+        // accessed non-atomically while the `AtomicU32` is alive.
+        let atomic = unsafe { AtomicU32::from_ptr(ptr) };
+        return atomic
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| Some(f(old)))
+            .unwrap_or_else(|old| old);
+    }
+    let old = *bits;
+    *bits = f(old);
+    old
+}
+
+/// Atomically updates the raw bits of an entry with `f`, like
+/// [`update_bits_u32`].
+#[allow(dead_code)] // not used by all architectures
+fn update_bits_u64(bits: &mut u64, f: impl Fn(u64) -> u64) -> u64 {
+    #[cfg(target_has_atomic = "64")]
+    {
+        let ptr: *mut u64 = bits;
+        if ptr.cast::<AtomicU64>().is_aligned() {
+            // SAFETY: the pointer is valid and aligned, and the entry is not
+            // accessed non-atomically while the `AtomicU64` is alive.
+            let atomic = unsafe { AtomicU64::from_ptr(ptr) };
+            return atomic
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| Some(f(old)))
+                .unwrap_or_else(|old| old);
+        }
+    }
+    let old = *bits;
+    *bits = f(old);
+    old
+}
+
+/// A physical address that may be wider than `usize`.
+///
+/// Some 32-bit architectures can translate to physical addresses above 4GB,
+/// such as ARMv7 LPAE with 40-bit output addresses, which do not fit in a
+/// [`PhysAddr`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysAddr64(u64);
+
+impl PhysAddr64 {
+    /// Converts a `u64` to a physical address.
+    pub const fn from_u64(addr: u64) -> Self {
+        Self(addr)
+    }
+
+    /// Converts the address to a `u64`.
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Aligns the address downwards to the given alignment, which must be a
+    /// power of two.
+    pub const fn align_down(self, align: usize) -> Self {
+        Self(self.0 & !(align as u64 - 1))
+    }
+
+    /// Checks whether the address is aligned to the given alignment, which
+    /// must be a power of two.
+    pub const fn is_aligned(self, align: usize) -> bool {
+        self.0 & (align as u64 - 1) == 0
+    }
+
+    /// Adds an offset to the address.
+    pub const fn add(self, offset: usize) -> Self {
+        Self(self.0 + offset as u64)
+    }
+}
+
+impl From<PhysAddr> for PhysAddr64 {
+    fn from(paddr: PhysAddr) -> Self {
+        Self(paddr.as_usize() as u64)
+    }
+}
+
+impl From<u64> for PhysAddr64 {
+    fn from(addr: u64) -> Self {
+        Self(addr)
+    }
+}
+
+impl TryFrom<PhysAddr64> for PhysAddr {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(paddr: PhysAddr64) -> Result<Self, Self::Error> {
+        usize::try_from(paddr.0).map(PhysAddr::from)
+    }
+}
+
+impl fmt::Debug for PhysAddr64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PA:{:#x}", self.0)
+    }
+}
+
+impl fmt::LowerHex for PhysAddr64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
 /// A generic page table entry.
 ///
 /// All architecture-specific page table entry types implement this trait.
 pub trait GenericPTE: fmt::Debug + Clone + Copy + Sync + Send + Sized {
+    /// The memory types honoured by this entry, the other types fall back to
+    /// them by [`MemType::fallback`].
+    ///
+    /// [`flags`](Self::flags) returns the honoured memory type.
+    const MEM_TYPES: &'static [MemType] = &[MemType::Normal];
+
+    /// The accessed and dirty state recorded by the hardware in this entry,
+    /// see [`access_state`](Self::access_state).
+    const ACCESS_STATE: AccessState = AccessState::empty();
+
     /// Creates a page table entry point to a terminate page or block.
     fn new_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self;
     /// Creates a page table entry point to a next level page table.
     fn new_table(paddr: PhysAddr) -> Self;
+    /// Creates an entry of the root page table point to a next level page
+    /// table.
+    ///
+    /// Only needed if the root table entries have a different format from
+    /// [`new_table`](Self::new_table), such as the PDPTEs of x86 PAE paging.
+    fn new_root_table(paddr: PhysAddr) -> Self {
+        Self::new_table(paddr)
+    }
+
+    /// Creates one of the replicated entries of a contiguous page, which is
+    /// mapped by a run of adjacent entries in the same table.
+    ///
+    /// `paddr` is the address of the part mapped by this entry. Only needed if
+    /// the hardware supports such pages, such as the ARMv7-A supersections and
+    /// large pages.
+    fn new_contiguous_page(paddr: PhysAddr, flags: MappingFlags, is_huge: bool) -> Self {
+        Self::new_page(paddr, flags, is_huge)
+    }
+    /// Creates a page table entry point to a page with the attributes of this
+    /// leaf entry, including those not reported by [`flags`](Self::flags),
+    /// such as the x86 PAT memory types. It is used to split, collapse and
+    /// remap huge pages.
+    ///
+    /// `was_huge` and `is_huge` indicate whether this entry and the new entry
+    /// are above the last level respectively.
+    fn new_page_like(&self, paddr: PhysAddr, was_huge: bool, is_huge: bool) -> Self {
+        Self::new_page(paddr, self.leaf_flags(was_huge), is_huge)
+    }
+    /// Creates a page table entry point to the page at `offset` from the page
+    /// mapped by this leaf entry, with its attributes like
+    /// [`new_page_like`](Self::new_page_like).
+    ///
+    /// Only needed if the physical address may be wider than `usize`, such as
+    /// the entries of [`GenericLargePTE`].
+    fn new_page_at(&self, offset: usize, was_huge: bool, is_huge: bool) -> Self {
+        self.new_page_like(self.leaf_paddr(was_huge) + offset, was_huge, is_huge)
+    }
 
     /// Returns the physical address mapped by this entry.
     fn paddr(&self) -> PhysAddr;
     /// Returns the flags of this entry.
     fn flags(&self) -> MappingFlags;
+    /// Returns the physical address mapped by this leaf entry.
+    ///
+    /// `is_huge` indicates whether the entry is above the last level. Only
+    /// needed if the leaf entries of different levels can not be told apart
+    /// by themselves, such as the ARMv7-A sections and small pages.
+    fn leaf_paddr(&self, is_huge: bool) -> PhysAddr {
+        let _ = is_huge;
+        self.paddr()
+    }
+    /// Returns the flags of this leaf entry, like
+    /// [`leaf_paddr`](Self::leaf_paddr).
+    fn leaf_flags(&self, is_huge: bool) -> MappingFlags {
+        let _ = is_huge;
+        self.flags()
+    }
 
     /// Set mapped physical address of the entry.
     fn set_paddr(&mut self, paddr: PhysAddr);
     /// Set flags of the entry.
     fn set_flags(&mut self, flags: MappingFlags, is_huge: bool);
 
+    /// Returns the accessed and dirty state of this leaf entry.
+    ///
+    /// Only the state in [`ACCESS_STATE`](Self::ACCESS_STATE) is recorded by
+    /// the hardware, the other state is never reported.
+    fn access_state(&self) -> AccessState {
+        AccessState::empty()
+    }
+    /// Atomically clears the given accessed and dirty `state` of this leaf
+    /// entry, returns the state before clearing.
+    ///
+    /// The TLB entries of the page must be flushed afterwards, so that the
+    /// hardware records the state again.
+    fn clear_access_state(&mut self, _state: AccessState) -> AccessState {
+        self.access_state()
+    }
+
     /// Returns the raw bits of this entry.
     fn bits(self) -> usize;
     /// Returns whether this entry is zero.
@@ -63,6 +418,33 @@ pub trait GenericPTE: fmt::Debug + Clone + Copy + Sync + Send + Sized {
     /// For non-last level translation, returns whether this entry maps to a
     /// huge frame.
     fn is_huge(&self) -> bool;
+    /// For leaf entries, returns whether this entry is one of the replicated
+    /// entries of a contiguous page.
+    ///
+    /// `is_huge` indicates whether the entry is above the last level.
+    fn is_contiguous(&self, is_huge: bool) -> bool {
+        let _ = is_huge;
+        false
+    }
     /// Set this entry to zero.
     fn clear(&mut self);
 }
+
+/// A page table entry whose output address may be wider than `usize`.
+///
+/// The [`GenericPTE`] methods only handle the addresses that fit in a
+/// [`PhysAddr`], while the methods of this trait handle the full output
+/// address with a [`PhysAddr64`].
+pub trait GenericLargePTE: GenericPTE {
+    /// The maximum number of bits of the physical addresses that can be mapped
+    /// by this entry.
+    const PA_MAX_BITS: usize;
+
+    /// Creates a page table entry point to a terminate page or block at a
+    /// possibly large physical address.
+    fn new_large_page(paddr: PhysAddr64, flags: MappingFlags, is_huge: bool) -> Self;
+    /// Returns the full physical address mapped by this entry.
+    fn large_paddr(&self) -> PhysAddr64;
+    /// Set the full mapped physical address of the entry.
+    fn set_large_paddr(&mut self, paddr: PhysAddr64);
+}
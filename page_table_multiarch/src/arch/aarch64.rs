@@ -1,25 +1,166 @@
 //! AArch64 specific page table structures.
 
-use core::arch::asm;
+use core::{arch::asm, marker::PhantomData};
 
-use memory_addr::VirtAddr;
-use page_table_entry::aarch64::A64PTE;
+use memory_addr::{PhysAddr, VirtAddr};
+use page_table_entry::aarch64::{A64Lpa2PTE, A64LpaPTE, A64PTE, A64S2PTE};
 
-use crate::{PageTable64, PageTable64Cursor, PagingMetaData};
+use crate::{GuestPhysAddr, PageSize, PageTable64, PageTable64Cursor, PagingMetaData};
+
+/// Returns the encoding of the physical address size in `TCR_EL1.IPS` and
+/// `VTCR_EL2.PS`.
+const fn pa_size_bits(pa_bits: usize) -> u64 {
+    match pa_bits {
+        32 => 0b000,
+        36 => 0b001,
+        40 => 0b010,
+        42 => 0b011,
+        44 => 0b100,
+        48 => 0b101,
+        _ => 0b110,
+    }
+}
+
+/// A translation granule of AArch64 page tables.
+///
+/// It determines the base page size, the number of entries in each table, and
+/// the sizes of blocks that can be mapped.
+pub trait A64Granule: Sync + Send {
+    /// The number of bits of the granule size.
+    const PAGE_SHIFT: usize;
+    /// The page and block sizes supported by the granule, in descending order.
+    ///
+    /// It includes the sizes of contiguous runs of pages or blocks, which are
+    /// mapped by replicated entries with the contiguous hint.
+    const PAGE_SIZES: &'static [PageSize];
+    /// The encoding of the granule in the `TCR_EL1.TG0` field.
+    const TG0: u64;
+    /// The encoding of the granule in the `TCR_EL1.TG1` field.
+    const TG1: u64;
+}
+
+/// The 4K translation granule, with 2M and 1G blocks.
+///
+/// The contiguous hint maps 64K (16 pages) and 32M (16 blocks of 2M).
+pub struct Granule4K;
+
+/// The 16K translation granule, with 32M blocks.
+///
+/// The contiguous hint maps 2M (128 pages) and 1G (32 blocks of 32M).
+pub struct Granule16K;
+
+/// The 64K translation granule, with 512M blocks.
+///
+/// The contiguous hint maps 2M (32 pages).
+pub struct Granule64K;
+
+impl A64Granule for Granule4K {
+    const PAGE_SHIFT: usize = 12;
+    const PAGE_SIZES: &'static [PageSize] = &[
+        PageSize::Size1G,
+        PageSize::Size32M,
+        PageSize::Size2M,
+        PageSize::Size64K,
+        PageSize::Size4K,
+    ];
+    const TG0: u64 = 0b00;
+    const TG1: u64 = 0b10;
+}
+
+impl A64Granule for Granule16K {
+    const PAGE_SHIFT: usize = 14;
+    // 64G blocks require `FEAT_LPA2`
+    const PAGE_SIZES: &'static [PageSize] = &[
+        PageSize::Size1G,
+        PageSize::Size32M,
+        PageSize::Size2M,
+        PageSize::Size16K,
+    ];
+    const TG0: u64 = 0b10;
+    const TG1: u64 = 0b01;
+}
+
+impl A64Granule for Granule64K {
+    const PAGE_SHIFT: usize = 16;
+    // 4T blocks require `FEAT_LPA`, 16G contiguous blocks are not supported
+    const PAGE_SIZES: &'static [PageSize] =
+        &[PageSize::Size512M, PageSize::Size2M, PageSize::Size64K];
+    const TG0: u64 = 0b01;
+    const TG1: u64 = 0b11;
+}
 
 /// Metadata of AArch64 page tables.
-pub struct A64PagingMetaData;
+///
+/// The translation granule is selected by `G`, which is [`Granule4K`] by
+/// default. `VA_BITS` is the size of the virtual address space (i.e.,
+/// `64 - T0SZ` or `64 - T1SZ`), which is 48 by default.
+///
+/// The number of levels is the least one that can translate `VA_BITS` with
+/// the granule, so the translation may start at level 0, 1 or 2 (see
+/// [`START_LEVEL`](Self::START_LEVEL)), and the root table may have fewer
+/// entries than the other tables. For example, `A64PagingMetaData<Granule4K,
+/// 39>` describes a 3-level table starting at level 1.
+///
+/// `PA_BITS` is the size of the physical address space, which is either 48
+/// (by default) or 52. 52-bit physical addresses require a different
+/// descriptor encoding, [`A64LpaPTE`] for the 64K granule (`FEAT_LPA`), and
+/// [`A64Lpa2PTE`] for the 4K and 16K granules (`FEAT_LPA2`). The larger blocks
+/// introduced by these features are not used.
+///
+/// 52-bit virtual addresses are supported with the 64K granule (`FEAT_LVA`),
+/// or with 52-bit physical addresses (`FEAT_LPA2`), where the 4K granule
+/// needs 5 levels starting at level -1.
+pub struct A64PagingMetaData<
+    G: A64Granule = Granule4K,
+    const VA_BITS: usize = 48,
+    const PA_BITS: usize = 48,
+>(PhantomData<G>);
 
-impl PagingMetaData for A64PagingMetaData {
-    const LEVELS: usize = 4;
-    const PA_MAX_BITS: usize = 48;
-    const VA_MAX_BITS: usize = 48;
+impl<G: A64Granule, const VA_BITS: usize, const PA_BITS: usize>
+    A64PagingMetaData<G, VA_BITS, PA_BITS>
+{
+    /// The level of the root table in the VMSAv8-64 translation process.
+    pub const START_LEVEL: isize = 4 - <Self as PagingMetaData>::LEVELS as isize;
+
+    /// The fields of `TCR_EL1` that match this page table layout, for both
+    /// `TTBR0_EL1` and `TTBR1_EL1`: `T0SZ`, `TG0`, `T1SZ`, `TG1`, `IPS`, and
+    /// `DS` for [`A64Lpa2PTE`].
+    ///
+    /// The other fields, such as the cacheability and shareability of table
+    /// walks, should be set by the caller.
+    pub const TCR_EL1_VALUE: u64 = {
+        let ds = (PA_BITS == 52 && G::PAGE_SHIFT != 16) as u64;
+        let txsz = (64 - VA_BITS) as u64;
+        let ips = pa_size_bits(PA_BITS);
+        txsz | (G::TG0 << 14) | (txsz << 16) | (G::TG1 << 30) | (ips << 32) | (ds << 59)
+    };
+}
+
+impl<G: A64Granule, const VA_BITS: usize, const PA_BITS: usize> PagingMetaData
+    for A64PagingMetaData<G, VA_BITS, PA_BITS>
+{
+    const LEVELS: usize = {
+        assert!(VA_BITS >= 25 && VA_BITS <= 52, "unsupported VA size");
+        assert!(PA_BITS == 48 || PA_BITS == 52, "unsupported PA size");
+        assert!(
+            VA_BITS <= 48 || G::PAGE_SHIFT == 16 || PA_BITS == 52,
+            "52-bit VA requires the 64K granule or FEAT_LPA2"
+        );
+        (VA_BITS - G::PAGE_SHIFT).div_ceil(Self::INDEX_BITS)
+    };
+    const PA_MAX_BITS: usize = PA_BITS;
+    const VA_MAX_BITS: usize = VA_BITS;
+    const PAGE_SHIFT: usize = G::PAGE_SHIFT;
+    // each table is a granule of 8-byte descriptors
+    const INDEX_BITS: usize = G::PAGE_SHIFT - 3;
+    const PAGE_SIZES: &'static [PageSize] = G::PAGE_SIZES;
 
     type VirtAddr = VirtAddr;
 
     fn vaddr_is_valid(vaddr: usize) -> bool {
-        let top_bits = vaddr >> Self::VA_MAX_BITS;
-        top_bits == 0 || top_bits == 0xffff
+        // the upper range is translated by `TTBR1_EL1`
+        let top_bits = vaddr >> VA_BITS;
+        top_bits == 0 || top_bits == usize::MAX >> VA_BITS
     }
 
     #[inline]
@@ -37,7 +178,138 @@ impl PagingMetaData for A64PagingMetaData {
     }
 }
 
+/// Metadata of AArch64 stage 2 translation tables, which translate the
+/// intermediate physical addresses (IPA) of guests to physical addresses.
+///
+/// The translation granule is selected by `G`, which is [`Granule4K`] by
+/// default. `IPA_BITS` is the size of the IPA space (i.e.,
+/// `64 - VTCR_EL2.T0SZ`), which is 40 by default.
+///
+/// The number of levels is the least one with which the root table fits in 16
+/// concatenated tables. For example, with the 4K granule and 40-bit IPA, the
+/// translation starts at level 1 with 2 concatenated tables (8K in size).
+///
+/// The TLB maintenance applies to the current VMID, so `VTTBR_EL2` must be
+/// set to this table when the TLB is flushed.
+pub struct A64Stage2PagingMetaData<G: A64Granule = Granule4K, const IPA_BITS: usize = 40>(
+    PhantomData<G>,
+);
+
+impl<G: A64Granule, const IPA_BITS: usize> A64Stage2PagingMetaData<G, IPA_BITS> {
+    /// The level of the root table in the VMSAv8-64 translation process.
+    pub const START_LEVEL: usize = 4 - <Self as PagingMetaData>::LEVELS;
+
+    /// The fields of `VTCR_EL2` that match this page table layout: `T0SZ`,
+    /// `SL0`, `TG0` and `PS`.
+    ///
+    /// The other fields, such as the cacheability and shareability of table
+    /// walks, should be set by the caller.
+    pub const VTCR_EL2_VALUE: u64 = {
+        let t0sz = (64 - IPA_BITS) as u64;
+        // SL0 counts down from level 2 for the 4K granule, and from level 3
+        // for the 16K and 64K granules
+        let sl0_base: u64 = if G::PAGE_SHIFT == 12 { 2 } else { 3 };
+        let sl0 = sl0_base - Self::START_LEVEL as u64;
+        let ps = pa_size_bits(<Self as PagingMetaData>::PA_MAX_BITS);
+        t0sz | (sl0 << 6) | (G::TG0 << 14) | (ps << 16)
+    };
+
+    /// Returns the value of `VTTBR_EL2` to use the stage 2 translation table
+    /// at `root_paddr` with the given `vmid`.
+    pub const fn vttbr_el2(root_paddr: PhysAddr, vmid: u16) -> u64 {
+        root_paddr.as_usize() as u64 | ((vmid as u64) << 48)
+    }
+}
+
+impl<G: A64Granule, const IPA_BITS: usize> PagingMetaData for A64Stage2PagingMetaData<G, IPA_BITS> {
+    const LEVELS: usize = {
+        assert!(IPA_BITS >= 32 && IPA_BITS <= 48, "unsupported IPA size");
+        // up to 16 (2^4) tables can be concatenated at the start level
+        (IPA_BITS - G::PAGE_SHIFT - 4).div_ceil(Self::INDEX_BITS)
+    };
+    const PA_MAX_BITS: usize = 48;
+    const VA_MAX_BITS: usize = IPA_BITS;
+    const PAGE_SHIFT: usize = G::PAGE_SHIFT;
+    const INDEX_BITS: usize = G::PAGE_SHIFT - 3;
+    const PAGE_SIZES: &'static [PageSize] = G::PAGE_SIZES;
+
+    type VirtAddr = GuestPhysAddr;
+
+    fn vaddr_is_valid(vaddr: usize) -> bool {
+        vaddr >> IPA_BITS == 0
+    }
+
+    #[inline]
+    fn flush_tlb(gpa: Option<GuestPhysAddr>) {
+        unsafe {
+            if let Some(gpa) = gpa {
+                // TLB Invalidate by IPA, Stage 2, EL1, Inner Shareable. The
+                // combined stage 1 and 2 entries are not tagged by IPA, so
+                // they have to be invalidated as well.
+                const IPA_MASK: usize = (1 << 40) - 1; // IPA[51:12] => bits[39:0]
+                asm!(
+                    "tlbi ipas2e1is, {}; dsb ish; tlbi vmalle1is; dsb ish; isb",
+                    in(reg) ((gpa.as_usize() >> 12) & IPA_MASK)
+                )
+            } else {
+                // TLB Invalidate by VMID, All at stage 1 and 2, EL1, Inner Shareable
+                asm!("tlbi vmalls12e1is; dsb ish; isb")
+            }
+        }
+    }
+}
+
 /// AArch64 VMSAv8-64 translation table.
 pub type A64PageTable<H> = PageTable64<A64PagingMetaData, A64PTE, H>;
 /// AArch64 VMSAv8-64 translation table cursor.
 pub type A64PageTableCursor<'a, H> = PageTable64Cursor<'a, A64PagingMetaData, A64PTE, H>;
+
+/// AArch64 VMSAv8-64 translation table with the 16K granule (4 levels).
+pub type A64PageTable16K<H> = PageTable64<A64PagingMetaData<Granule16K>, A64PTE, H>;
+/// AArch64 VMSAv8-64 translation table cursor with the 16K granule.
+pub type A64PageTable16KCursor<'a, H> =
+    PageTable64Cursor<'a, A64PagingMetaData<Granule16K>, A64PTE, H>;
+
+/// AArch64 VMSAv8-64 translation table with the 64K granule (3 levels).
+pub type A64PageTable64K<H> = PageTable64<A64PagingMetaData<Granule64K>, A64PTE, H>;
+/// AArch64 VMSAv8-64 translation table cursor with the 64K granule.
+pub type A64PageTable64KCursor<'a, H> =
+    PageTable64Cursor<'a, A64PagingMetaData<Granule64K>, A64PTE, H>;
+
+/// AArch64 VMSAv8-64 translation table with 52-bit virtual and physical
+/// addresses (`FEAT_LPA2`), with the 4K granule (5 levels).
+pub type A64Lpa2PageTable<H> = PageTable64<A64PagingMetaData<Granule4K, 52, 52>, A64Lpa2PTE, H>;
+/// AArch64 VMSAv8-64 translation table cursor with 52-bit virtual and physical
+/// addresses, with the 4K granule.
+pub type A64Lpa2PageTableCursor<'a, H> =
+    PageTable64Cursor<'a, A64PagingMetaData<Granule4K, 52, 52>, A64Lpa2PTE, H>;
+
+/// AArch64 VMSAv8-64 translation table with 52-bit virtual and physical
+/// addresses (`FEAT_LPA2`), with the 16K granule (4 levels).
+pub type A64Lpa2PageTable16K<H> = PageTable64<A64PagingMetaData<Granule16K, 52, 52>, A64Lpa2PTE, H>;
+/// AArch64 VMSAv8-64 translation table cursor with 52-bit virtual and physical
+/// addresses, with the 16K granule.
+pub type A64Lpa2PageTable16KCursor<'a, H> =
+    PageTable64Cursor<'a, A64PagingMetaData<Granule16K, 52, 52>, A64Lpa2PTE, H>;
+
+/// AArch64 VMSAv8-64 translation table with 52-bit virtual and physical
+/// addresses (`FEAT_LVA` and `FEAT_LPA`), with the 64K granule (3 levels).
+pub type A64LpaPageTable64K<H> = PageTable64<A64PagingMetaData<Granule64K, 52, 52>, A64LpaPTE, H>;
+/// AArch64 VMSAv8-64 translation table cursor with 52-bit virtual and physical
+/// addresses, with the 64K granule.
+pub type A64LpaPageTable64KCursor<'a, H> =
+    PageTable64Cursor<'a, A64PagingMetaData<Granule64K, 52, 52>, A64LpaPTE, H>;
+
+/// AArch64 VMSAv8-64 stage 2 translation table.
+pub type A64Stage2PageTable<H> = PageTable64<A64Stage2PagingMetaData, A64S2PTE<false>, H>;
+/// AArch64 VMSAv8-64 stage 2 translation table cursor.
+pub type A64Stage2PageTableCursor<'a, H> =
+    PageTable64Cursor<'a, A64Stage2PagingMetaData, A64S2PTE<false>, H>;
+
+/// AArch64 VMSAv8-64 stage 2 translation table, whose memory types override
+/// the stage 1 ones (`HCR_EL2.FWB` is set).
+pub type A64Stage2FwbPageTable<H> = PageTable64<A64Stage2PagingMetaData, A64S2PTE<true>, H>;
+/// AArch64 VMSAv8-64 stage 2 translation table cursor, whose memory types
+/// override the stage 1 ones.
+pub type A64Stage2FwbPageTableCursor<'a, H> =
+    PageTable64Cursor<'a, A64Stage2PagingMetaData, A64S2PTE<true>, H>;
@@ -2,9 +2,33 @@
 
 use core::arch::asm;
 
-use page_table_entry::arm::A32PTE;
+use page_table_entry::arm::{A32LpaePTE, A32PTE};
 
-use crate::{PageTable32, PageTable32Cursor, PagingMetaData};
+use crate::{PageSize, PageTable32, PageTable32Cursor, PagingMetaData};
+
+#[inline]
+fn flush_tlb(vaddr: Option<memory_addr::VirtAddr>) {
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            // Invalidate unified TLB entries by MVA, all ASIDs
+            asm!(
+                "mcr p15, 0, {0}, c8, c7, 3", // TLBIMVAA
+                in(reg) vaddr.as_usize(),
+            );
+        } else {
+            // Invalidate entire unified TLB
+            let zero: usize = 0;
+            asm!(
+                "mcr p15, 0, {0}, c8, c7, 0", // TLBIALL
+                in(reg) zero,
+            );
+        }
+        // Data Synchronization Barrier
+        asm!("dsb");
+        // Instruction Synchronization Barrier
+        asm!("isb");
+    }
+}
 
 /// Metadata of ARMv7-A page tables.
 pub struct A32PagingMetaData;
@@ -13,6 +37,14 @@ impl PagingMetaData for A32PagingMetaData {
     const LEVELS: usize = 2; // ARMv7-A uses 2-level page tables
     const PA_MAX_BITS: usize = 32;
     const VA_MAX_BITS: usize = 32;
+    const INDEX_BITS: usize = 8; // L2 has 256 entries, L1 uses the remaining 12 bits
+    // Supersections and large pages are replicated in 16 entries
+    const PAGE_SIZES: &'static [PageSize] = &[
+        PageSize::Size16M,
+        PageSize::Size1M,
+        PageSize::Size64K,
+        PageSize::Size4K,
+    ];
     type VirtAddr = memory_addr::VirtAddr;
 
     fn vaddr_is_valid(_vaddr: usize) -> bool {
@@ -23,26 +55,47 @@ impl PagingMetaData for A32PagingMetaData {
 
     #[inline]
     fn flush_tlb(vaddr: Option<memory_addr::VirtAddr>) {
-        unsafe {
-            if let Some(vaddr) = vaddr {
-                // Invalidate unified TLB entry by MVA
-                asm!(
-                    "mcr p15, 0, {0}, c8, c7, 1", // TLBIMVA
-                    in(reg) vaddr.as_usize(),
-                );
-            } else {
-                // Invalidate entire unified TLB
-                let zero: usize = 0;
-                asm!(
-                    "mcr p15, 0, {0}, c8, c7, 0", // TLBIALL
-                    in(reg) zero,
-                );
-            }
-            // Data Synchronization Barrier
-            asm!("dsb");
-            // Instruction Synchronization Barrier
-            asm!("isb");
-        }
+        flush_tlb(vaddr)
+    }
+}
+
+/// Metadata of ARMv7-A page tables with the Long-descriptor format (LPAE).
+///
+/// `TTBR0` translates the whole 32-bit address space, the system must set
+/// `TTBCR` to [`TTBCR_VALUE`](Self::TTBCR_VALUE), and `MAIR0`/`MAIR1` to
+/// [`MAIR0_VALUE`]/[`MAIR1_VALUE`].
+///
+/// [`MAIR0_VALUE`]: page_table_entry::arm::LpaeMemAttr::MAIR0_VALUE
+/// [`MAIR1_VALUE`]: page_table_entry::arm::LpaeMemAttr::MAIR1_VALUE
+pub struct A32LpaePagingMetaData;
+
+impl A32LpaePagingMetaData {
+    /// The `TTBCR` value for this page table:
+    ///
+    /// - EAE = 1: use the Long-descriptor format.
+    /// - T0SZ = 0: `TTBR0` translates all 32-bit addresses, so the first level
+    ///   table has 4 entries.
+    /// - IRGN0/ORGN0 = 0b01, SH0 = 0b11: table walks are Inner Shareable,
+    ///   Write-Back Write-Allocate cacheable.
+    pub const TTBCR_VALUE: u32 = (1 << 31) | (0b11 << 12) | (0b01 << 10) | (0b01 << 8);
+}
+
+impl PagingMetaData for A32LpaePagingMetaData {
+    const LEVELS: usize = 3;
+    // 40 bits in hardware through the `*_large` methods, limited by `usize`
+    const PA_MAX_BITS: usize = 32;
+    const VA_MAX_BITS: usize = 32;
+    const PAGE_SIZES: &'static [PageSize] = &[PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+    type VirtAddr = memory_addr::VirtAddr;
+
+    fn vaddr_is_valid(_vaddr: usize) -> bool {
+        // All 32-bit addresses are valid
+        true
+    }
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<memory_addr::VirtAddr>) {
+        flush_tlb(vaddr)
     }
 }
 
@@ -50,3 +103,8 @@ impl PagingMetaData for A32PagingMetaData {
 pub type A32PageTable<H> = PageTable32<A32PagingMetaData, A32PTE, H>;
 /// ARMv7-A translation table cursor.
 pub type A32PageCursor<'a, H> = PageTable32Cursor<'a, A32PagingMetaData, A32PTE, H>;
+
+/// ARMv7-A Long-descriptor (LPAE) translation table (3 levels).
+pub type A32LpaePageTable<H> = PageTable32<A32LpaePagingMetaData, A32LpaePTE, H>;
+/// ARMv7-A Long-descriptor (LPAE) translation table cursor.
+pub type A32LpaePageCursor<'a, H> = PageTable32Cursor<'a, A32LpaePagingMetaData, A32LpaePTE, H>;
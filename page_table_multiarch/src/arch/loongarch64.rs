@@ -65,12 +65,10 @@ impl PagingMetaData for LA64MetaData {
                 //
                 // formats: invtlb op, asid, addr
                 //
-                // op 0x5: Clear all page table entries with G=0 and ASID equal to the
-                // register specified ASID, and VA equal to the register specified VA.
-                //
-                // When the operation indicated by op does not require an ASID, the
-                // general register rj should be set to r0.
-                asm!("dbar 0; invtlb 0x05, $r0, {reg}", reg = in(reg) vaddr.as_usize());
+                // op 0x6: Clear all page table entries with G=1 or ASID equal to the
+                // register specified ASID, and VA equal to the register specified VA,
+                // so that the global mappings are also flushed.
+                asm!("dbar 0; invtlb 0x06, $r0, {reg}", reg = in(reg) vaddr.as_usize());
             } else {
                 // op 0x0: Clear all page table entries
                 asm!("dbar 0; invtlb 0x00, $r0, $r0");
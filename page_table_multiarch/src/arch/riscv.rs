@@ -1,11 +1,24 @@
 //! RISC-V specific page table structures.
+//!
+//! The RV64 page tables take the entry type as an optional parameter, which
+//! selects the encoding of memory types, e.g. `Sv39PageTable<H, Rv64SvpbmtPTE>`
+//! for the Svpbmt extension. See
+//! [`Rv64MemType`](page_table_entry::riscv::Rv64MemType) for the encodings.
 
 use memory_addr::VirtAddr;
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+use page_table_entry::riscv::Rv32PTE;
+#[cfg(target_pointer_width = "64")]
 use page_table_entry::riscv::Rv64PTE;
 
-use crate::{PageTable64, PageTable64Cursor, PagingMetaData};
+#[cfg(target_pointer_width = "64")]
+use crate::{GuestPhysAddr, PageTable64, PageTable64Cursor};
+use crate::{PageSize, PagingMetaData};
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+use crate::{PageTable32, PageTable32Cursor};
 
-/// A virtual address that can be used in RISC-V Sv39 and Sv48 page tables.
+/// A virtual address that can be used in RISC-V Sv32, Sv39, Sv48 and Sv57
+/// page tables.
 pub trait SvVirtAddr: memory_addr::MemoryAddr + Send + Sync {
     /// Flush the TLB.
     fn flush_tlb(vaddr: Option<Self>);
@@ -22,20 +35,106 @@ impl SvVirtAddr for VirtAddr {
     }
 }
 
+/// Metadata of RISC-V Sv32 page tables.
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+pub struct Sv32MetaData<VA: SvVirtAddr> {
+    _virt_addr: core::marker::PhantomData<VA>,
+}
+
 /// Metadata of RISC-V Sv39 page tables.
-pub struct Sv39MetaData<VA: SvVirtAddr> {
+///
+/// If `NAPOT` is true, the 64K pages of the Svnapot extension are also used.
+#[cfg(target_pointer_width = "64")]
+pub struct Sv39MetaData<VA: SvVirtAddr, const NAPOT: bool = false> {
     _virt_addr: core::marker::PhantomData<VA>,
 }
 
 /// Metadata of RISC-V Sv48 page tables.
-pub struct Sv48MetaData<VA: SvVirtAddr> {
+///
+/// If `NAPOT` is true, the 64K pages of the Svnapot extension are also used.
+#[cfg(target_pointer_width = "64")]
+pub struct Sv48MetaData<VA: SvVirtAddr, const NAPOT: bool = false> {
+    _virt_addr: core::marker::PhantomData<VA>,
+}
+
+/// Metadata of RISC-V Sv57 page tables.
+///
+/// If `NAPOT` is true, the 64K pages of the Svnapot extension are also used.
+#[cfg(target_pointer_width = "64")]
+pub struct Sv57MetaData<VA: SvVirtAddr, const NAPOT: bool = false> {
     _virt_addr: core::marker::PhantomData<VA>,
 }
 
-impl<VA: SvVirtAddr> PagingMetaData for Sv39MetaData<VA> {
+/// Metadata of RISC-V Sv39x4 G-stage page tables of the hypervisor extension,
+/// which translate 41-bit guest physical addresses.
+///
+/// The root table is widened by 2 bits to 2048 entries (16K in size). Since
+/// all guest accesses are treated as U-mode accesses, the mappings should have
+/// the [`USER`](crate::MappingFlags::USER) flag.
+#[cfg(target_pointer_width = "64")]
+pub struct Sv39x4MetaData;
+
+/// Metadata of RISC-V Sv48x4 G-stage page tables of the hypervisor extension,
+/// which translate 50-bit guest physical addresses.
+///
+/// The root table is widened by 2 bits to 2048 entries (16K in size). Since
+/// all guest accesses are treated as U-mode accesses, the mappings should have
+/// the [`USER`](crate::MappingFlags::USER) flag.
+#[cfg(target_pointer_width = "64")]
+pub struct Sv48x4MetaData;
+
+/// Flushes the G-stage TLB entries of all VMIDs by `hfence.gvma`.
+#[cfg(target_pointer_width = "64")]
+#[inline]
+fn hfence_gvma(gpa: Option<GuestPhysAddr>) {
+    // `.insn r SYSTEM, 0, 0x31, x0, rs1, x0` is `hfence.gvma rs1, zero`, it is
+    // encoded directly as the H extension may not be enabled for the target.
+    unsafe {
+        if let Some(gpa) = gpa {
+            // the guest physical address is shifted right by 2 bits
+            core::arch::asm!(".insn r 0x73, 0, 0x31, x0, {}, x0", in(reg) gpa.as_usize() >> 2);
+        } else {
+            core::arch::asm!(".insn r 0x73, 0, 0x31, x0, x0, x0");
+        }
+    }
+}
+
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+impl<VA: SvVirtAddr> PagingMetaData for Sv32MetaData<VA> {
+    const LEVELS: usize = 2;
+    const PA_MAX_BITS: usize = 32; // 34 bits in hardware, limited by `usize`
+    const VA_MAX_BITS: usize = 32;
+    const INDEX_BITS: usize = 10;
+    const PAGE_SIZES: &'static [PageSize] = &[PageSize::Size4M, PageSize::Size4K];
+
+    type VirtAddr = VA;
+
+    fn vaddr_is_valid(_vaddr: usize) -> bool {
+        // All 32-bit addresses are valid
+        true
+    }
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<VA>) {
+        <VA as SvVirtAddr>::flush_tlb(vaddr);
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl<VA: SvVirtAddr, const NAPOT: bool> PagingMetaData for Sv39MetaData<VA, NAPOT> {
     const LEVELS: usize = 3;
     const PA_MAX_BITS: usize = 56;
     const VA_MAX_BITS: usize = 39;
+    const PAGE_SIZES: &'static [PageSize] = if NAPOT {
+        &[
+            PageSize::Size1G,
+            PageSize::Size2M,
+            PageSize::Size64K,
+            PageSize::Size4K,
+        ]
+    } else {
+        &[PageSize::Size1G, PageSize::Size2M, PageSize::Size4K]
+    };
 
     type VirtAddr = VA;
 
@@ -45,10 +144,27 @@ impl<VA: SvVirtAddr> PagingMetaData for Sv39MetaData<VA> {
     }
 }
 
-impl<VA: SvVirtAddr> PagingMetaData for Sv48MetaData<VA> {
+#[cfg(target_pointer_width = "64")]
+impl<VA: SvVirtAddr, const NAPOT: bool> PagingMetaData for Sv48MetaData<VA, NAPOT> {
     const LEVELS: usize = 4;
     const PA_MAX_BITS: usize = 56;
     const VA_MAX_BITS: usize = 48;
+    const PAGE_SIZES: &'static [PageSize] = if NAPOT {
+        &[
+            PageSize::Size512G,
+            PageSize::Size1G,
+            PageSize::Size2M,
+            PageSize::Size64K,
+            PageSize::Size4K,
+        ]
+    } else {
+        &[
+            PageSize::Size512G,
+            PageSize::Size1G,
+            PageSize::Size2M,
+            PageSize::Size4K,
+        ]
+    };
 
     type VirtAddr = VA;
 
@@ -58,12 +174,148 @@ impl<VA: SvVirtAddr> PagingMetaData for Sv48MetaData<VA> {
     }
 }
 
+#[cfg(target_pointer_width = "64")]
+impl<VA: SvVirtAddr, const NAPOT: bool> PagingMetaData for Sv57MetaData<VA, NAPOT> {
+    const LEVELS: usize = 5;
+    const PA_MAX_BITS: usize = 56;
+    const VA_MAX_BITS: usize = 57;
+    // 256T petapages are not supported.
+    const PAGE_SIZES: &'static [PageSize] = if NAPOT {
+        &[
+            PageSize::Size512G,
+            PageSize::Size1G,
+            PageSize::Size2M,
+            PageSize::Size64K,
+            PageSize::Size4K,
+        ]
+    } else {
+        &[
+            PageSize::Size512G,
+            PageSize::Size1G,
+            PageSize::Size2M,
+            PageSize::Size4K,
+        ]
+    };
+
+    type VirtAddr = VA;
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<VA>) {
+        <VA as SvVirtAddr>::flush_tlb(vaddr);
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl PagingMetaData for Sv39x4MetaData {
+    const LEVELS: usize = 3;
+    const PA_MAX_BITS: usize = 56;
+    const VA_MAX_BITS: usize = 41;
+
+    type VirtAddr = GuestPhysAddr;
+
+    fn vaddr_is_valid(vaddr: usize) -> bool {
+        vaddr >> Self::VA_MAX_BITS == 0
+    }
+
+    #[inline]
+    fn flush_tlb(gpa: Option<GuestPhysAddr>) {
+        hfence_gvma(gpa)
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+impl PagingMetaData for Sv48x4MetaData {
+    const LEVELS: usize = 4;
+    const PA_MAX_BITS: usize = 56;
+    const VA_MAX_BITS: usize = 50;
+    const PAGE_SIZES: &'static [PageSize] = &[
+        PageSize::Size512G,
+        PageSize::Size1G,
+        PageSize::Size2M,
+        PageSize::Size4K,
+    ];
+
+    type VirtAddr = GuestPhysAddr;
+
+    fn vaddr_is_valid(vaddr: usize) -> bool {
+        vaddr >> Self::VA_MAX_BITS == 0
+    }
+
+    #[inline]
+    fn flush_tlb(gpa: Option<GuestPhysAddr>) {
+        hfence_gvma(gpa)
+    }
+}
+
+/// Sv32: Page-Based 32-bit (2 levels) Virtual-Memory System.
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+pub type Sv32PageTable<H> = PageTable32<Sv32MetaData<VirtAddr>, Rv32PTE, H>;
+/// Sv32 page table cursor.
+#[cfg(any(target_pointer_width = "32", doc, docsrs))]
+pub type Sv32PageTableCursor<'a, H> = PageTable32Cursor<'a, Sv32MetaData<VirtAddr>, Rv32PTE, H>;
+
 /// Sv39: Page-Based 39-bit (3 levels) Virtual-Memory System.
-pub type Sv39PageTable<H> = PageTable64<Sv39MetaData<VirtAddr>, Rv64PTE, H>;
+#[cfg(target_pointer_width = "64")]
+pub type Sv39PageTable<H, PTE = Rv64PTE> = PageTable64<Sv39MetaData<VirtAddr>, PTE, H>;
 /// Sv39 page table cursor.
-pub type Sv39PageTableCursor<'a, H> = PageTable64Cursor<'a, Sv39MetaData<VirtAddr>, Rv64PTE, H>;
+#[cfg(target_pointer_width = "64")]
+pub type Sv39PageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv39MetaData<VirtAddr>, PTE, H>;
+
+/// Sv39 page table with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv39NapotPageTable<H, PTE = Rv64PTE> = PageTable64<Sv39MetaData<VirtAddr, true>, PTE, H>;
+/// Sv39 page table cursor with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv39NapotPageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv39MetaData<VirtAddr, true>, PTE, H>;
 
 /// Sv48: Page-Based 48-bit (4 levels) Virtual-Memory System.
-pub type Sv48PageTable<H> = PageTable64<Sv48MetaData<VirtAddr>, Rv64PTE, H>;
+#[cfg(target_pointer_width = "64")]
+pub type Sv48PageTable<H, PTE = Rv64PTE> = PageTable64<Sv48MetaData<VirtAddr>, PTE, H>;
 /// Sv48 page table cursor.
-pub type Sv48PageTableCursor<'a, H> = PageTable64Cursor<'a, Sv48MetaData<VirtAddr>, Rv64PTE, H>;
+#[cfg(target_pointer_width = "64")]
+pub type Sv48PageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv48MetaData<VirtAddr>, PTE, H>;
+
+/// Sv48 page table with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv48NapotPageTable<H, PTE = Rv64PTE> = PageTable64<Sv48MetaData<VirtAddr, true>, PTE, H>;
+/// Sv48 page table cursor with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv48NapotPageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv48MetaData<VirtAddr, true>, PTE, H>;
+
+/// Sv57: Page-Based 57-bit (5 levels) Virtual-Memory System.
+#[cfg(target_pointer_width = "64")]
+pub type Sv57PageTable<H, PTE = Rv64PTE> = PageTable64<Sv57MetaData<VirtAddr>, PTE, H>;
+/// Sv57 page table cursor.
+#[cfg(target_pointer_width = "64")]
+pub type Sv57PageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv57MetaData<VirtAddr>, PTE, H>;
+
+/// Sv57 page table with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv57NapotPageTable<H, PTE = Rv64PTE> = PageTable64<Sv57MetaData<VirtAddr, true>, PTE, H>;
+/// Sv57 page table cursor with the 64K NAPOT pages of the Svnapot extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv57NapotPageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv57MetaData<VirtAddr, true>, PTE, H>;
+
+/// Sv39x4: Page-Based 41-bit (3 levels) G-stage translation of the hypervisor
+/// extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv39x4PageTable<H, PTE = Rv64PTE> = PageTable64<Sv39x4MetaData, PTE, H>;
+/// Sv39x4 page table cursor.
+#[cfg(target_pointer_width = "64")]
+pub type Sv39x4PageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv39x4MetaData, PTE, H>;
+
+/// Sv48x4: Page-Based 50-bit (4 levels) G-stage translation of the hypervisor
+/// extension.
+#[cfg(target_pointer_width = "64")]
+pub type Sv48x4PageTable<H, PTE = Rv64PTE> = PageTable64<Sv48x4MetaData, PTE, H>;
+/// Sv48x4 page table cursor.
+#[cfg(target_pointer_width = "64")]
+pub type Sv48x4PageTableCursor<'a, H, PTE = Rv64PTE> =
+    PageTable64Cursor<'a, Sv48x4MetaData, PTE, H>;
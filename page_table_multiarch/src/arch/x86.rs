@@ -0,0 +1,83 @@
+//! x86 (32-bit) specific page table structures.
+
+use memory_addr::VirtAddr;
+use page_table_entry::x86::{X86PTE, X86PaePTE};
+
+use crate::{PageSize, PageTable32, PageTable32Cursor, PagingMetaData};
+
+#[inline]
+fn flush_tlb(vaddr: Option<VirtAddr>) {
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            x86::tlb::flush(vaddr.into());
+        } else {
+            x86::tlb::flush_all();
+        }
+    }
+}
+
+/// Metadata of x86 page tables on 32-bit paging.
+///
+/// The 4M pages require `CR4.PSE` to be set.
+pub struct X86PagingMetaData;
+
+impl PagingMetaData for X86PagingMetaData {
+    const LEVELS: usize = 2;
+    const PA_MAX_BITS: usize = 32;
+    const VA_MAX_BITS: usize = 32;
+    const INDEX_BITS: usize = 10;
+    const PAGE_SIZES: &'static [PageSize] = &[PageSize::Size4M, PageSize::Size4K];
+
+    type VirtAddr = VirtAddr;
+
+    fn vaddr_is_valid(_vaddr: usize) -> bool {
+        // All 32-bit addresses are valid
+        true
+    }
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<VirtAddr>) {
+        flush_tlb(vaddr)
+    }
+}
+
+/// Metadata of x86 page tables on PAE paging.
+///
+/// The root table is the 4-entry page-directory-pointer table (PDPT), which
+/// is loaded into `CR3`. The processor caches the 4 PDPTEs on `CR3` writes, so
+/// all the page directories are allocated with the page table.
+///
+/// The mappings without [`EXECUTE`](crate::MappingFlags::EXECUTE) set the
+/// execute-disable bit, which requires `IA32_EFER.NXE` to be set. Otherwise,
+/// the bit is reserved and any access through such mappings faults.
+pub struct X86PaePagingMetaData;
+
+impl PagingMetaData for X86PaePagingMetaData {
+    const LEVELS: usize = 3;
+    const PA_MAX_BITS: usize = 32; // 52 bits in hardware, limited by `usize`
+    const VA_MAX_BITS: usize = 32;
+    const PAGE_SIZES: &'static [PageSize] = &[PageSize::Size2M, PageSize::Size4K];
+    const FIXED_ROOT_ENTRIES: bool = true;
+
+    type VirtAddr = VirtAddr;
+
+    fn vaddr_is_valid(_vaddr: usize) -> bool {
+        // All 32-bit addresses are valid
+        true
+    }
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<VirtAddr>) {
+        flush_tlb(vaddr)
+    }
+}
+
+/// x86 page table on 32-bit paging (2 levels).
+pub type X86PageTable<H> = PageTable32<X86PagingMetaData, X86PTE, H>;
+/// x86 page table cursor on 32-bit paging.
+pub type X86PageTableCursor<'a, H> = PageTable32Cursor<'a, X86PagingMetaData, X86PTE, H>;
+
+/// x86 page table on PAE paging (3 levels).
+pub type X86PaePageTable<H> = PageTable32<X86PaePagingMetaData, X86PaePTE, H>;
+/// x86 page table cursor on PAE paging.
+pub type X86PaePageTableCursor<'a, H> = PageTable32Cursor<'a, X86PaePagingMetaData, X86PaePTE, H>;
@@ -1,9 +1,22 @@
 //! x86 specific page table structures.
 
-use memory_addr::VirtAddr;
-use page_table_entry::x86_64::X64PTE;
+use core::{arch::asm, marker::PhantomData};
 
-use crate::{PageTable64, PageTable64Cursor, PagingMetaData};
+use memory_addr::{PhysAddr, VirtAddr};
+use page_table_entry::x86_64::{EPTMemType, EptPTE, X64PTE};
+
+use crate::{GuestPhysAddr, PageTable64, PageTable64Cursor, PagingMetaData};
+
+#[inline]
+fn flush_tlb(vaddr: Option<VirtAddr>) {
+    unsafe {
+        if let Some(vaddr) = vaddr {
+            x86::tlb::flush(vaddr.into());
+        } else {
+            x86::tlb::flush_all();
+        }
+    }
+}
 
 /// metadata of x86_64 page tables.
 pub struct X64PagingMetaData;
@@ -17,17 +30,146 @@ impl PagingMetaData for X64PagingMetaData {
 
     #[inline]
     fn flush_tlb(vaddr: Option<VirtAddr>) {
+        flush_tlb(vaddr)
+    }
+}
+
+/// metadata of x86_64 page tables with 5-level paging (LA57).
+///
+/// The 1G huge pages are still the largest pages, the PML5 and PML4 entries
+/// can only reference the next level tables.
+pub struct X64La57PagingMetaData;
+
+impl PagingMetaData for X64La57PagingMetaData {
+    const LEVELS: usize = 5;
+    const PA_MAX_BITS: usize = 52;
+    const VA_MAX_BITS: usize = 57;
+
+    type VirtAddr = VirtAddr;
+
+    #[inline]
+    fn flush_tlb(vaddr: Option<VirtAddr>) {
+        flush_tlb(vaddr)
+    }
+}
+
+/// Metadata of Intel EPT (Extended Page Table), which translates guest
+/// physical addresses to host physical addresses with 4 levels.
+///
+/// The `INVEPT` instruction can not invalidate a single guest physical
+/// address, so all contexts are invalidated on every flush.
+pub struct EptMetaData;
+
+impl EptMetaData {
+    /// Returns the EPT pointer (EPTP) value of the VMCS to use the EPT at
+    /// `root_paddr`.
+    ///
+    /// The paging-structure memory type is write-back. If `enable_ad` is
+    /// true, the accessed and dirty flags of EPT entries are set by the CPU.
+    pub const fn eptp(root_paddr: PhysAddr, enable_ad: bool) -> u64 {
+        let walk_length = (<Self as PagingMetaData>::LEVELS - 1) as u64;
+        let mut eptp = EPTMemType::WriteBack as u64 | (walk_length << 3);
+        if enable_ad {
+            eptp |= 1 << 6;
+        }
+        eptp | root_paddr.as_usize() as u64
+    }
+}
+
+impl PagingMetaData for EptMetaData {
+    const LEVELS: usize = 4;
+    const PA_MAX_BITS: usize = 52;
+    const VA_MAX_BITS: usize = 48;
+
+    type VirtAddr = GuestPhysAddr;
+
+    fn vaddr_is_valid(vaddr: usize) -> bool {
+        vaddr >> Self::VA_MAX_BITS == 0
+    }
+
+    #[inline]
+    fn flush_tlb(_gpa: Option<GuestPhysAddr>) {
+        // all-context invalidation, the descriptor is ignored
+        const INVEPT_ALL_CONTEXT: u64 = 2;
+        let descriptor = [0u64; 2];
         unsafe {
-            if let Some(vaddr) = vaddr {
-                x86::tlb::flush(vaddr.into());
-            } else {
-                x86::tlb::flush_all();
-            }
+            asm!("invept {}, [{}]", in(reg) INVEPT_ALL_CONTEXT, in(reg) &descriptor);
         }
     }
 }
 
+/// The actions of the `TLB_CONTROL` field in the AMD SVM VMCB, which are
+/// performed on the next `VMRUN`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NptTlbControl {
+    /// Do nothing.
+    DoNothing           = 0,
+    /// Flush the entire TLB, including the entries of all ASIDs.
+    FlushAll            = 1,
+    /// Flush the TLB entries of the guest's ASID.
+    FlushGuest          = 3,
+    /// Flush the non-global TLB entries of the guest's ASID.
+    FlushGuestNonGlobal = 7,
+}
+
+/// The hook to flush the TLB entries translated by AMD NPT.
+///
+/// The nested translations are tagged by the guest's ASID, and can not be
+/// invalidated on the host by `INVLPG`. Instead, the hypervisor records the
+/// action and applies it to the `TLB_CONTROL` field of the VMCB before the
+/// next `VMRUN`.
+pub trait NptFlushHandler: Sync + Send {
+    /// Records the `TLB_CONTROL` action that is needed after the nested page
+    /// table is modified.
+    fn request_flush(control: NptTlbControl);
+}
+
+/// Metadata of AMD NPT (Nested Page Table), which translates guest physical
+/// addresses to host physical addresses with 4 levels.
+///
+/// NPT uses the same entry format as the host long-mode paging ([`X64PTE`]).
+/// Since the nested page walks treat all accesses as user accesses, the
+/// mappings should have the [`USER`](crate::MappingFlags::USER) flag.
+///
+/// TLB flushes are forwarded to `F`, which always requests to flush the
+/// guest's ASID since there is no way to flush a single guest physical
+/// address.
+pub struct NptMetaData<F: NptFlushHandler>(PhantomData<F>);
+
+impl<F: NptFlushHandler> PagingMetaData for NptMetaData<F> {
+    const LEVELS: usize = 4;
+    const PA_MAX_BITS: usize = 52;
+    const VA_MAX_BITS: usize = 48;
+
+    type VirtAddr = GuestPhysAddr;
+
+    fn vaddr_is_valid(vaddr: usize) -> bool {
+        vaddr >> Self::VA_MAX_BITS == 0
+    }
+
+    #[inline]
+    fn flush_tlb(_gpa: Option<GuestPhysAddr>) {
+        F::request_flush(NptTlbControl::FlushGuest)
+    }
+}
+
 /// x86_64 page table.
 pub type X64PageTable<H> = PageTable64<X64PagingMetaData, X64PTE, H>;
 /// x86_64 page table cursor.
 pub type X64PageTableCursor<'a, H> = PageTable64Cursor<'a, X64PagingMetaData, X64PTE, H>;
+
+/// x86_64 page table with 5-level paging (LA57).
+pub type X64La57PageTable<H> = PageTable64<X64La57PagingMetaData, X64PTE, H>;
+/// x86_64 page table cursor with 5-level paging (LA57).
+pub type X64La57PageTableCursor<'a, H> = PageTable64Cursor<'a, X64La57PagingMetaData, X64PTE, H>;
+
+/// Intel EPT (Extended Page Table).
+pub type EptPageTable<H> = PageTable64<EptMetaData, EptPTE, H>;
+/// Intel EPT (Extended Page Table) cursor.
+pub type EptPageTableCursor<'a, H> = PageTable64Cursor<'a, EptMetaData, EptPTE, H>;
+
+/// AMD NPT (Nested Page Table).
+pub type NptPageTable<F, H> = PageTable64<NptMetaData<F>, X64PTE, H>;
+/// AMD NPT (Nested Page Table) cursor.
+pub type NptPageTableCursor<'a, F, H> = PageTable64Cursor<'a, NptMetaData<F>, X64PTE, H>;
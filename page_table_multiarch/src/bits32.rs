@@ -1,90 +1,59 @@
+#[cfg(feature = "copy-from")]
+use alloc::vec::Vec;
 use core::{marker::PhantomData, ops::Deref};
 
 use arrayvec::ArrayVec;
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr};
+use memory_addr::{AddrRange, MemoryAddr, PAGE_SIZE_4K, PhysAddr};
 
 use crate::{
-    GenericPTE, MappingFlags, PageSize, PagingError, PagingHandler, PagingMetaData, PagingResult,
-    TlbFlusher,
+    AccessState, FREED_TABLE_THRESHOLD, GenericLargePTE, GenericPTE, MappingFlags, PageSize,
+    PagingError, PagingHandler, PagingMetaData, PagingResult, PhysAddr64, TlbFlusher, check_paddr,
+    check_vaddr, mark_dirty,
 };
 
-#[cfg(target_arch = "arm")]
-const ENTRY_COUNT: usize = 4096; // ARMv7-A L1 has 4096 entries
-#[cfg(not(target_arch = "arm"))]
-const ENTRY_COUNT: usize = 512; // 512 entries per table
-
-/// Extract the L1 (first-level) page table index from a virtual address.
-///
-/// For ARMv7-A:
-/// - L1 uses bits[31:20] of the virtual address (12 bits = 4096 entries)
-/// - Each L1 entry covers 1MB of virtual address space
-const fn p1_index(vaddr: usize) -> usize {
-    (vaddr >> 20) & 0xFFF // bits[31:20] for 1MB sections
-}
-
-/// Extract the L2 (second-level) page table index from a virtual address.
+/// A generic page table struct for 32-bit platform.
 ///
-/// For ARMv7-A:
-/// - L2 uses bits[19:12] of the virtual address (8 bits = 256 entries)
-/// - Each L2 entry covers 4KB of virtual address space
-const fn p2_index(vaddr: usize) -> usize {
-    (vaddr >> 12) & 0xFF // bits[19:12] for 4KB pages
-}
-
-/// A generic page table struct for 32-bit ARM platform (ARMv7-A).
+/// The table geometry is described by the [`PagingMetaData`], for example:
 ///
-/// This implements a 2-level page table:
-/// - L1: 4096 entries, each covering 1MB (Section) or pointing to L2
-/// - L2: 256 entries, each covering 4KB (Small Page)
+/// - ARMv7-A short-descriptor: L1 has 4096 entries, each covering 1MB (Section)
+///   or pointing to L2; L2 has 256 entries, each covering 4KB (Small Page).
+///   16MB Supersections and 64KB Large Pages are replicated in 16 entries.
+/// - RISC-V Sv32: both levels have 1024 entries, covering 4MB (Megapage) and
+///   4KB respectively.
 ///
-/// It tracks all L2 tables for proper deallocation.
+/// It tracks all intermediate level tables for proper deallocation.
 pub struct PageTable32<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> {
     root_paddr: PhysAddr,
+    // The bitmap of root entries borrowed by `copy_from`, allocated on the
+    // first call
     #[cfg(feature = "copy-from")]
-    borrowed_entries: [u64; ENTRY_COUNT / 64],
+    borrowed_entries: Vec<u64>,
     _phantom: PhantomData<(M, PTE, H)>,
 }
 
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H> {
     /// Creates a new page table instance or returns the error.
     ///
-    /// It will allocate a new 16KB aligned page for the L1 page table.
+    /// It will allocate a new page for the root page table, the root table
+    /// larger than 4KB (e.g., 16KB for ARMv7-A L1) is aligned to its size.
+    /// If [`PagingMetaData::FIXED_ROOT_ENTRIES`] is set (e.g., x86 PAE), the
+    /// next level tables of all root entries are allocated as well.
     pub fn try_new() -> PagingResult<Self> {
-        let (root_paddr, size_pages) = {
-            #[cfg(target_arch = "arm")]
-            {
-                // ARMv7-A L1 page table: 4096 entries * 4 bytes = 16KB
-                // Requires 16KB alignment for TTBR0
-                const L1_SIZE_PAGES: usize = 4; // 16KB = 4 * 4KB
-                const L1_ALIGN: usize = 16384; // 16KB alignment
-
-                let root_paddr =
-                    H::alloc_frames(L1_SIZE_PAGES, L1_ALIGN).ok_or(PagingError::NoMemory)?;
-
-                (root_paddr, L1_SIZE_PAGES)
-            }
-
-            #[cfg(not(target_arch = "arm"))]
-            {
-                // Other 32-bit architectures page table: 512 entries * 8 bytes = 4KB
-                const SIZE_PAGES: usize = 1; // 4KB = 1 * 4KB
-                let root_paddr = H::alloc_frame().ok_or(PagingError::NoMemory)?;
-                (root_paddr, SIZE_PAGES)
-            }
-        };
-
-        // Zero out the root page table
-        let virt = H::phys_to_virt(root_paddr);
-        unsafe {
-            core::ptr::write_bytes(virt.as_mut_ptr(), 0, size_pages * PAGE_SIZE_4K);
-        }
-
-        Ok(Self {
+        let root_paddr = Self::alloc_table(0)?;
+        let table = Self {
             root_paddr,
             #[cfg(feature = "copy-from")]
-            borrowed_entries: [0; ENTRY_COUNT / 64],
+            borrowed_entries: Vec::new(),
             _phantom: PhantomData,
-        })
+        };
+        if M::FIXED_ROOT_ENTRIES {
+            // The allocated tables are deallocated on drop if it fails
+            let root = table.get_table_mut(root_paddr, 0);
+            for entry in root.iter_mut() {
+                *entry = GenericPTE::new_root_table(Self::alloc_table(1)?);
+            }
+        }
+        Ok(table)
     }
 
     /// Returns the physical address of the root page table (L1).
@@ -98,14 +67,20 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
     /// the page size.
     ///
     /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
-    /// mapping is not present.
+    /// mapping is not present, or
+    /// [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr) if
+    /// `vaddr` is not valid.
     pub fn query(&self, vaddr: M::VirtAddr) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
-        let (entry, size) = self.get_entry(vaddr)?;
+        let (entry, size, is_huge) = self.get_entry(vaddr)?;
         if entry.is_unused() {
             return Err(PagingError::NotMapped);
         }
         let off = vaddr.into() & (size as usize - 1);
-        Ok((entry.paddr().add(off), entry.flags(), size))
+        Ok((
+            entry.leaf_paddr(is_huge).align_down(size).add(off),
+            entry.leaf_flags(is_huge),
+            size,
+        ))
     }
 
     /// Walk the page table recursively.
@@ -114,7 +89,7 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
         F: Fn(usize, usize, M::VirtAddr, &PTE),
     {
         self.walk_recursive(
-            self.get_table(self.root_paddr),
+            self.get_table(self.root_paddr, 0),
             0,
             0.into(),
             limit,
@@ -123,6 +98,33 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
         )
     }
 
+    /// Returns an iterator over the leaf mappings that overlap `range`, in
+    /// ascending order of virtual addresses.
+    ///
+    /// Each item is the start address of the page, the physical address of
+    /// the target frame, the mapping flags, and the page size. The empty
+    /// subtrees are skipped without visiting their entries.
+    ///
+    /// Returns [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr)
+    /// if the range is not valid.
+    pub fn mappings(
+        &self,
+        range: AddrRange<M::VirtAddr>,
+    ) -> PagingResult<impl Iterator<Item = (M::VirtAddr, PhysAddr, MappingFlags, PageSize)> + '_>
+    {
+        let mut vaddr: usize = range.start.into();
+        let end: usize = range.end.into();
+        if !range.is_empty() {
+            check_vaddr::<M>(vaddr)?;
+            check_vaddr::<M>(end - 1)?;
+        }
+        Ok(core::iter::from_fn(move || {
+            let (start, paddr, flags, size) = self.next_mapping(vaddr, end)?;
+            vaddr = start.checked_add(size as usize).unwrap_or(end);
+            Some((start.into(), paddr, flags, size))
+        }))
+    }
+
     /// Gets a cursor to modify the page table.
     ///
     /// The TLB will be flushed automatically when the cursor is dropped.
@@ -131,91 +133,284 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
     }
 
     // Private helpers
-    fn get_entry_mut(&mut self, vaddr: M::VirtAddr) -> PagingResult<(&mut PTE, PageSize)> {
-        let vaddr_usize = vaddr.into();
-        let p1 = p1_index(vaddr_usize);
-        let table = self.get_table_mut(self.root_paddr);
-        let entry = &mut table[p1];
 
-        if entry.is_unused() {
-            return Err(PagingError::NotMapped);
+    /// Returns the number of low address bits that are not translated by the
+    /// entries of the given level (level `0` is the root).
+    const fn level_shift(level: usize) -> usize {
+        M::PAGE_SHIFT + (M::LEVELS - 1 - level) * M::INDEX_BITS
+    }
+
+    /// Returns the number of entries in a table of the given level.
+    const fn entry_count(level: usize) -> usize {
+        if level == 0 {
+            1 << (M::VA_MAX_BITS - Self::level_shift(0))
+        } else {
+            1 << M::INDEX_BITS
         }
+    }
+
+    /// Returns the number of 4KB frames occupied by a table of the given level.
+    ///
+    /// Tables smaller than 4KB (e.g., 1KB for ARMv7-A L2) still occupy a whole
+    /// frame.
+    const fn table_frames(level: usize) -> usize {
+        let size = Self::entry_count(level) * core::mem::size_of::<PTE>();
+        size.div_ceil(PAGE_SIZE_4K)
+    }
+
+    /// Returns the index of the entry that translates `vaddr` in the table of
+    /// the given level.
+    const fn entry_index(vaddr: usize, level: usize) -> usize {
+        (vaddr >> Self::level_shift(level)) & (Self::entry_count(level) - 1)
+    }
 
-        // Check if it's a 1MB Section
-        if entry.is_huge() {
-            return Ok((entry, PageSize::Size1M));
+    /// Returns the page size mapped by a leaf entry of the given level, or
+    /// [`None`] if the hardware does not allow leaf entries at this level.
+    fn level_page_size(level: usize) -> Option<PageSize> {
+        let size = 1usize << Self::level_shift(level);
+        M::PAGE_SIZES.iter().copied().find(|s| *s as usize == size)
+    }
+
+    /// Returns the level whose leaf entries map pages of `page_size`, either
+    /// by a single entry or by a run of replicated entries (e.g., ARMv7-A
+    /// supersections and large pages).
+    fn page_size_level(page_size: PageSize) -> PagingResult<usize> {
+        if !M::PAGE_SIZES.contains(&page_size) {
+            return Err(PagingError::UnsupportedPageSize);
         }
+        (0..M::LEVELS)
+            .find(|&level| 1usize << Self::level_shift(level) <= page_size as usize)
+            .ok_or(PagingError::UnsupportedPageSize)
+    }
 
-        // It's a page table pointer, go to L2
-        let p2_table = self.get_table_mut(entry.paddr());
-        let p2 = p2_index(vaddr_usize);
-        Ok((&mut p2_table[p2], PageSize::Size4K))
+    /// Returns the size of the contiguous pages mapped by replicated leaf
+    /// entries of the given level, if supported.
+    fn level_contiguous_size(level: usize) -> Option<PageSize> {
+        M::PAGE_SIZES.iter().copied().find(|&size| {
+            size as usize > 1 << Self::level_shift(level)
+                && Self::page_size_level(size) == Ok(level)
+        })
     }
 
-    fn get_entry(&self, vaddr: M::VirtAddr) -> PagingResult<(&PTE, PageSize)> {
-        let vaddr_usize = vaddr.into();
-        let p1 = p1_index(vaddr_usize);
-        let table = self.get_table(self.root_paddr);
-        let entry = &table[p1];
+    /// Returns the page size mapped by the leaf `entry` of the given level.
+    fn leaf_page_size(entry: &PTE, level: usize) -> PagingResult<PageSize> {
+        let is_huge = level < M::LEVELS - 1;
+        let size = if is_huge {
+            Self::level_page_size(level).ok_or(PagingError::MappedToHugePage)?
+        } else {
+            PageSize::Size4K
+        };
+        if entry.is_contiguous(is_huge) {
+            Ok(Self::level_contiguous_size(level).unwrap_or(size))
+        } else {
+            Ok(size)
+        }
+    }
 
-        if entry.is_unused() {
-            return Err(PagingError::NotMapped);
+    /// Returns the leaf entries in `table` of the given level that map the
+    /// page of `page_size` containing `vaddr`.
+    fn page_entries(
+        table: &mut [PTE],
+        vaddr: usize,
+        level: usize,
+        page_size: PageSize,
+    ) -> &mut [PTE] {
+        let count = page_size as usize >> Self::level_shift(level);
+        let start = Self::entry_index(vaddr, level) & !(count - 1);
+        &mut table[start..start + count]
+    }
+
+    /// Sets the leaf `entries` of the given level to map a page starting at
+    /// `paddr`.
+    fn fill_entries(entries: &mut [PTE], paddr: PhysAddr, flags: MappingFlags, level: usize) {
+        let is_huge = level < M::LEVELS - 1;
+        if let [entry] = entries {
+            *entry = GenericPTE::new_page(paddr, flags, is_huge);
+            return;
+        }
+        let entry_size = 1 << Self::level_shift(level);
+        for (i, entry) in entries.iter_mut().enumerate() {
+            *entry = GenericPTE::new_contiguous_page(paddr.add(i * entry_size), flags, is_huge);
         }
+    }
+
+    fn alloc_table(level: usize) -> PagingResult<PhysAddr> {
+        let frames = Self::table_frames(level);
+        let paddr = if frames == 1 {
+            H::alloc_frame()
+        } else {
+            H::alloc_frames(frames, frames * PAGE_SIZE_4K)
+        }
+        .ok_or(PagingError::NoMemory)?;
+        check_paddr::<M>(paddr, frames * PAGE_SIZE_4K)
+            .inspect_err(|_| Self::dealloc_table(paddr, level))?;
 
-        if entry.is_huge() {
-            return Ok((entry, PageSize::Size1M));
+        // Zero out the page table
+        let virt = H::phys_to_virt(paddr);
+        unsafe {
+            core::ptr::write_bytes(virt.as_mut_ptr(), 0, frames * PAGE_SIZE_4K);
         }
+        Ok(paddr)
+    }
 
-        let p2_table = self.get_table(entry.paddr());
-        let p2 = p2_index(vaddr_usize);
-        Ok((&p2_table[p2], PageSize::Size4K))
+    fn dealloc_table(paddr: PhysAddr, level: usize) {
+        let frames = Self::table_frames(level);
+        if frames == 1 {
+            H::dealloc_frame(paddr);
+        } else {
+            H::dealloc_frames(paddr, frames);
+        }
     }
 
-    fn get_entry_mut_or_create(
+    /// Returns the leaf entries that map `vaddr`, which has more than one
+    /// entry for contiguous pages, the page size and the level of entries.
+    fn get_entries_mut(
         &mut self,
         vaddr: M::VirtAddr,
-        page_size: PageSize,
-    ) -> PagingResult<&mut PTE> {
+    ) -> PagingResult<(&mut [PTE], PageSize, usize)> {
         let vaddr_usize = vaddr.into();
-        let p1 = p1_index(vaddr_usize);
-        let table = self.get_table_mut(self.root_paddr);
-
-        if page_size == PageSize::Size1M {
-            // Map as 1MB Section in L1
-            return Ok(&mut table[p1]);
+        check_vaddr::<M>(vaddr_usize)?;
+        let mut table = self.get_table_mut(self.root_paddr, 0);
+        let mut level = 0;
+        while level < M::LEVELS - 1 {
+            let entry = &table[Self::entry_index(vaddr_usize, level)];
+            if entry.is_unused() {
+                return Err(PagingError::NotMapped);
+            }
+            if entry.is_huge() {
+                break;
+            }
+            // It's a page table pointer, go to the next level
+            table = self.get_table_mut(entry.paddr(), level + 1);
+            level += 1;
         }
+        let size = Self::leaf_page_size(&table[Self::entry_index(vaddr_usize, level)], level)?;
+        Ok((
+            Self::page_entries(table, vaddr_usize, level, size),
+            size,
+            level,
+        ))
+    }
 
-        // Need L2 page table for 4KB mapping
-        let entry = &mut table[p1];
-        if entry.is_unused() {
-            // Create new L2 page table (allocate 4KB, though only 1KB is used)
-            let paddr = H::alloc_frame().ok_or(PagingError::NoMemory)?;
+    /// Returns the first leaf mapping that overlaps `[vaddr, end)`, with the
+    /// start address of the page.
+    ///
+    /// The unused entries are skipped in the same table, which is only walked
+    /// again from the root after its last entry.
+    fn next_mapping(
+        &self,
+        mut vaddr: usize,
+        end: usize,
+    ) -> Option<(usize, PhysAddr, MappingFlags, PageSize)> {
+        'walk: while vaddr < end && M::vaddr_is_valid(vaddr) {
+            let mut table = self.get_table(self.root_paddr, 0);
+            let mut level = 0;
+            loop {
+                let entry = &table[Self::entry_index(vaddr, level)];
+                if !entry.is_unused() {
+                    if level < M::LEVELS - 1 && !entry.is_huge() {
+                        table = self.get_table(entry.paddr(), level + 1);
+                        level += 1;
+                        continue;
+                    }
+                    if let Ok(size) = Self::leaf_page_size(entry, level) {
+                        let start = vaddr & !(size as usize - 1);
+                        let is_huge = level < M::LEVELS - 1;
+                        let paddr = entry.leaf_paddr(is_huge).align_down(size);
+                        return Some((start, paddr, entry.leaf_flags(is_huge), size));
+                    }
+                }
 
-            // Zero out the L2 page table
-            let virt = H::phys_to_virt(paddr);
-            unsafe {
-                core::ptr::write_bytes(virt.as_mut_ptr(), 0, PAGE_SIZE_4K);
+                let span = 1usize << Self::level_shift(level);
+                vaddr = (vaddr & !(span - 1)).checked_add(span)?;
+                if vaddr >= end || !M::vaddr_is_valid(vaddr) {
+                    return None;
+                } else if Self::entry_index(vaddr, level) == 0 {
+                    continue 'walk;
+                }
             }
+        }
+        None
+    }
 
-            *entry = GenericPTE::new_table(paddr);
-        } else if entry.is_huge() {
-            // Already mapped as huge page
-            return Err(PagingError::AlreadyMapped);
+    fn get_entry(&self, vaddr: M::VirtAddr) -> PagingResult<(&PTE, PageSize, bool)> {
+        let vaddr_usize = vaddr.into();
+        check_vaddr::<M>(vaddr_usize)?;
+        let mut table = self.get_table(self.root_paddr, 0);
+        for level in 0..M::LEVELS - 1 {
+            let entry = &table[Self::entry_index(vaddr_usize, level)];
+            if entry.is_unused() {
+                return Err(PagingError::NotMapped);
+            }
+            if entry.is_huge() {
+                return Ok((entry, Self::leaf_page_size(entry, level)?, true));
+            }
+            table = self.get_table(entry.paddr(), level + 1);
         }
+        let entry = &table[Self::entry_index(vaddr_usize, M::LEVELS - 1)];
+        Ok((entry, Self::leaf_page_size(entry, M::LEVELS - 1)?, false))
+    }
 
-        let p2_table = self.get_table_mut(entry.paddr());
-        let p2 = p2_index(vaddr_usize);
-        Ok(&mut p2_table[p2])
+    /// Returns the leaf entries to map the page of `page_size` at `vaddr`,
+    /// creating the intermediate tables if needed, and the level of entries.
+    fn get_entries_mut_or_create(
+        &mut self,
+        vaddr: M::VirtAddr,
+        page_size: PageSize,
+    ) -> PagingResult<(&mut [PTE], usize)> {
+        let vaddr_usize = vaddr.into();
+        check_vaddr::<M>(vaddr_usize)?;
+        let target_level = Self::page_size_level(page_size)?;
+        let mut table = self.get_table_mut(self.root_paddr, 0);
+        for level in 0..target_level {
+            let entry = &mut table[Self::entry_index(vaddr_usize, level)];
+            if entry.is_unused() {
+                // Create a new next level page table
+                let paddr = Self::alloc_table(level + 1)?;
+                *entry = if level == 0 {
+                    GenericPTE::new_root_table(paddr)
+                } else {
+                    GenericPTE::new_table(paddr)
+                };
+            } else if entry.is_huge() {
+                // Already mapped as huge page
+                return Err(PagingError::AlreadyMapped);
+            }
+            table = self.get_table_mut(entry.paddr(), level + 1);
+        }
+        Ok((
+            Self::page_entries(table, vaddr_usize, target_level, page_size),
+            target_level,
+        ))
     }
 
-    fn get_table<'a>(&self, paddr: PhysAddr) -> &'a [PTE] {
+    fn get_table<'a>(&self, paddr: PhysAddr, level: usize) -> &'a [PTE] {
         let ptr = H::phys_to_virt(paddr).as_ptr() as *const PTE;
-        unsafe { core::slice::from_raw_parts(ptr, ENTRY_COUNT) }
+        unsafe { core::slice::from_raw_parts(ptr, Self::entry_count(level)) }
     }
 
-    fn get_table_mut<'a>(&self, paddr: PhysAddr) -> &'a mut [PTE] {
+    fn get_table_mut<'a>(&self, paddr: PhysAddr, level: usize) -> &'a mut [PTE] {
         let ptr = H::phys_to_virt(paddr).as_mut_ptr() as *mut PTE;
-        unsafe { core::slice::from_raw_parts_mut(ptr, ENTRY_COUNT) }
+        unsafe { core::slice::from_raw_parts_mut(ptr, Self::entry_count(level)) }
+    }
+
+    /// Returns whether the root entry that translates `vaddr` is borrowed from
+    /// another page table by `copy_from`, which owns the tables under it.
+    #[allow(unused_variables)]
+    fn is_borrowed(&self, vaddr: usize) -> bool {
+        #[cfg(feature = "copy-from")]
+        if self.is_borrowed_entry(Self::entry_index(vaddr, 0)) {
+            return true;
+        }
+        false
+    }
+
+    /// Returns whether the root entry at `index` is borrowed by `copy_from`.
+    #[cfg(feature = "copy-from")]
+    fn is_borrowed_entry(&self, index: usize) -> bool {
+        self.borrowed_entries
+            .get(index / 64)
+            .is_some_and(|bits| bits & (1 << (index % 64)) != 0)
     }
 
     fn walk_recursive<F>(
@@ -232,18 +427,15 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
         let start_vaddr_usize: usize = start_vaddr.into();
         let mut n = 0;
         for (i, entry) in table.iter().enumerate() {
-            // L1 (level 0): each entry covers 1MB (shift 20)
-            // L2 (level 1): each entry covers 4KB (shift 12)
-            let shift = if level == 0 { 20 } else { 12 };
-            let vaddr_usize = start_vaddr_usize + (i << shift);
+            let vaddr_usize = start_vaddr_usize + (i << Self::level_shift(level));
             let vaddr = vaddr_usize.into();
 
             if !entry.is_unused() {
                 if let Some(func) = pre_func {
                     func(level, i, vaddr, entry);
                 }
-                if level == 0 && !entry.is_huge() {
-                    let next_table = self.get_table(entry.paddr());
+                if level < M::LEVELS - 1 && !entry.is_huge() {
+                    let next_table = self.get_table(entry.paddr(), level + 1);
                     self.walk_recursive(next_table, level + 1, vaddr, limit, pre_func, post_func);
                 }
                 if let Some(func) = post_func {
@@ -256,25 +448,34 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32<M, PTE, H
             }
         }
     }
+
+    fn dealloc_tree(&self, table_paddr: PhysAddr, level: usize) {
+        // don't free the entries in last level, they are not tables.
+        if level < M::LEVELS - 1 {
+            for entry in self.get_table(table_paddr, level) {
+                if !entry.is_unused() && !entry.is_huge() {
+                    self.dealloc_tree(entry.paddr(), level + 1);
+                }
+            }
+        }
+        Self::dealloc_table(table_paddr, level);
+    }
 }
 
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Drop for PageTable32<M, PTE, H> {
     fn drop(&mut self) {
-        // Deallocate all L2 page tables (each is 4KB)
-        let table = self.get_table(self.root_paddr);
+        let table = self.get_table(self.root_paddr, 0);
         #[allow(unused_variables)]
         for (i, entry) in table.iter().enumerate() {
             #[cfg(feature = "copy-from")]
-            if (self.borrowed_entries[i / 64] & (1 << (i % 64))) != 0 {
+            if self.is_borrowed_entry(i) {
                 continue;
             }
-            if !entry.is_unused() && !entry.is_huge() {
-                // This is an L2 page table (4KB)
-                H::dealloc_frame(entry.paddr());
+            if M::LEVELS > 1 && !entry.is_unused() && !entry.is_huge() {
+                self.dealloc_tree(entry.paddr(), 1);
             }
         }
-        // Deallocate L1 page table (16KB = 4 pages)
-        H::dealloc_frames(self.root_paddr, 4);
+        Self::dealloc_table(self.root_paddr, 0);
     }
 }
 
@@ -282,6 +483,8 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Drop for PageTable32<
 pub struct PageTable32Cursor<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> {
     inner: &'a mut PageTable32<M, PTE, H>,
     flusher: TlbFlusher<M>,
+    // The unlinked tables and their levels, deallocated after the TLB flush
+    freed: ArrayVec<(PhysAddr, usize), FREED_TABLE_THRESHOLD>,
 }
 
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Deref
@@ -299,6 +502,7 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         Self {
             inner,
             flusher: TlbFlusher::None,
+            freed: ArrayVec::new(),
         }
     }
 
@@ -318,8 +522,33 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         }
     }
 
+    /// Returns the largest page size to map the region starting at `vaddr`
+    /// with `size` bytes left, whose physical address alignment is checked by
+    /// `paddr_aligned`.
+    fn select_page_size(
+        vaddr: usize,
+        size: usize,
+        allow_huge: bool,
+        paddr_aligned: impl Fn(PageSize) -> bool,
+    ) -> PageSize {
+        if !allow_huge {
+            return PageSize::Size4K;
+        }
+        M::PAGE_SIZES
+            .iter()
+            .copied()
+            .find(|&page_size| {
+                page_size.is_aligned(vaddr)
+                    && paddr_aligned(page_size)
+                    && size >= page_size as usize
+            })
+            .unwrap_or(PageSize::Size4K)
+    }
+
     /// Maps a virtual page to a physical frame with the given `page_size`
     /// and mapping `flags`.
+    ///
+    /// Returns an error if `vaddr` or `target` is not valid.
     pub fn map(
         &mut self,
         vaddr: M::VirtAddr,
@@ -327,56 +556,186 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         page_size: PageSize,
         flags: MappingFlags,
     ) -> PagingResult {
-        let entry = self.inner.get_entry_mut_or_create(vaddr, page_size)?;
-        if !entry.is_unused() {
+        let paddr = target.align_down(page_size);
+        check_paddr::<M>(paddr, page_size as usize)?;
+        let (entries, level) = self.inner.get_entries_mut_or_create(vaddr, page_size)?;
+        if entries.iter().any(|entry| !entry.is_unused()) {
             return Err(PagingError::AlreadyMapped);
         }
-        *entry = GenericPTE::new_page(target.align_down(page_size), flags, page_size.is_huge());
+        PageTable32::<M, PTE, H>::fill_entries(entries, paddr, flags, level);
         self.push(vaddr);
         Ok(())
     }
 
     /// Remaps the mapping starting at `vaddr`, updates both the physical
     /// address and flags.
+    ///
+    /// Returns an error if `vaddr` or `paddr` is not valid.
     pub fn remap(
         &mut self,
         vaddr: M::VirtAddr,
         paddr: PhysAddr,
         flags: MappingFlags,
     ) -> PagingResult<PageSize> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        *entry = GenericPTE::new_page(paddr, flags, size.is_huge());
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        let paddr = paddr.align_down(size);
+        check_paddr::<M>(paddr, size as usize)?;
+        PageTable32::<M, PTE, H>::fill_entries(entries, paddr, flags, level);
         self.push(vaddr);
         Ok(size)
     }
 
     /// Updates the flags of the mapping starting at `vaddr`.
     pub fn protect(&mut self, vaddr: M::VirtAddr, flags: MappingFlags) -> PagingResult<PageSize> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        if entry.is_unused() {
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        if entries[0].is_unused() {
             return Err(PagingError::NotMapped);
         }
-        *entry = GenericPTE::new_page(entry.paddr(), flags, size.is_huge());
+        for entry in entries {
+            entry.set_flags(flags, level < M::LEVELS - 1);
+        }
         self.push(vaddr);
         Ok(size)
     }
 
     /// Unmaps the mapping starting at `vaddr`.
+    ///
+    /// The tables that become empty are unlinked, and deallocated after the
+    /// TLB is flushed.
     pub fn unmap(
         &mut self,
         vaddr: M::VirtAddr,
     ) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        if entry.is_unused() {
+        let ret = self.unmap_entries(vaddr)?;
+        self.reclaim_tables(vaddr.into());
+        Ok(ret)
+    }
+
+    /// Clears the leaf entries of the mapping starting at `vaddr`, without
+    /// reclaiming the tables.
+    fn unmap_entries(
+        &mut self,
+        vaddr: M::VirtAddr,
+    ) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        if entries[0].is_unused() {
             return Err(PagingError::NotMapped);
         }
-        let paddr = entry.paddr();
-        let flags = entry.flags();
-        entry.clear();
+        let is_huge = level < M::LEVELS - 1;
+        let paddr = entries[0].leaf_paddr(is_huge).align_down(size);
+        let flags = entries[0].leaf_flags(is_huge);
+        entries.iter_mut().for_each(PTE::clear);
         self.push(vaddr);
         Ok((paddr, flags, size))
     }
 
+    /// Unlinks the tables that translate `vaddr` if they are empty, from the
+    /// last level up to the root (excluded). The next level tables of the root
+    /// are kept if [`PagingMetaData::FIXED_ROOT_ENTRIES`] is set.
+    ///
+    /// The unlinked tables may still be cached by the TLB (paging-structure
+    /// caches), so they are deallocated after the flush. Some architectures
+    /// (e.g., RISC-V) only invalidate the leaf entries by an address, so the
+    /// whole TLB will be flushed.
+    fn reclaim_tables(&mut self, vaddr: usize) {
+        if self.inner.is_borrowed(vaddr) {
+            return;
+        }
+        loop {
+            // Find the last table and the entry that references it
+            let mut table = self.inner.get_table_mut(self.root_paddr(), 0);
+            let mut parent = None;
+            for level in 0..M::LEVELS - 1 {
+                let index = PageTable32::<M, PTE, H>::entry_index(vaddr, level);
+                let entry = &table[index];
+                if entry.is_unused() || entry.is_huge() {
+                    break;
+                }
+                let next = self.inner.get_table_mut(entry.paddr(), level + 1);
+                parent = Some((table, index, level + 1));
+                table = next;
+            }
+            let Some((parent, index, level)) = parent else {
+                return;
+            };
+            if (M::FIXED_ROOT_ENTRIES && level == 1) || !table.iter().all(PTE::is_unused) {
+                return;
+            }
+            let entry = &mut parent[index];
+            let table_paddr = entry.paddr();
+            entry.clear();
+            self.flusher = TlbFlusher::Full;
+            if self.freed.is_full() {
+                self.flush();
+            }
+            self.freed.push((table_paddr, level));
+        }
+    }
+
+    /// Splits the huge page containing `vaddr`, until the page fits in the
+    /// region `[vaddr, vaddr + size)` or is a base page.
+    ///
+    /// The contiguous pages (e.g., ARMv7-A supersections) are broken into
+    /// individual entries, and the other huge pages (e.g., ARMv7-A sections)
+    /// into a next level table that preserves the mapping. The old entries
+    /// are invalidated and the TLB is flushed before the new ones are written
+    /// (break-before-make).
+    fn split_huge(&mut self, vaddr: usize, size: usize) -> PagingResult {
+        loop {
+            let Ok((entries, page_size, level)) = self.inner.get_entries_mut(vaddr.into()) else {
+                return Ok(());
+            };
+            if entries[0].is_unused() || (page_size.is_aligned(vaddr) && size >= page_size as usize)
+            {
+                return Ok(());
+            }
+            let count = entries.len();
+            if count == 1 && level == M::LEVELS - 1 {
+                return Ok(());
+            }
+            let was_huge = level < M::LEVELS - 1;
+            let huge_entry = entries[0];
+            let start = vaddr - page_size.align_offset(vaddr);
+            let entry_size = 1 << PageTable32::<M, PTE, H>::level_shift(level);
+
+            let table_paddr = if count == 1 {
+                let table_paddr = PageTable32::<M, PTE, H>::alloc_table(level + 1)?;
+                let table = self.inner.get_table_mut(table_paddr, level + 1);
+                let next_size = 1 << PageTable32::<M, PTE, H>::level_shift(level + 1);
+                let is_huge = level + 1 < M::LEVELS - 1;
+                for (i, entry) in table.iter_mut().enumerate() {
+                    *entry = huge_entry.new_page_at(i * next_size, was_huge, is_huge);
+                }
+                Some(table_paddr)
+            } else {
+                None
+            };
+
+            let (entries, ..) = self.inner.get_entries_mut(vaddr.into())?;
+            entries.iter_mut().for_each(PTE::clear);
+            for i in 0..count {
+                self.push((start + i * entry_size).into());
+            }
+            self.flush();
+
+            // The intermediate tables are present, nothing will be allocated
+            let (entries, _) = self
+                .inner
+                .get_entries_mut_or_create(start.into(), page_size)?;
+            if let Some(table_paddr) = table_paddr {
+                entries[0] = if level == 0 {
+                    GenericPTE::new_root_table(table_paddr)
+                } else {
+                    GenericPTE::new_table(table_paddr)
+                };
+            } else {
+                for (i, entry) in entries.iter_mut().enumerate() {
+                    *entry = huge_entry.new_page_at(i * entry_size, was_huge, was_huge);
+                }
+            }
+        }
+    }
+
     /// Maps a contiguous virtual memory region to a contiguous physical memory
     /// region with the given mapping `flags`.
     pub fn map_region(
@@ -402,15 +761,9 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         while size > 0 {
             let vaddr = vaddr_usize.into();
             let paddr = get_paddr(vaddr);
-            let page_size = if allow_huge
-                && PageSize::Size1M.is_aligned(vaddr_usize)
-                && paddr.is_aligned(PageSize::Size1M)
-                && size >= PageSize::Size1M as usize
-            {
-                PageSize::Size1M
-            } else {
-                PageSize::Size4K
-            };
+            let page_size = Self::select_page_size(vaddr_usize, size, allow_huge, |page_size| {
+                paddr.is_aligned(page_size)
+            });
             self.map(vaddr, paddr, page_size, flags).inspect_err(|e| {
                 error!("failed to map page: {vaddr_usize:#x?}({page_size:?}) -> {paddr:#x?}, {e:?}")
             })?;
@@ -422,6 +775,10 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
     }
 
     /// Unmaps a contiguous virtual memory region.
+    ///
+    /// The huge pages that are partially covered by the region are split
+    /// into smaller pages first. The tables that become empty are unlinked,
+    /// and deallocated after the TLB is flushed.
     pub fn unmap_region(&mut self, vaddr: M::VirtAddr, size: usize) -> PagingResult {
         let mut vaddr_usize: usize = vaddr.into();
         let mut size = size;
@@ -431,21 +788,32 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
             vaddr_usize,
             vaddr_usize + size,
         );
+        // The span of the last level tables
+        let table_span = 1 << PageTable32::<M, PTE, H>::level_shift(M::LEVELS - 2);
         while size > 0 {
             let vaddr = vaddr_usize.into();
+            self.split_huge(vaddr_usize, size)
+                .inspect_err(|e| error!("failed to split page: {vaddr_usize:#x?}, {e:?}"))?;
             let (_, _, page_size) = self
-                .unmap(vaddr)
+                .unmap_entries(vaddr)
                 .inspect_err(|e| error!("failed to unmap page: {vaddr_usize:#x?}, {e:?}"))?;
 
             assert!(page_size.is_aligned(vaddr_usize));
             assert!(page_size as usize <= size);
             vaddr_usize += page_size as usize;
             size -= page_size as usize;
+            // Reclaim the tables once, after their last entries in the region
+            if size == 0 || vaddr_usize & (table_span - 1) == 0 {
+                self.reclaim_tables(vaddr.into());
+            }
         }
         Ok(())
     }
 
     /// Updates mapping flags of a contiguous virtual memory region.
+    ///
+    /// The huge pages that are partially covered by the region are split
+    /// into smaller pages first.
     pub fn protect_region(
         &mut self,
         vaddr: M::VirtAddr,
@@ -463,10 +831,14 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         );
         while size > 0 {
             let vaddr = vaddr_usize.into();
-            let page_size = match self.inner.get_entry_mut(vaddr) {
-                Ok((entry, page_size)) => {
-                    if !entry.is_unused() {
-                        entry.set_flags(flags, page_size.is_huge());
+            self.split_huge(vaddr_usize, size)
+                .inspect_err(|e| error!("failed to split page: {vaddr_usize:#x?}, {e:?}"))?;
+            let page_size = match self.inner.get_entries_mut(vaddr) {
+                Ok((entries, page_size, level)) => {
+                    if !entries[0].is_unused() {
+                        for entry in entries {
+                            entry.set_flags(flags, level < M::LEVELS - 1);
+                        }
                         self.push(vaddr);
                     }
                     // ignore if not present
@@ -488,38 +860,385 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
         Ok(())
     }
 
+    /// Collapses the table referenced by the entry of the given `level` that
+    /// translates `vaddr` into a huge page, if the table maps physically
+    /// contiguous pages with identical flags. `f` is called with the start
+    /// address and the size of the huge page.
+    ///
+    /// Returns whether the entry maps a huge page afterwards.
+    fn collapse_table(
+        &mut self,
+        vaddr: usize,
+        level: usize,
+        f: &mut impl FnMut(M::VirtAddr, PageSize),
+    ) -> PagingResult<bool> {
+        check_vaddr::<M>(vaddr)?;
+        let Some(page_size) = PageTable32::<M, PTE, H>::level_page_size(level) else {
+            return Ok(false);
+        };
+        if self.inner.is_borrowed(vaddr) {
+            return Ok(false);
+        }
+        let mut table = self.inner.get_table_mut(self.root_paddr(), 0);
+        for level in 0..level {
+            let entry = &table[PageTable32::<M, PTE, H>::entry_index(vaddr, level)];
+            if entry.is_unused() || entry.is_huge() {
+                return Ok(false);
+            }
+            table = self.inner.get_table_mut(entry.paddr(), level + 1);
+        }
+        let entry = &mut table[PageTable32::<M, PTE, H>::entry_index(vaddr, level)];
+        if entry.is_unused() {
+            return Ok(false);
+        } else if entry.is_huge() {
+            return Ok(true);
+        }
+
+        let table_paddr = entry.paddr();
+        let entries = self.inner.get_table(table_paddr, level + 1);
+        let entry_size = 1 << PageTable32::<M, PTE, H>::level_shift(level + 1);
+        let is_huge = level + 1 < M::LEVELS - 1;
+        // The part of a contiguous page mapped by `entry` as an individual page,
+        // compared with the full-width physical addresses of the entries
+        let part = |i: usize, entry: &PTE| {
+            let size = PageTable32::<M, PTE, H>::leaf_page_size(entry, level + 1).ok()?;
+            Some(entry.new_page_at(i * entry_size % size as usize, is_huge, is_huge))
+        };
+        let Some(first) = part(0, &entries[0]) else {
+            return Ok(false);
+        };
+        if !page_size.is_aligned(first.leaf_paddr(is_huge).as_usize())
+            || !entries.iter().enumerate().all(|(i, entry)| {
+                !entry.is_unused()
+                    && (!is_huge || entry.is_huge())
+                    && part(i, entry).map(PTE::bits)
+                        == Some(first.new_page_at(i * entry_size, is_huge, is_huge).bits())
+            })
+        {
+            return Ok(false);
+        }
+
+        let start = vaddr & !(page_size as usize - 1);
+        entry.clear();
+        for i in 0..entries.len() {
+            self.push((start + i * entry_size).into());
+        }
+        self.flush();
+        *entry = first.new_page_at(0, is_huge, true);
+        PageTable32::<M, PTE, H>::dealloc_table(table_paddr, level + 1);
+        f(start.into(), page_size);
+        Ok(true)
+    }
+
+    /// Collapses the tables in a virtual memory region that map physically
+    /// contiguous pages with identical flags into huge pages (e.g., ARMv7-A
+    /// sections), and deallocates them.
+    ///
+    /// Only the huge pages fully covered by the region are created. The
+    /// accessed and dirty state of the collapsed pages is not preserved,
+    /// harvest it before collapsing if needed.
+    ///
+    /// `f` is called with the start address and the page size of each huge
+    /// page created.
+    pub fn collapse_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        mut f: impl FnMut(M::VirtAddr, PageSize),
+    ) -> PagingResult {
+        let start: usize = vaddr.into();
+        trace!(
+            "collapse_region({:#x}) [{:#x}, {:#x})",
+            self.root_paddr(),
+            start,
+            start + size,
+        );
+        let block = 1usize << PageTable32::<M, PTE, H>::level_shift(M::LEVELS - 2);
+        let mut offset = block.wrapping_sub(start) & (block - 1);
+        while offset < size && size - offset >= block {
+            let vaddr_usize = start + offset;
+            let mut level = M::LEVELS - 2;
+            while self
+                .collapse_table(vaddr_usize, level, &mut f)
+                .inspect_err(|e| error!("failed to collapse page: {vaddr_usize:#x?}, {e:?}"))?
+                && level > 0
+            {
+                // Collapse the upper table after its last block is collapsed
+                level -= 1;
+                let upper = 1usize << PageTable32::<M, PTE, H>::level_shift(level);
+                let upper_start = vaddr_usize & !(upper - 1);
+                if (vaddr_usize & (upper - 1)) + block != upper
+                    || upper_start < start
+                    || size - (upper_start - start) < upper
+                {
+                    break;
+                }
+            }
+            offset += block;
+        }
+        Ok(())
+    }
+
+    /// Harvests the accessed and dirty state of the mapping starting at
+    /// `vaddr`, clearing the state in `clear` atomically.
+    ///
+    /// Returns the state before clearing and the page size of the mapping.
+    /// The TLB flush is recorded if any state in `clear` is cleared.
+    pub fn harvest(
+        &mut self,
+        vaddr: M::VirtAddr,
+        clear: AccessState,
+    ) -> PagingResult<(AccessState, PageSize)> {
+        let (entries, size, _) = self.inner.get_entries_mut(vaddr)?;
+        if entries[0].is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        // The hardware may update any entry of a contiguous page.
+        let state = entries
+            .iter_mut()
+            .fold(AccessState::empty(), |state, entry| {
+                state | entry.clear_access_state(clear)
+            });
+        if state.intersects(clear) {
+            self.push(vaddr);
+        }
+        Ok((state, size))
+    }
+
+    /// Harvests the accessed and dirty state of the mappings in a virtual
+    /// memory region, clearing the state in `clear` atomically.
+    ///
+    /// `f` is called with the start address, the page size and the state
+    /// before clearing of each page that has been accessed or written. The
+    /// pages partially covered by the region are harvested as a whole, and
+    /// the unmapped pages are skipped. The TLB flushes are batched until
+    /// [`flush`](Self::flush) is called or the cursor is dropped.
+    pub fn harvest_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        clear: AccessState,
+        mut f: impl FnMut(M::VirtAddr, PageSize, AccessState),
+    ) -> PagingResult {
+        let mut vaddr_usize: usize = vaddr.into();
+        let mut size = size;
+        trace!(
+            "harvest_region({:#x}) [{:#x}, {:#x}) {:?}",
+            self.root_paddr(),
+            vaddr_usize,
+            vaddr_usize + size,
+            clear,
+        );
+        while size > 0 {
+            let page_size = match self.harvest(vaddr_usize.into(), clear) {
+                Ok((state, page_size)) => {
+                    if !state.is_empty() {
+                        let start = vaddr_usize - page_size.align_offset(vaddr_usize);
+                        f(start.into(), page_size, state);
+                    }
+                    page_size
+                }
+                Err(PagingError::NotMapped) => PageSize::Size4K,
+                Err(e) => {
+                    error!("failed to harvest page: {vaddr_usize:#x?}, {e:?}");
+                    return Err(e);
+                }
+            };
+
+            let step = page_size as usize - page_size.align_offset(vaddr_usize);
+            if step >= size {
+                break;
+            }
+            vaddr_usize += step;
+            size -= step;
+        }
+        Ok(())
+    }
+
+    /// Write-protects the writable pages in a virtual memory region, `f` is
+    /// called with the start address and the page size of each of them.
+    fn write_protect_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        mut f: impl FnMut(usize, PageSize),
+    ) -> PagingResult {
+        let mut vaddr_usize: usize = vaddr.into();
+        let mut size = size;
+        while size > 0 {
+            let vaddr = vaddr_usize.into();
+            let page_size = match self.inner.get_entries_mut(vaddr) {
+                Ok((entries, page_size, level)) => {
+                    let is_huge = level < M::LEVELS - 1;
+                    let flags = entries[0].leaf_flags(is_huge);
+                    if !entries[0].is_unused() && flags.contains(MappingFlags::WRITE) {
+                        for entry in entries {
+                            entry.set_flags(flags - MappingFlags::WRITE, is_huge);
+                        }
+                        self.push(vaddr);
+                        f(vaddr_usize - page_size.align_offset(vaddr_usize), page_size);
+                    }
+                    page_size
+                }
+                Err(PagingError::NotMapped) => PageSize::Size4K,
+                Err(e) => {
+                    error!("failed to write-protect page: {vaddr_usize:#x?}, {e:?}");
+                    return Err(e);
+                }
+            };
+
+            let step = page_size as usize - page_size.align_offset(vaddr_usize);
+            if step >= size {
+                break;
+            }
+            vaddr_usize += step;
+            size -= step;
+        }
+        Ok(())
+    }
+
+    /// Starts dirty-page logging in a virtual memory region.
+    ///
+    /// If the dirty state is recorded by the hardware (see
+    /// [`GenericPTE::ACCESS_STATE`]), it is cleared in the mapped pages.
+    /// Otherwise, the writable pages are write-protected. In both cases, if a
+    /// write to a clean page raises a page fault, the fault handler should
+    /// restore the mapping flags with [`protect`](Self::protect), which makes
+    /// the page dirty.
+    ///
+    /// The region must be aligned to the base page size, otherwise it will
+    /// return [`Err(PagingError::NotAligned)`](PagingError::NotAligned). The
+    /// huge pages are logged as a whole.
+    pub fn start_dirty_log(&mut self, vaddr: M::VirtAddr, size: usize) -> PagingResult {
+        let base_page_size = PageSize::Size4K;
+        if !base_page_size.is_aligned(vaddr.into()) || !base_page_size.is_aligned(size) {
+            return Err(PagingError::NotAligned);
+        }
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            self.harvest_region(vaddr, size, AccessState::DIRTY, |_, _, _| {})
+        } else {
+            self.write_protect_region(vaddr, size, |_, _| {})
+        }
+    }
+
+    /// Fetches the pages in a virtual memory region that have been written
+    /// since the dirty-page logging was started or last fetched, and makes
+    /// them clean again.
+    ///
+    /// Bit `i` of `bitmap` (bit `i % usize::BITS` of `bitmap[i / usize::BITS]`)
+    /// is set if the base page at `vaddr + i * base_page_size` is dirty, the
+    /// other bits are left unchanged. All base pages of a dirty huge page are
+    /// reported.
+    ///
+    /// The region must be aligned to the base page size, otherwise it will
+    /// return [`Err(PagingError::NotAligned)`](PagingError::NotAligned).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bitmap` has fewer bits than the base pages in the region.
+    pub fn fetch_dirty_log(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        bitmap: &mut [usize],
+    ) -> PagingResult {
+        let base_page_size = PageSize::Size4K;
+        let vaddr_usize: usize = vaddr.into();
+        if !base_page_size.is_aligned(vaddr_usize) || !base_page_size.is_aligned(size) {
+            return Err(PagingError::NotAligned);
+        }
+        assert!(bitmap.len() * usize::BITS as usize >= size / base_page_size as usize);
+        let mut mark = |page, page_size| {
+            mark_dirty(bitmap, vaddr_usize, size, base_page_size, page, page_size)
+        };
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            self.harvest_region(vaddr, size, AccessState::DIRTY, |page, page_size, state| {
+                if state.contains(AccessState::DIRTY) {
+                    mark(page.into(), page_size);
+                }
+            })
+        } else {
+            // the pages made writable by the page fault handler are dirty
+            self.write_protect_region(vaddr, size, mark)
+        }
+    }
+
+    /// Stops dirty-page logging in a virtual memory region.
+    ///
+    /// If the dirty state is not recorded by the hardware, the mapping flags
+    /// of the region are restored to `flags`, which makes the write-protected
+    /// pages writable again. Otherwise, nothing needs to be done and `flags`
+    /// is ignored.
+    pub fn stop_dirty_log(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> PagingResult {
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            Ok(())
+        } else {
+            self.protect_region(vaddr, size, flags)
+        }
+    }
+
     /// Copy entries from another page table within the given virtual memory
     /// range.
+    ///
+    /// The root entries are replaced, so the entire TLB will be flushed. On x86
+    /// PAE paging, it also reloads the PDPTEs by writing `CR3`.
+    ///
+    /// Returns [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr)
+    /// if the range is not valid.
     #[cfg(feature = "copy-from")]
-    pub fn copy_from(&mut self, other: &PageTable32<M, PTE, H>, start: M::VirtAddr, size: usize) {
+    pub fn copy_from(
+        &mut self,
+        other: &PageTable32<M, PTE, H>,
+        start: M::VirtAddr,
+        size: usize,
+    ) -> PagingResult {
         if size == 0 {
-            return;
+            return Ok(());
         }
-        let src_table = self.inner.get_table(other.root_paddr);
-        let dst_table = self.inner.get_table_mut(self.inner.root_paddr);
+        let start: usize = start.into();
+        let last = start
+            .checked_add(size - 1)
+            .ok_or(PagingError::InvalidVirtAddr)?;
+        check_vaddr::<M>(start)?;
+        check_vaddr::<M>(last)?;
+        let src_table = self.inner.get_table(other.root_paddr, 0);
+        let dst_table = self.inner.get_table_mut(self.inner.root_paddr, 0);
 
-        let start_idx = p1_index(start.into());
-        let end_idx = p1_index(start.into() + size - 1) + 1;
-        assert!(start_idx < ENTRY_COUNT);
-        assert!(end_idx <= ENTRY_COUNT);
+        let start_idx = PageTable32::<M, PTE, H>::entry_index(start, 0);
+        let end_idx = PageTable32::<M, PTE, H>::entry_index(last, 0) + 1;
+        let entry_count = PageTable32::<M, PTE, H>::entry_count(0);
+        assert!(start_idx < entry_count);
+        assert!(end_idx <= entry_count);
+        if self.inner.borrowed_entries.is_empty() {
+            self.inner
+                .borrowed_entries
+                .resize(entry_count.div_ceil(64), 0);
+        }
 
         // Simple copy here, no smart flush or tracking borrowing for now in 32-bit
         // The user just wants interface consistency.
         for i in start_idx..end_idx {
             let entry = &mut dst_table[i];
-            let is_borrowed = (self.inner.borrowed_entries[i / 64] & (1 << (i % 64))) != 0;
-            if !is_borrowed {
+            if !self.inner.is_borrowed_entry(i) {
                 self.inner.borrowed_entries[i / 64] |= 1 << (i % 64);
-                if !entry.is_unused() && !entry.is_huge() {
-                    H::dealloc_frame(entry.paddr());
+                if M::LEVELS > 1 && !entry.is_unused() && !entry.is_huge() {
+                    self.inner.dealloc_tree(entry.paddr(), 1);
                 }
             }
             *entry = src_table[i];
         }
         self.flusher = TlbFlusher::Full;
+        Ok(())
     }
 
-    /// Flushes the TLB according to the recorded flush requests.
+    /// Flushes the TLB according to the recorded flush requests, then
+    /// deallocates the tables unlinked by [`unmap`](Self::unmap) and
+    /// [`unmap_region`](Self::unmap_region).
     pub fn flush(&mut self) {
         #[cfg(not(docsrs))]
         match &self.flusher {
@@ -534,6 +1253,132 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable32Cursor
             }
         }
         self.flusher = TlbFlusher::None;
+        for (paddr, level) in self.freed.drain(..) {
+            PageTable32::<M, PTE, H>::dealloc_table(paddr, level);
+        }
+    }
+}
+
+/// Checks whether the physical region `[paddr, paddr + size)` can be mapped by
+/// the entries of `PTE`.
+fn check_large_paddr<PTE: GenericLargePTE>(paddr: PhysAddr64, size: usize) -> PagingResult {
+    match paddr.as_u64().checked_add(size as u64 - 1) {
+        Some(end) if end >> PTE::PA_MAX_BITS == 0 => Ok(()),
+        _ => Err(PagingError::InvalidPhysAddr),
+    }
+}
+
+impl<M: PagingMetaData, PTE: GenericLargePTE, H: PagingHandler> PageTable32<M, PTE, H> {
+    /// Query the result of the mapping starts with `vaddr`, like
+    /// [`query`](Self::query), but returns the physical address that may be
+    /// wider than `usize`.
+    pub fn query_large(
+        &self,
+        vaddr: M::VirtAddr,
+    ) -> PagingResult<(PhysAddr64, MappingFlags, PageSize)> {
+        let (entry, size, is_huge) = self.get_entry(vaddr)?;
+        if entry.is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let off = vaddr.into() & (size as usize - 1);
+        Ok((
+            entry.large_paddr().align_down(size as usize).add(off),
+            entry.leaf_flags(is_huge),
+            size,
+        ))
+    }
+}
+
+impl<M: PagingMetaData, PTE: GenericLargePTE, H: PagingHandler> PageTable32Cursor<'_, M, PTE, H> {
+    /// Maps a virtual page to a physical frame that may be above the range of
+    /// `usize`, like [`map`](Self::map).
+    ///
+    /// The physical address is checked against
+    /// [`GenericLargePTE::PA_MAX_BITS`] instead of
+    /// [`PagingMetaData::paddr_is_valid`], which is limited to `usize`.
+    pub fn map_large(
+        &mut self,
+        vaddr: M::VirtAddr,
+        target: PhysAddr64,
+        page_size: PageSize,
+        flags: MappingFlags,
+    ) -> PagingResult {
+        // Contiguous pages are not supported with large physical addresses
+        let level = PageTable32::<M, PTE, H>::page_size_level(page_size)?;
+        if PageTable32::<M, PTE, H>::level_page_size(level) != Some(page_size) {
+            return Err(PagingError::UnsupportedPageSize);
+        }
+        let paddr = target.align_down(page_size as usize);
+        check_large_paddr::<PTE>(paddr, page_size as usize)?;
+        let (entries, level) = self.inner.get_entries_mut_or_create(vaddr, page_size)?;
+        let entry = &mut entries[0];
+        if !entry.is_unused() {
+            return Err(PagingError::AlreadyMapped);
+        }
+        *entry = GenericLargePTE::new_large_page(paddr, flags, level < M::LEVELS - 1);
+        self.push(vaddr);
+        Ok(())
+    }
+
+    /// Unmaps the mapping starting at `vaddr`, like [`unmap`](Self::unmap),
+    /// but returns the physical address that may be wider than `usize`.
+    pub fn unmap_large(
+        &mut self,
+        vaddr: M::VirtAddr,
+    ) -> PagingResult<(PhysAddr64, MappingFlags, PageSize)> {
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        if entries[0].is_unused() {
+            return Err(PagingError::NotMapped);
+        }
+        let paddr = entries[0].large_paddr().align_down(size as usize);
+        let flags = entries[0].leaf_flags(level < M::LEVELS - 1);
+        entries.iter_mut().for_each(PTE::clear);
+        self.push(vaddr);
+        self.reclaim_tables(vaddr.into());
+        Ok((paddr, flags, size))
+    }
+
+    /// Maps a contiguous virtual memory region to a contiguous physical memory
+    /// region that may be above the range of `usize`, like
+    /// [`map_region`](Self::map_region).
+    pub fn map_region_large(
+        &mut self,
+        vaddr: M::VirtAddr,
+        get_paddr: impl Fn(M::VirtAddr) -> PhysAddr64,
+        size: usize,
+        flags: MappingFlags,
+        allow_huge: bool,
+    ) -> PagingResult {
+        let mut vaddr_usize: usize = vaddr.into();
+        let mut size = size;
+        if !PageSize::Size4K.is_aligned(vaddr_usize) || !PageSize::Size4K.is_aligned(size) {
+            return Err(PagingError::NotAligned);
+        }
+        trace!(
+            "map_region_large({:#x}): [{:#x}, {:#x}) {:?}",
+            self.root_paddr(),
+            vaddr_usize,
+            vaddr_usize + size,
+            flags,
+        );
+        while size > 0 {
+            let vaddr = vaddr_usize.into();
+            let paddr = get_paddr(vaddr);
+            let page_size = Self::select_page_size(vaddr_usize, size, allow_huge, |page_size| {
+                paddr.is_aligned(page_size as usize)
+            });
+            self.map_large(vaddr, paddr, page_size, flags)
+                .inspect_err(|e| {
+                    error!(
+                        "failed to map page: {vaddr_usize:#x?}({page_size:?}) -> {paddr:#x?}, \
+                         {e:?}"
+                    )
+                })?;
+
+            vaddr_usize += page_size as usize;
+            size -= page_size as usize;
+        }
+        Ok(())
     }
 }
 
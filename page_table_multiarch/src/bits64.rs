@@ -1,39 +1,25 @@
+#[cfg(feature = "copy-from")]
+use alloc::vec::Vec;
 use core::{marker::PhantomData, ops::Deref};
 
 use arrayvec::ArrayVec;
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr};
+use memory_addr::{AddrRange, MemoryAddr, PAGE_SIZE_4K, PhysAddr};
 
 use crate::{
-    GenericPTE, MappingFlags, PageSize, PagingError, PagingHandler, PagingMetaData, PagingResult,
-    TlbFlusher,
+    AccessState, FREED_TABLE_THRESHOLD, GenericPTE, MappingFlags, PageSize, PagingError,
+    PagingHandler, PagingMetaData, PagingResult, TlbFlusher, check_paddr, check_vaddr, mark_dirty,
 };
 
-const ENTRY_COUNT: usize = 512;
-
-const fn p4_index(vaddr: usize) -> usize {
-    (vaddr >> (12 + 27)) & (ENTRY_COUNT - 1)
-}
-
-const fn p3_index(vaddr: usize) -> usize {
-    (vaddr >> (12 + 18)) & (ENTRY_COUNT - 1)
-}
-
-const fn p2_index(vaddr: usize) -> usize {
-    (vaddr >> (12 + 9)) & (ENTRY_COUNT - 1)
-}
-
-const fn p1_index(vaddr: usize) -> usize {
-    (vaddr >> 12) & (ENTRY_COUNT - 1)
-}
-
 /// A generic page table struct for 64-bit platform.
 ///
 /// It also tracks all intermediate level tables. They will be deallocated
 /// When the [`PageTable64`] itself is dropped.
 pub struct PageTable64<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> {
     root_paddr: PhysAddr,
+    // The bitmap of root entries borrowed by `copy_from`, allocated on the
+    // first call
     #[cfg(feature = "copy-from")]
-    borrowed_entries: bitmaps::Bitmap<ENTRY_COUNT>,
+    borrowed_entries: Vec<u64>,
     _phantom: PhantomData<(M, PTE, H)>,
 }
 
@@ -42,11 +28,11 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
     ///
     /// It will allocate a new page for the root page table.
     pub fn try_new() -> PagingResult<Self> {
-        let root_paddr = Self::alloc_table()?;
+        let root_paddr = Self::alloc_table(0)?;
         Ok(Self {
             root_paddr,
             #[cfg(feature = "copy-from")]
-            borrowed_entries: bitmaps::Bitmap::new(),
+            borrowed_entries: Vec::new(),
             _phantom: PhantomData,
         })
     }
@@ -62,14 +48,16 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
     /// the page size.
     ///
     /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
-    /// mapping is not present.
+    /// mapping is not present, or
+    /// [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr) if
+    /// `vaddr` is not valid.
     pub fn query(&self, vaddr: M::VirtAddr) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
         let (entry, size) = self.get_entry(vaddr)?;
         if !entry.is_present() {
             return Err(PagingError::NotMapped);
         }
         let off = size.align_offset(vaddr.into());
-        Ok((entry.paddr().add(off), entry.flags(), size))
+        Ok((entry.paddr().align_down(size).add(off), entry.flags(), size))
     }
 
     /// Walk the page table recursively.
@@ -89,7 +77,7 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
         F: Fn(usize, usize, M::VirtAddr, &PTE),
     {
         self.walk_recursive(
-            self.table_of(self.root_paddr()),
+            self.table_of(self.root_paddr(), 0),
             0,
             0.into(),
             limit,
@@ -98,6 +86,35 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
         )
     }
 
+    /// Returns an iterator over the leaf mappings that overlap `range`, in
+    /// ascending order of virtual addresses.
+    ///
+    /// Each item is the start address of the page, the physical address of
+    /// the target frame, the mapping flags, and the page size. The empty
+    /// subtrees are skipped without visiting their entries.
+    ///
+    /// Returns [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr)
+    /// if the range is not valid. The non-canonical hole in the range is
+    /// skipped, and the iteration continues from the first address of the
+    /// upper half (e.g., `0xffff_8000_0000_0000` on x86_64 with 4 levels).
+    pub fn mappings(
+        &self,
+        range: AddrRange<M::VirtAddr>,
+    ) -> PagingResult<impl Iterator<Item = (M::VirtAddr, PhysAddr, MappingFlags, PageSize)> + '_>
+    {
+        let mut vaddr: usize = range.start.into();
+        let end: usize = range.end.into();
+        if !range.is_empty() {
+            check_vaddr::<M>(vaddr)?;
+            check_vaddr::<M>(end - 1)?;
+        }
+        Ok(core::iter::from_fn(move || {
+            let (start, paddr, flags, size) = self.next_mapping(vaddr, end)?;
+            vaddr = start.checked_add(size as usize).unwrap_or(end);
+            Some((start.into(), paddr, flags, size))
+        }))
+    }
+
     /// Gets a cursor to modify the page table.
     ///
     /// The TLB will be flushed automatically when the cursor is dropped.
@@ -108,139 +125,315 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
 
 // Private implements.
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H> {
-    fn alloc_table() -> PagingResult<PhysAddr> {
-        if let Some(paddr) = H::alloc_frame() {
+    fn alloc_table(level: usize) -> PagingResult<PhysAddr> {
+        let frames = Self::table_frames(level);
+        let paddr = if frames == 1 {
+            H::alloc_frame()
+        } else {
+            H::alloc_frames(frames, frames * PAGE_SIZE_4K)
+        };
+        if let Some(paddr) = paddr {
+            check_paddr::<M>(paddr, frames * PAGE_SIZE_4K)
+                .inspect_err(|_| Self::dealloc_table(paddr, level))?;
             let ptr = H::phys_to_virt(paddr).as_mut_ptr();
-            unsafe { core::ptr::write_bytes(ptr, 0, PAGE_SIZE_4K) };
+            unsafe { core::ptr::write_bytes(ptr, 0, frames * PAGE_SIZE_4K) };
             Ok(paddr)
         } else {
             Err(PagingError::NoMemory)
         }
     }
 
-    fn table_of<'a>(&self, paddr: PhysAddr) -> &'a [PTE] {
+    fn dealloc_table(paddr: PhysAddr, level: usize) {
+        let frames = Self::table_frames(level);
+        if frames == 1 {
+            H::dealloc_frame(paddr);
+        } else {
+            H::dealloc_frames(paddr, frames);
+        }
+    }
+
+    fn table_of<'a>(&self, paddr: PhysAddr, level: usize) -> &'a [PTE] {
         let ptr = H::phys_to_virt(paddr).as_ptr() as _;
-        unsafe { core::slice::from_raw_parts(ptr, ENTRY_COUNT) }
+        unsafe { core::slice::from_raw_parts(ptr, Self::entry_count(level)) }
     }
 
-    fn table_of_mut<'a>(&mut self, paddr: PhysAddr) -> &'a mut [PTE] {
+    fn table_of_mut<'a>(&mut self, paddr: PhysAddr, level: usize) -> &'a mut [PTE] {
         let ptr = H::phys_to_virt(paddr).as_mut_ptr() as _;
-        unsafe { core::slice::from_raw_parts_mut(ptr, ENTRY_COUNT) }
+        unsafe { core::slice::from_raw_parts_mut(ptr, Self::entry_count(level)) }
     }
 
-    fn next_table<'a>(&self, entry: &PTE) -> PagingResult<&'a [PTE]> {
+    /// Returns the table referenced by `entry`, which is in a table of the
+    /// given `level`.
+    fn next_table<'a>(&self, entry: &PTE, level: usize) -> PagingResult<&'a [PTE]> {
         if entry.paddr().as_usize() == 0 {
             Err(PagingError::NotMapped)
         } else if entry.is_huge() {
             Err(PagingError::MappedToHugePage)
         } else {
-            Ok(self.table_of(entry.paddr()))
+            Ok(self.table_of(entry.paddr(), level + 1))
         }
     }
 
-    fn next_table_mut<'a>(&mut self, entry: &PTE) -> PagingResult<&'a mut [PTE]> {
+    fn next_table_mut<'a>(&mut self, entry: &PTE, level: usize) -> PagingResult<&'a mut [PTE]> {
         if entry.paddr().as_usize() == 0 {
             Err(PagingError::NotMapped)
         } else if entry.is_huge() {
             Err(PagingError::MappedToHugePage)
         } else {
-            Ok(self.table_of_mut(entry.paddr()))
+            Ok(self.table_of_mut(entry.paddr(), level + 1))
         }
     }
 
-    fn next_table_mut_or_create<'a>(&mut self, entry: &mut PTE) -> PagingResult<&'a mut [PTE]> {
+    fn next_table_mut_or_create<'a>(
+        &mut self,
+        entry: &mut PTE,
+        level: usize,
+    ) -> PagingResult<&'a mut [PTE]> {
         if entry.is_unused() {
-            let paddr = Self::alloc_table()?;
-            *entry = GenericPTE::new_table(paddr);
-            Ok(self.table_of_mut(paddr))
+            let paddr = Self::alloc_table(level + 1)?;
+            *entry = if level == 0 {
+                GenericPTE::new_root_table(paddr)
+            } else {
+                GenericPTE::new_table(paddr)
+            };
+            Ok(self.table_of_mut(paddr, level + 1))
         } else {
-            self.next_table_mut(entry)
+            self.next_table_mut(entry, level)
         }
     }
 
-    fn get_entry(&self, vaddr: M::VirtAddr) -> PagingResult<(&PTE, PageSize)> {
-        let vaddr: usize = vaddr.into();
-        let p3 = if M::LEVELS == 3 {
-            self.table_of(self.root_paddr())
-        } else if M::LEVELS == 4 {
-            let p4 = self.table_of(self.root_paddr());
-            let p4e = &p4[p4_index(vaddr)];
-            self.next_table(p4e)?
+    /// Returns the number of low address bits that are not translated by the
+    /// entries of the given level (level `0` is the root).
+    const fn level_shift(level: usize) -> usize {
+        M::PAGE_SHIFT + (M::LEVELS - 1 - level) * M::INDEX_BITS
+    }
+
+    /// Returns the number of entries in a table of the given level.
+    ///
+    /// The root table translates all the remaining bits of the virtual address,
+    /// so it may be smaller or larger than the tables of other levels.
+    const fn entry_count(level: usize) -> usize {
+        if level == 0 {
+            1 << (M::VA_MAX_BITS - Self::level_shift(0))
         } else {
-            unreachable!()
-        };
-        let p3e = &p3[p3_index(vaddr)];
-        if p3e.is_huge() {
-            return Ok((p3e, PageSize::Size1G));
+            1 << M::INDEX_BITS
+        }
+    }
+
+    /// Returns the number of 4K frames occupied by a table of the given level.
+    const fn table_frames(level: usize) -> usize {
+        let size = Self::entry_count(level) * core::mem::size_of::<PTE>();
+        size.div_ceil(PAGE_SIZE_4K)
+    }
+
+    /// Returns the index of the entry that translates `vaddr` in the table of
+    /// the given level.
+    const fn entry_index(vaddr: usize, level: usize) -> usize {
+        (vaddr >> Self::level_shift(level)) & (Self::entry_count(level) - 1)
+    }
+
+    /// Returns the page size mapped by a leaf entry of the given level, or
+    /// [`None`] if the hardware does not allow leaf entries at this level.
+    fn level_page_size(level: usize) -> Option<PageSize> {
+        let size = 1usize << Self::level_shift(level);
+        M::PAGE_SIZES.iter().copied().find(|s| *s as usize == size)
+    }
+
+    /// Returns the size of pages mapped by the last level entries.
+    const fn base_page_size() -> PageSize {
+        M::PAGE_SIZES[M::PAGE_SIZES.len() - 1]
+    }
+
+    /// Returns the level whose leaf entries map pages of `page_size`, either
+    /// by a single entry or by a run of replicated entries (e.g., the AArch64
+    /// contiguous hint).
+    fn page_size_level(page_size: PageSize) -> PagingResult<usize> {
+        if !M::PAGE_SIZES.contains(&page_size) {
+            return Err(PagingError::UnsupportedPageSize);
         }
+        (0..M::LEVELS)
+            .find(|&level| 1usize << Self::level_shift(level) <= page_size as usize)
+            .ok_or(PagingError::UnsupportedPageSize)
+    }
 
-        let p2 = self.next_table(p3e)?;
-        let p2e = &p2[p2_index(vaddr)];
-        if p2e.is_huge() {
-            return Ok((p2e, PageSize::Size2M));
+    /// Returns the size of the contiguous pages mapped by replicated leaf
+    /// entries of the given level, if supported.
+    fn level_contiguous_size(level: usize) -> Option<PageSize> {
+        M::PAGE_SIZES.iter().copied().find(|&size| {
+            size as usize > 1 << Self::level_shift(level)
+                && Self::page_size_level(size) == Ok(level)
+        })
+    }
+
+    /// Returns the page size mapped by the leaf `entry` of the given level,
+    /// whose size without the contiguous hint is `size`.
+    fn leaf_page_size(entry: &PTE, level: usize, size: PageSize) -> PageSize {
+        if entry.is_contiguous(level < M::LEVELS - 1) {
+            Self::level_contiguous_size(level).unwrap_or(size)
+        } else {
+            size
         }
+    }
 
-        let p1 = self.next_table(p2e)?;
-        let p1e = &p1[p1_index(vaddr)];
-        Ok((p1e, PageSize::Size4K))
+    /// Returns the leaf entries in `table` of the given level that map the
+    /// page of `page_size` containing `vaddr`.
+    fn page_entries(
+        table: &mut [PTE],
+        vaddr: usize,
+        level: usize,
+        page_size: PageSize,
+    ) -> &mut [PTE] {
+        let count = page_size as usize >> Self::level_shift(level);
+        let start = Self::entry_index(vaddr, level) & !(count - 1);
+        &mut table[start..start + count]
     }
 
-    fn get_entry_mut(&mut self, vaddr: M::VirtAddr) -> PagingResult<(&mut PTE, PageSize)> {
+    fn get_entry(&self, vaddr: M::VirtAddr) -> PagingResult<(&PTE, PageSize)> {
         let vaddr: usize = vaddr.into();
-        let p3 = if M::LEVELS == 3 {
-            self.table_of_mut(self.root_paddr())
-        } else if M::LEVELS == 4 {
-            let p4 = self.table_of_mut(self.root_paddr());
-            let p4e = &mut p4[p4_index(vaddr)];
-            self.next_table_mut(p4e)?
-        } else {
-            unreachable!()
-        };
-        let p3e = &mut p3[p3_index(vaddr)];
-        if p3e.is_huge() {
-            return Ok((p3e, PageSize::Size1G));
+        check_vaddr::<M>(vaddr)?;
+        let mut table = self.table_of(self.root_paddr(), 0);
+        for level in 0..M::LEVELS - 1 {
+            let entry = &table[Self::entry_index(vaddr, level)];
+            if entry.is_huge()
+                && let Some(size) = Self::level_page_size(level)
+            {
+                return Ok((entry, Self::leaf_page_size(entry, level, size)));
+            }
+            table = self.next_table(entry, level)?;
         }
+        let entry = &table[Self::entry_index(vaddr, M::LEVELS - 1)];
+        let size = Self::leaf_page_size(entry, M::LEVELS - 1, Self::base_page_size());
+        Ok((entry, size))
+    }
 
-        let p2 = self.next_table_mut(p3e)?;
-        let p2e = &mut p2[p2_index(vaddr)];
-        if p2e.is_huge() {
-            return Ok((p2e, PageSize::Size2M));
+    /// Returns the first leaf mapping that overlaps `[vaddr, end)`, with the
+    /// start address of the page.
+    ///
+    /// The entries that are not present are skipped in the same table, which
+    /// is only walked again from the root after its last entry.
+    fn next_mapping(
+        &self,
+        mut vaddr: usize,
+        end: usize,
+    ) -> Option<(usize, PhysAddr, MappingFlags, PageSize)> {
+        'walk: loop {
+            vaddr = Self::skip_hole(vaddr)?;
+            if vaddr >= end {
+                return None;
+            }
+            let mut table = self.table_of(self.root_paddr(), 0);
+            let mut level = 0;
+            loop {
+                let entry = &table[Self::entry_index(vaddr, level)];
+                if entry.is_present() {
+                    let size = if level == M::LEVELS - 1 {
+                        Some(Self::base_page_size())
+                    } else if entry.is_huge() {
+                        Self::level_page_size(level)
+                    } else {
+                        None
+                    };
+                    if let Some(size) = size {
+                        let size = Self::leaf_page_size(entry, level, size);
+                        let start = vaddr & !(size as usize - 1);
+                        return Some((start, entry.paddr().align_down(size), entry.flags(), size));
+                    }
+                    if let Ok(next) = self.next_table(entry, level) {
+                        table = next;
+                        level += 1;
+                        continue;
+                    }
+                }
+
+                let span = 1usize << Self::level_shift(level);
+                vaddr = (vaddr & !(span - 1)).checked_add(span)?;
+                if vaddr >= end {
+                    return None;
+                } else if Self::entry_index(vaddr, level) == 0 || !M::vaddr_is_valid(vaddr) {
+                    continue 'walk;
+                }
+            }
         }
+    }
 
-        let p1 = self.next_table_mut(p2e)?;
-        let p1e = &mut p1[p1_index(vaddr)];
-        Ok((p1e, PageSize::Size4K))
+    /// Returns `vaddr` if it is valid, or the first address of the upper half
+    /// if `vaddr` is in the non-canonical hole below it.
+    fn skip_hole(vaddr: usize) -> Option<usize> {
+        if M::vaddr_is_valid(vaddr) {
+            return Some(vaddr);
+        }
+        // The upper half starts with the top bits set, above `VA_MAX_BITS`
+        // (e.g., AArch64) or sign extended from the highest bit (e.g., x86_64)
+        [M::VA_MAX_BITS, M::VA_MAX_BITS - 1]
+            .into_iter()
+            .filter_map(|bits| usize::MAX.checked_shl(bits as u32))
+            .find(|&upper| upper > vaddr && M::vaddr_is_valid(upper))
     }
 
-    fn get_entry_mut_or_create(
+    /// Returns the leaf entries that map `vaddr`, which has more than one
+    /// entry for contiguous pages, the page size and the level of entries.
+    fn get_entries_mut(
+        &mut self,
+        vaddr: M::VirtAddr,
+    ) -> PagingResult<(&mut [PTE], PageSize, usize)> {
+        let vaddr: usize = vaddr.into();
+        check_vaddr::<M>(vaddr)?;
+        let mut table = self.table_of_mut(self.root_paddr(), 0);
+        let mut level = 0;
+        let mut size = Self::base_page_size();
+        while level < M::LEVELS - 1 {
+            let entry = &table[Self::entry_index(vaddr, level)];
+            if entry.is_huge()
+                && let Some(level_size) = Self::level_page_size(level)
+            {
+                size = level_size;
+                break;
+            }
+            table = self.next_table_mut(entry, level)?;
+            level += 1;
+        }
+        let size = Self::leaf_page_size(&table[Self::entry_index(vaddr, level)], level, size);
+        Ok((Self::page_entries(table, vaddr, level, size), size, level))
+    }
+
+    /// Returns the leaf entries to map the page of `page_size` at `vaddr`,
+    /// creating the intermediate tables if needed, and the level of entries.
+    fn get_entries_mut_or_create(
         &mut self,
         vaddr: M::VirtAddr,
         page_size: PageSize,
-    ) -> PagingResult<&mut PTE> {
+    ) -> PagingResult<(&mut [PTE], usize)> {
         let vaddr: usize = vaddr.into();
-        let p3 = if M::LEVELS == 3 {
-            self.table_of_mut(self.root_paddr())
-        } else if M::LEVELS == 4 {
-            let p4 = self.table_of_mut(self.root_paddr());
-            let p4e = &mut p4[p4_index(vaddr)];
-            self.next_table_mut_or_create(p4e)?
-        } else {
-            unreachable!()
-        };
-        let p3e = &mut p3[p3_index(vaddr)];
-        if page_size == PageSize::Size1G {
-            return Ok(p3e);
+        check_vaddr::<M>(vaddr)?;
+        let target_level = Self::page_size_level(page_size)?;
+        let mut table = self.table_of_mut(self.root_paddr(), 0);
+        for level in 0..target_level {
+            let entry = &mut table[Self::entry_index(vaddr, level)];
+            table = self.next_table_mut_or_create(entry, level)?;
         }
+        Ok((
+            Self::page_entries(table, vaddr, target_level, page_size),
+            target_level,
+        ))
+    }
 
-        let p2 = self.next_table_mut_or_create(p3e)?;
-        let p2e = &mut p2[p2_index(vaddr)];
-        if page_size == PageSize::Size2M {
-            return Ok(p2e);
+    /// Returns whether the root entry that translates `vaddr` is borrowed from
+    /// another page table by `copy_from`, which owns the tables under it.
+    #[allow(unused_variables)]
+    fn is_borrowed(&self, vaddr: usize) -> bool {
+        #[cfg(feature = "copy-from")]
+        if self.is_borrowed_entry(Self::entry_index(vaddr, 0)) {
+            return true;
         }
+        false
+    }
 
-        let p1 = self.next_table_mut_or_create(p2e)?;
-        let p1e = &mut p1[p1_index(vaddr)];
-        Ok(p1e)
+    /// Returns whether the root entry at `index` is borrowed by `copy_from`.
+    #[cfg(feature = "copy-from")]
+    fn is_borrowed_entry(&self, index: usize) -> bool {
+        self.borrowed_entries
+            .get(index / 64)
+            .is_some_and(|bits| bits & (1 << (index % 64)) != 0)
     }
 
     fn walk_recursive<F>(
@@ -257,7 +450,7 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
         let start_vaddr_usize: usize = start_vaddr.into();
         let mut n = 0;
         for (i, entry) in table.iter().enumerate() {
-            let vaddr_usize = start_vaddr_usize + (i << (12 + (M::LEVELS - 1 - level) * 9));
+            let vaddr_usize = start_vaddr_usize + (i << Self::level_shift(level));
             let vaddr = vaddr_usize.into();
 
             if entry.is_present() {
@@ -266,7 +459,7 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
                 }
                 if level < M::LEVELS - 1
                     && !entry.is_huge()
-                    && let Ok(table) = self.next_table(entry)
+                    && let Ok(table) = self.next_table(entry, level)
                 {
                     self.walk_recursive(table, level + 1, vaddr, limit, pre_func, post_func);
                 }
@@ -284,30 +477,30 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64<M, PTE, H
     fn dealloc_tree(&self, table_paddr: PhysAddr, level: usize) {
         // don't free the entries in last level, they are not array.
         if level < M::LEVELS - 1 {
-            for entry in self.table_of(table_paddr) {
-                if self.next_table(entry).is_ok() {
+            for entry in self.table_of(table_paddr, level) {
+                if self.next_table(entry, level).is_ok() {
                     self.dealloc_tree(entry.paddr(), level + 1);
                 }
             }
         }
-        H::dealloc_frame(table_paddr);
+        Self::dealloc_table(table_paddr, level);
     }
 }
 
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Drop for PageTable64<M, PTE, H> {
     fn drop(&mut self) {
-        let root = self.table_of(self.root_paddr);
+        let root = self.table_of(self.root_paddr, 0);
         #[allow(unused_variables)]
         for (i, entry) in root.iter().enumerate() {
             #[cfg(feature = "copy-from")]
-            if self.borrowed_entries.get(i) {
+            if self.is_borrowed_entry(i) {
                 continue;
             }
-            if self.next_table(entry).is_ok() {
+            if self.next_table(entry, 0).is_ok() {
                 self.dealloc_tree(entry.paddr(), 1);
             }
         }
-        H::dealloc_frame(self.root_paddr());
+        Self::dealloc_table(self.root_paddr(), 0);
     }
 }
 
@@ -315,6 +508,8 @@ impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Drop for PageTable64<
 pub struct PageTable64Cursor<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> {
     inner: &'a mut PageTable64<M, PTE, H>,
     flusher: TlbFlusher<M>,
+    // The unlinked tables and their levels, deallocated after the TLB flush
+    freed: ArrayVec<(PhysAddr, usize), FREED_TABLE_THRESHOLD>,
 }
 
 impl<M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> Deref
@@ -332,6 +527,7 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
         Self {
             inner,
             flusher: TlbFlusher::None,
+            freed: ArrayVec::new(),
         }
     }
 
@@ -359,7 +555,8 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     /// aligned down automatically.
     ///
     /// Returns [`Err(PagingError::AlreadyMapped)`](PagingError::AlreadyMapped)
-    /// if the mapping is already present.
+    /// if the mapping is already present, or an error if `vaddr` or `target`
+    /// is not valid.
     pub fn map(
         &mut self,
         vaddr: M::VirtAddr,
@@ -367,13 +564,23 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
         page_size: PageSize,
         flags: MappingFlags,
     ) -> PagingResult {
-        // `vaddr` does not need to be page-aligned here; `get_entry_mut_or_create`
-        // internally maps `vaddr` to its corresponding page table entry (PTE).
-        let entry = self.inner.get_entry_mut_or_create(vaddr, page_size)?;
-        if !entry.is_unused() {
+        let paddr = target.align_down(page_size);
+        check_paddr::<M>(paddr, page_size as usize)?;
+        // `vaddr` does not need to be page-aligned here; `get_entries_mut_or_create`
+        // internally maps `vaddr` to its corresponding page table entries (PTEs).
+        let (entries, level) = self.inner.get_entries_mut_or_create(vaddr, page_size)?;
+        if entries.iter().any(|entry| !entry.is_unused()) {
             return Err(PagingError::AlreadyMapped);
         }
-        *entry = GenericPTE::new_page(target.align_down(page_size), flags, page_size.is_huge());
+        let is_huge = level < M::LEVELS - 1;
+        if let [entry] = entries {
+            *entry = GenericPTE::new_page(paddr, flags, is_huge);
+        } else {
+            let entry_size = 1 << PageTable64::<M, PTE, H>::level_shift(level);
+            for (i, entry) in entries.iter_mut().enumerate() {
+                *entry = GenericPTE::new_contiguous_page(paddr.add(i * entry_size), flags, is_huge);
+            }
+        }
         self.push(vaddr);
         Ok(())
     }
@@ -384,16 +591,23 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     /// Returns the page size of the mapping.
     ///
     /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
-    /// intermediate level tables of the mapping is not present.
+    /// intermediate level tables of the mapping is not present, or an error if
+    /// `vaddr` or `paddr` is not valid.
     pub fn remap(
         &mut self,
         vaddr: M::VirtAddr,
         paddr: PhysAddr,
         flags: MappingFlags,
     ) -> PagingResult<PageSize> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        entry.set_paddr(paddr);
-        entry.set_flags(flags, size.is_huge());
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        let is_huge = level < M::LEVELS - 1;
+        let paddr = paddr.align_down(size);
+        check_paddr::<M>(paddr, size as usize)?;
+        let entry_size = 1 << PageTable64::<M, PTE, H>::level_shift(level);
+        for (i, entry) in entries.iter_mut().enumerate() {
+            *entry = entry.new_page_like(paddr.add(i * entry_size), is_huge, is_huge);
+            entry.set_flags(flags, is_huge);
+        }
         self.push(vaddr);
         Ok(size)
     }
@@ -405,45 +619,190 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
     /// mapping is not present.
     pub fn protect(&mut self, vaddr: M::VirtAddr, flags: MappingFlags) -> PagingResult<PageSize> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        if !entry.is_present() {
+        let (entries, size, level) = self.inner.get_entries_mut(vaddr)?;
+        if !entries[0].is_present() {
             return Err(PagingError::NotMapped);
         }
-        entry.set_flags(flags, size.is_huge());
+        for entry in entries {
+            entry.set_flags(flags, level < M::LEVELS - 1);
+        }
         self.push(vaddr);
         Ok(size)
     }
 
     /// Unmaps the mapping starting at `vaddr`.
     ///
+    /// The tables that become empty are unlinked, and deallocated after the
+    /// TLB is flushed.
+    ///
     /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
     /// mapping is not present.
     pub fn unmap(
         &mut self,
         vaddr: M::VirtAddr,
     ) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
-        let (entry, size) = self.inner.get_entry_mut(vaddr)?;
-        if !entry.is_present() {
-            entry.clear();
+        let ret = self.unmap_entries(vaddr)?;
+        self.reclaim_tables(vaddr.into());
+        Ok(ret)
+    }
+
+    /// Clears the leaf entries of the mapping starting at `vaddr`, without
+    /// reclaiming the tables.
+    fn unmap_entries(
+        &mut self,
+        vaddr: M::VirtAddr,
+    ) -> PagingResult<(PhysAddr, MappingFlags, PageSize)> {
+        let (entries, size, _) = self.inner.get_entries_mut(vaddr)?;
+        if !entries[0].is_present() {
+            entries.iter_mut().for_each(PTE::clear);
             return Err(PagingError::NotMapped);
         }
-        let paddr = entry.paddr();
-        let flags = entry.flags();
-        entry.clear();
+        let paddr = entries[0].paddr().align_down(size);
+        let flags = entries[0].flags();
+        entries.iter_mut().for_each(PTE::clear);
         self.push(vaddr);
         Ok((paddr, flags, size))
     }
 
+    /// Unlinks the tables that translate `vaddr` if they are empty, from the
+    /// last level up to the root (excluded).
+    ///
+    /// The unlinked tables may still be cached by the TLB (paging-structure
+    /// caches), so they are deallocated after the flush. Some architectures
+    /// (e.g., RISC-V) only invalidate the leaf entries by an address, so the
+    /// whole TLB will be flushed.
+    fn reclaim_tables(&mut self, vaddr: usize) {
+        if self.inner.is_borrowed(vaddr) {
+            return;
+        }
+        loop {
+            // Find the last table and the entry that references it
+            let mut table = self.inner.table_of_mut(self.root_paddr(), 0);
+            let mut parent = None;
+            for level in 0..M::LEVELS - 1 {
+                let index = PageTable64::<M, PTE, H>::entry_index(vaddr, level);
+                match self.inner.next_table_mut(&table[index], level) {
+                    Ok(next) => {
+                        parent = Some((table, index, level + 1));
+                        table = next;
+                    }
+                    Err(_) => break,
+                }
+            }
+            let Some((parent, index, level)) = parent else {
+                return;
+            };
+            if !table.iter().all(PTE::is_unused) {
+                return;
+            }
+            let entry = &mut parent[index];
+            let table_paddr = entry.paddr();
+            entry.clear();
+            self.flusher = TlbFlusher::Full;
+            if self.freed.is_full() {
+                self.flush();
+            }
+            self.freed.push((table_paddr, level));
+        }
+    }
+
+    /// Breaks the contiguous page containing `vaddr` into individual entries,
+    /// if the region `[vaddr, vaddr + size)` only covers part of it.
+    ///
+    /// The architectures (e.g., AArch64) require the replicated entries to be
+    /// consistent, so all of them are invalidated and the TLB is flushed
+    /// before the individual entries are written (break-before-make).
+    fn break_contiguous(&mut self, vaddr: usize, size: usize) {
+        let Ok((entries, page_size, level)) = self.inner.get_entries_mut(vaddr.into()) else {
+            return;
+        };
+        if entries.len() == 1
+            || !entries[0].is_present()
+            || (page_size.is_aligned(vaddr) && size >= page_size as usize)
+        {
+            return;
+        }
+        let paddr = entries[0].paddr().align_down(page_size);
+        let flags = entries[0].flags();
+        let count = entries.len();
+        entries.iter_mut().for_each(PTE::clear);
+
+        let entry_size = 1 << PageTable64::<M, PTE, H>::level_shift(level);
+        let start = vaddr & !(page_size as usize - 1);
+        for i in 0..count {
+            self.push((start + i * entry_size).into());
+        }
+        self.flush();
+
+        let is_huge = level < M::LEVELS - 1;
+        // The intermediate tables are present, nothing will be allocated
+        if let Ok((entries, _)) = self
+            .inner
+            .get_entries_mut_or_create(start.into(), page_size)
+        {
+            for (i, entry) in entries.iter_mut().enumerate() {
+                *entry = GenericPTE::new_page(paddr.add(i * entry_size), flags, is_huge);
+            }
+        }
+    }
+
+    /// Splits the huge page containing `vaddr` into a next level table that
+    /// preserves the mapping, until the page fits in the region
+    /// `[vaddr, vaddr + size)` or is a base page.
+    ///
+    /// Like [`break_contiguous`](Self::break_contiguous), the huge page is
+    /// invalidated and the TLB is flushed before the table is written
+    /// (break-before-make).
+    fn split_huge(&mut self, vaddr: usize, size: usize) -> PagingResult {
+        loop {
+            self.break_contiguous(vaddr, size);
+            let Ok((entries, page_size, level)) = self.inner.get_entries_mut(vaddr.into()) else {
+                return Ok(());
+            };
+            if level == M::LEVELS - 1
+                || !entries[0].is_present()
+                || (page_size.is_aligned(vaddr) && size >= page_size as usize)
+            {
+                return Ok(());
+            }
+            let paddr = entries[0].paddr().align_down(page_size);
+            let huge_entry = entries[0];
+
+            let table_paddr = PageTable64::<M, PTE, H>::alloc_table(level + 1)?;
+            let entry_size = 1 << PageTable64::<M, PTE, H>::level_shift(level + 1);
+            let is_huge = level + 1 < M::LEVELS - 1;
+            let table = self.inner.table_of_mut(table_paddr, level + 1);
+            for (i, entry) in table.iter_mut().enumerate() {
+                *entry = huge_entry.new_page_like(paddr.add(i * entry_size), true, is_huge);
+            }
+
+            let start = vaddr - page_size.align_offset(vaddr);
+            let (entries, ..) = self.inner.get_entries_mut(vaddr.into())?;
+            entries[0].clear();
+            self.push(start.into());
+            self.flush();
+            // The intermediate tables are present, nothing will be allocated
+            let (entries, _) = self
+                .inner
+                .get_entries_mut_or_create(start.into(), page_size)?;
+            entries[0] = if level == 0 {
+                GenericPTE::new_root_table(table_paddr)
+            } else {
+                GenericPTE::new_table(table_paddr)
+            };
+        }
+    }
+
     /// Maps a contiguous virtual memory region to a contiguous physical memory
     /// region with the given mapping `flags`.
     ///
     /// The virtual and physical memory regions start at `vaddr` and `paddr`
     /// respectively. The region size is `size`. The addresses and `size` must
-    /// be aligned to 4K, otherwise it will return
-    /// [`Err(PagingError::NotAligned)`].
+    /// be aligned to the base page size (4K in most cases), otherwise it will
+    /// return [`Err(PagingError::NotAligned)`].
     ///
     /// When `allow_huge` is true, it will try to map the region with huge pages
-    /// if possible. Otherwise, it will map the region with 4K pages.
+    /// if possible. Otherwise, it will map the region with base pages.
     ///
     /// [`Err(PagingError::NotAligned)`]: PagingError::NotAligned
     pub fn map_region(
@@ -456,7 +815,8 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     ) -> PagingResult {
         let mut vaddr_usize: usize = vaddr.into();
         let mut size = size;
-        if !PageSize::Size4K.is_aligned(vaddr_usize) || !PageSize::Size4K.is_aligned(size) {
+        let base_page_size = PageTable64::<M, PTE, H>::base_page_size();
+        if !base_page_size.is_aligned(vaddr_usize) || !base_page_size.is_aligned(size) {
             return Err(PagingError::NotAligned);
         }
         trace!(
@@ -470,21 +830,17 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
             let vaddr = vaddr_usize.into();
             let paddr = get_paddr(vaddr);
             let page_size = if allow_huge {
-                if PageSize::Size1G.is_aligned(vaddr_usize)
-                    && paddr.is_aligned(PageSize::Size1G)
-                    && size >= PageSize::Size1G as usize
-                {
-                    PageSize::Size1G
-                } else if PageSize::Size2M.is_aligned(vaddr_usize)
-                    && paddr.is_aligned(PageSize::Size2M)
-                    && size >= PageSize::Size2M as usize
-                {
-                    PageSize::Size2M
-                } else {
-                    PageSize::Size4K
-                }
+                M::PAGE_SIZES
+                    .iter()
+                    .copied()
+                    .find(|&page_size| {
+                        page_size.is_aligned(vaddr_usize)
+                            && paddr.is_aligned(page_size)
+                            && size >= page_size as usize
+                    })
+                    .unwrap_or(base_page_size)
             } else {
-                PageSize::Size4K
+                base_page_size
             };
             self.map(vaddr, paddr, page_size, flags).inspect_err(|e| {
                 error!("failed to map page: {vaddr_usize:#x?}({page_size:?}) -> {paddr:#x?}, {e:?}")
@@ -499,8 +855,10 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     /// Unmaps a contiguous virtual memory region.
     ///
     /// The region must be mapped before using [`Self::map_region`], or
-    /// unexpected behaviors may occur. It can deal with huge pages
-    /// automatically.
+    /// unexpected behaviors may occur. The huge pages that are partially
+    /// covered by the region are split into smaller pages first, which
+    /// allocates the next level tables. The tables that become empty are
+    /// unlinked, and deallocated after the TLB is flushed.
     pub fn unmap_region(&mut self, vaddr: M::VirtAddr, size: usize) -> PagingResult {
         let mut vaddr_usize: usize = vaddr.into();
         let mut size = size;
@@ -510,16 +868,24 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
             vaddr_usize,
             vaddr_usize + size,
         );
+        // The span of the last level tables
+        let table_span = 1 << PageTable64::<M, PTE, H>::level_shift(M::LEVELS - 2);
         while size > 0 {
             let vaddr = vaddr_usize.into();
+            self.split_huge(vaddr_usize, size)
+                .inspect_err(|e| error!("failed to split page: {vaddr_usize:#x?}, {e:?}"))?;
             let (_, _, page_size) = self
-                .unmap(vaddr)
+                .unmap_entries(vaddr)
                 .inspect_err(|e| error!("failed to unmap page: {vaddr_usize:#x?}, {e:?}"))?;
 
             assert!(page_size.is_aligned(vaddr_usize));
             assert!(page_size as usize <= size);
             vaddr_usize += page_size as usize;
             size -= page_size as usize;
+            // Reclaim the tables once, after their last entries in the region
+            if size == 0 || vaddr_usize & (table_span - 1) == 0 {
+                self.reclaim_tables(vaddr.into());
+            }
         }
         Ok(())
     }
@@ -527,8 +893,9 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
     /// Updates mapping flags of a contiguous virtual memory region.
     ///
     /// The region must be mapped before using [`Self::map_region`], or
-    /// unexpected behaviors may occur. It can deal with huge pages
-    /// automatically.
+    /// unexpected behaviors may occur. The huge pages that are partially
+    /// covered by the region are split into smaller pages first, which
+    /// allocates the next level tables.
     pub fn protect_region(
         &mut self,
         vaddr: M::VirtAddr,
@@ -546,17 +913,21 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
         );
         while size > 0 {
             let vaddr = vaddr_usize.into();
-            let page_size = match self.inner.get_entry_mut(vaddr) {
-                Ok((entry, page_size)) => {
-                    if entry.is_present() {
-                        entry.set_flags(flags, page_size.is_huge());
+            self.split_huge(vaddr_usize, size)
+                .inspect_err(|e| error!("failed to split page: {vaddr_usize:#x?}, {e:?}"))?;
+            let page_size = match self.inner.get_entries_mut(vaddr) {
+                Ok((entries, page_size, level)) => {
+                    if entries[0].is_present() {
+                        for entry in entries {
+                            entry.set_flags(flags, level < M::LEVELS - 1);
+                        }
                         self.push(vaddr);
                     }
                     // ignore if not present
 
                     page_size
                 }
-                Err(PagingError::NotMapped) => PageSize::Size4K,
+                Err(PagingError::NotMapped) => PageTable64::<M, PTE, H>::base_page_size(),
                 Err(e) => {
                     error!("failed to protect page: {vaddr_usize:#x?}, {e:?}");
                     return Err(e);
@@ -571,38 +942,389 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
         Ok(())
     }
 
+    /// Collapses the table referenced by the entry of the given `level` that
+    /// translates `vaddr` into a huge page, if the table maps physically
+    /// contiguous pages with identical flags. `f` is called with the start
+    /// address and the size of the huge page.
+    ///
+    /// Returns whether the entry maps a huge page afterwards.
+    fn collapse_table(
+        &mut self,
+        vaddr: usize,
+        level: usize,
+        f: &mut impl FnMut(M::VirtAddr, PageSize),
+    ) -> PagingResult<bool> {
+        check_vaddr::<M>(vaddr)?;
+        let Some(page_size) = PageTable64::<M, PTE, H>::level_page_size(level) else {
+            return Ok(false);
+        };
+        if self.inner.is_borrowed(vaddr) {
+            return Ok(false);
+        }
+        let mut table = self.inner.table_of_mut(self.root_paddr(), 0);
+        for level in 0..level {
+            let entry = &table[PageTable64::<M, PTE, H>::entry_index(vaddr, level)];
+            match self.inner.next_table_mut(entry, level) {
+                Ok(next) => table = next,
+                Err(_) => return Ok(false),
+            }
+        }
+        let entry = &mut table[PageTable64::<M, PTE, H>::entry_index(vaddr, level)];
+        if !entry.is_present() {
+            return Ok(false);
+        } else if entry.is_huge() {
+            return Ok(true);
+        }
+
+        let table_paddr = entry.paddr();
+        let entries = self.inner.table_of(table_paddr, level + 1);
+        let entry_size = 1 << PageTable64::<M, PTE, H>::level_shift(level + 1);
+        let Some(entry_page_size) = PageTable64::<M, PTE, H>::level_page_size(level + 1) else {
+            return Ok(false);
+        };
+        let is_huge = level + 1 < M::LEVELS - 1;
+        // The physical address of the part of a contiguous page mapped by `entry`
+        let entry_paddr = |i: usize, entry: &PTE| {
+            let size = PageTable64::<M, PTE, H>::leaf_page_size(entry, level + 1, entry_page_size);
+            entry
+                .paddr()
+                .align_down(size)
+                .add(i * entry_size % size as usize)
+        };
+        let paddr = entry_paddr(0, &entries[0]);
+        // Compare the attributes of the entries as if they mapped the same page
+        let like = |entry: &PTE| entry.new_page_like(paddr, is_huge, is_huge).bits();
+        let first = like(&entries[0]);
+        if !page_size.is_aligned(paddr.as_usize())
+            || !entries.iter().enumerate().all(|(i, entry)| {
+                entry.is_present()
+                    && (!is_huge || entry.is_huge())
+                    && like(entry) == first
+                    && entry_paddr(i, entry) == paddr.add(i * entry_size)
+            })
+        {
+            return Ok(false);
+        }
+
+        let start = vaddr & !(page_size as usize - 1);
+        entry.clear();
+        for i in 0..entries.len() {
+            self.push((start + i * entry_size).into());
+        }
+        self.flush();
+        *entry = entries[0].new_page_like(paddr, is_huge, true);
+        PageTable64::<M, PTE, H>::dealloc_table(table_paddr, level + 1);
+        f(start.into(), page_size);
+        Ok(true)
+    }
+
+    /// Collapses the tables in a virtual memory region that map physically
+    /// contiguous pages with identical flags into huge pages, and deallocates
+    /// them.
+    ///
+    /// Only the huge pages fully covered by the region are created. The
+    /// tables of huge pages are collapsed again into larger pages if possible
+    /// (e.g., 512 2M pages into a 1G page). The accessed and dirty state of
+    /// the collapsed pages is not preserved, harvest it before collapsing if
+    /// needed.
+    ///
+    /// `f` is called with the start address and the page size of each huge
+    /// page created.
+    pub fn collapse_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        mut f: impl FnMut(M::VirtAddr, PageSize),
+    ) -> PagingResult {
+        let start: usize = vaddr.into();
+        trace!(
+            "collapse_region({:#x}) [{:#x}, {:#x})",
+            self.root_paddr(),
+            start,
+            start + size,
+        );
+        let block = 1usize << PageTable64::<M, PTE, H>::level_shift(M::LEVELS - 2);
+        let mut offset = block.wrapping_sub(start) & (block - 1);
+        while offset < size && size - offset >= block {
+            let vaddr_usize = start + offset;
+            let mut level = M::LEVELS - 2;
+            while self
+                .collapse_table(vaddr_usize, level, &mut f)
+                .inspect_err(|e| error!("failed to collapse page: {vaddr_usize:#x?}, {e:?}"))?
+                && level > 0
+            {
+                // Collapse the upper table after its last block is collapsed
+                level -= 1;
+                let upper = 1usize << PageTable64::<M, PTE, H>::level_shift(level);
+                let upper_start = vaddr_usize & !(upper - 1);
+                if (vaddr_usize & (upper - 1)) + block != upper
+                    || upper_start < start
+                    || size - (upper_start - start) < upper
+                {
+                    break;
+                }
+            }
+            offset += block;
+        }
+        Ok(())
+    }
+
+    /// Harvests the accessed and dirty state of the mapping starting at
+    /// `vaddr`, clearing the state in `clear` atomically.
+    ///
+    /// Returns the state before clearing and the page size of the mapping.
+    /// The TLB flush is recorded if any state in `clear` is cleared.
+    ///
+    /// Returns [`Err(PagingError::NotMapped)`](PagingError::NotMapped) if the
+    /// mapping is not present.
+    pub fn harvest(
+        &mut self,
+        vaddr: M::VirtAddr,
+        clear: AccessState,
+    ) -> PagingResult<(AccessState, PageSize)> {
+        let (entries, size, _) = self.inner.get_entries_mut(vaddr)?;
+        if !entries[0].is_present() {
+            return Err(PagingError::NotMapped);
+        }
+        // The hardware may update any entry of a contiguous page.
+        let state = entries
+            .iter_mut()
+            .fold(AccessState::empty(), |state, entry| {
+                state | entry.clear_access_state(clear)
+            });
+        if state.intersects(clear) {
+            self.push(vaddr);
+        }
+        Ok((state, size))
+    }
+
+    /// Harvests the accessed and dirty state of the mappings in a virtual
+    /// memory region, clearing the state in `clear` atomically.
+    ///
+    /// `f` is called with the start address, the page size and the state
+    /// before clearing of each page that has been accessed or written. The
+    /// pages partially covered by the region are harvested as a whole, and
+    /// the unmapped pages are skipped. The TLB flushes are batched until
+    /// [`flush`](Self::flush) is called or the cursor is dropped.
+    pub fn harvest_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        clear: AccessState,
+        mut f: impl FnMut(M::VirtAddr, PageSize, AccessState),
+    ) -> PagingResult {
+        let mut vaddr_usize: usize = vaddr.into();
+        let mut size = size;
+        trace!(
+            "harvest_region({:#x}) [{:#x}, {:#x}) {:?}",
+            self.root_paddr(),
+            vaddr_usize,
+            vaddr_usize + size,
+            clear,
+        );
+        while size > 0 {
+            let page_size = match self.harvest(vaddr_usize.into(), clear) {
+                Ok((state, page_size)) => {
+                    if !state.is_empty() {
+                        let start = vaddr_usize - page_size.align_offset(vaddr_usize);
+                        f(start.into(), page_size, state);
+                    }
+                    page_size
+                }
+                Err(PagingError::NotMapped) => PageTable64::<M, PTE, H>::base_page_size(),
+                Err(e) => {
+                    error!("failed to harvest page: {vaddr_usize:#x?}, {e:?}");
+                    return Err(e);
+                }
+            };
+
+            let step = page_size as usize - page_size.align_offset(vaddr_usize);
+            if step >= size {
+                break;
+            }
+            vaddr_usize += step;
+            size -= step;
+        }
+        Ok(())
+    }
+
+    /// Write-protects the writable pages in a virtual memory region, `f` is
+    /// called with the start address and the page size of each of them.
+    fn write_protect_region(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        mut f: impl FnMut(usize, PageSize),
+    ) -> PagingResult {
+        let mut vaddr_usize: usize = vaddr.into();
+        let mut size = size;
+        while size > 0 {
+            let vaddr = vaddr_usize.into();
+            let page_size = match self.inner.get_entries_mut(vaddr) {
+                Ok((entries, page_size, level)) => {
+                    let flags = entries[0].flags();
+                    if entries[0].is_present() && flags.contains(MappingFlags::WRITE) {
+                        for entry in entries {
+                            entry.set_flags(flags - MappingFlags::WRITE, level < M::LEVELS - 1);
+                        }
+                        self.push(vaddr);
+                        f(vaddr_usize - page_size.align_offset(vaddr_usize), page_size);
+                    }
+                    page_size
+                }
+                Err(PagingError::NotMapped) => PageTable64::<M, PTE, H>::base_page_size(),
+                Err(e) => {
+                    error!("failed to write-protect page: {vaddr_usize:#x?}, {e:?}");
+                    return Err(e);
+                }
+            };
+
+            let step = page_size as usize - page_size.align_offset(vaddr_usize);
+            if step >= size {
+                break;
+            }
+            vaddr_usize += step;
+            size -= step;
+        }
+        Ok(())
+    }
+
+    /// Starts dirty-page logging in a virtual memory region.
+    ///
+    /// If the dirty state is recorded by the hardware (see
+    /// [`GenericPTE::ACCESS_STATE`]), it is cleared in the mapped pages.
+    /// Otherwise, the writable pages are write-protected. In both cases, if a
+    /// write to a clean page raises a page fault, the fault handler should
+    /// restore the mapping flags with [`protect`](Self::protect), which makes
+    /// the page dirty.
+    ///
+    /// The region must be aligned to the base page size, otherwise it will
+    /// return [`Err(PagingError::NotAligned)`](PagingError::NotAligned). The
+    /// huge pages are logged as a whole.
+    pub fn start_dirty_log(&mut self, vaddr: M::VirtAddr, size: usize) -> PagingResult {
+        let base_page_size = PageTable64::<M, PTE, H>::base_page_size();
+        if !base_page_size.is_aligned(vaddr.into()) || !base_page_size.is_aligned(size) {
+            return Err(PagingError::NotAligned);
+        }
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            self.harvest_region(vaddr, size, AccessState::DIRTY, |_, _, _| {})
+        } else {
+            self.write_protect_region(vaddr, size, |_, _| {})
+        }
+    }
+
+    /// Fetches the pages in a virtual memory region that have been written
+    /// since the dirty-page logging was started or last fetched, and makes
+    /// them clean again.
+    ///
+    /// Bit `i` of `bitmap` (bit `i % usize::BITS` of `bitmap[i / usize::BITS]`)
+    /// is set if the base page at `vaddr + i * base_page_size` is dirty, the
+    /// other bits are left unchanged. All base pages of a dirty huge page are
+    /// reported.
+    ///
+    /// The region must be aligned to the base page size, otherwise it will
+    /// return [`Err(PagingError::NotAligned)`](PagingError::NotAligned).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bitmap` has fewer bits than the base pages in the region.
+    pub fn fetch_dirty_log(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        bitmap: &mut [usize],
+    ) -> PagingResult {
+        let base_page_size = PageTable64::<M, PTE, H>::base_page_size();
+        let vaddr_usize: usize = vaddr.into();
+        if !base_page_size.is_aligned(vaddr_usize) || !base_page_size.is_aligned(size) {
+            return Err(PagingError::NotAligned);
+        }
+        assert!(bitmap.len() * usize::BITS as usize >= size / base_page_size as usize);
+        let mut mark = |page, page_size| {
+            mark_dirty(bitmap, vaddr_usize, size, base_page_size, page, page_size)
+        };
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            self.harvest_region(vaddr, size, AccessState::DIRTY, |page, page_size, state| {
+                if state.contains(AccessState::DIRTY) {
+                    mark(page.into(), page_size);
+                }
+            })
+        } else {
+            // the pages made writable by the page fault handler are dirty
+            self.write_protect_region(vaddr, size, mark)
+        }
+    }
+
+    /// Stops dirty-page logging in a virtual memory region.
+    ///
+    /// If the dirty state is not recorded by the hardware, the mapping flags
+    /// of the region are restored to `flags`, which makes the write-protected
+    /// pages writable again. Otherwise, nothing needs to be done and `flags`
+    /// is ignored.
+    pub fn stop_dirty_log(
+        &mut self,
+        vaddr: M::VirtAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> PagingResult {
+        if PTE::ACCESS_STATE.contains(AccessState::DIRTY) {
+            Ok(())
+        } else {
+            self.protect_region(vaddr, size, flags)
+        }
+    }
+
     /// Copy entries from another page table within the given virtual memory
     /// range.
+    ///
+    /// Returns [`Err(PagingError::InvalidVirtAddr)`](PagingError::InvalidVirtAddr)
+    /// if the range is not valid.
     #[cfg(feature = "copy-from")]
-    pub fn copy_from(&mut self, other: &PageTable64<M, PTE, H>, start: M::VirtAddr, size: usize) {
+    pub fn copy_from(
+        &mut self,
+        other: &PageTable64<M, PTE, H>,
+        start: M::VirtAddr,
+        size: usize,
+    ) -> PagingResult {
         if size == 0 {
-            return;
+            return Ok(());
         }
-        let src_table = self.table_of(other.root_paddr);
+        let start: usize = start.into();
+        let last = start
+            .checked_add(size - 1)
+            .ok_or(PagingError::InvalidVirtAddr)?;
+        check_vaddr::<M>(start)?;
+        check_vaddr::<M>(last)?;
+        let src_table = self.table_of(other.root_paddr, 0);
         let root_paddr = self.root_paddr;
-        let dst_table = self.inner.table_of_mut(root_paddr);
-        let index_fn = if M::LEVELS == 3 {
-            p3_index
-        } else if M::LEVELS == 4 {
-            p4_index
-        } else {
-            unreachable!()
-        };
-        let start_idx = index_fn(start.into());
-        let end_idx = index_fn(start.into() + size - 1) + 1;
-        assert!(start_idx < ENTRY_COUNT);
-        assert!(end_idx <= ENTRY_COUNT);
+        let dst_table = self.inner.table_of_mut(root_paddr, 0);
+        let start_idx = PageTable64::<M, PTE, H>::entry_index(start, 0);
+        let end_idx = PageTable64::<M, PTE, H>::entry_index(last, 0) + 1;
+        let entry_count = PageTable64::<M, PTE, H>::entry_count(0);
+        assert!(start_idx < entry_count);
+        assert!(end_idx <= entry_count);
+        if self.inner.borrowed_entries.is_empty() {
+            self.inner
+                .borrowed_entries
+                .resize(entry_count.div_ceil(64), 0);
+        }
         for i in start_idx..end_idx {
             let entry = &mut dst_table[i];
-            if !self.inner.borrowed_entries.set(i, true) && self.next_table(entry).is_ok() {
-                self.dealloc_tree(entry.paddr(), 1);
+            if !self.inner.is_borrowed_entry(i) {
+                self.inner.borrowed_entries[i / 64] |= 1 << (i % 64);
+                if self.next_table(entry, 0).is_ok() {
+                    self.dealloc_tree(entry.paddr(), 1);
+                }
             }
             *entry = src_table[i];
         }
         self.flusher = TlbFlusher::Full;
+        Ok(())
     }
 
-    /// Flushes the TLB according to the recorded flush requests.
+    /// Flushes the TLB according to the recorded flush requests, then
+    /// deallocates the tables unlinked by [`unmap`](Self::unmap) and
+    /// [`unmap_region`](Self::unmap_region).
     pub fn flush(&mut self) {
         #[cfg(not(docsrs))]
         match &self.flusher {
@@ -617,6 +1339,9 @@ impl<'a, M: PagingMetaData, PTE: GenericPTE, H: PagingHandler> PageTable64Cursor
             }
         }
         self.flusher = TlbFlusher::None;
+        for (paddr, level) in self.freed.drain(..) {
+            PageTable64::<M, PTE, H>::dealloc_table(paddr, level);
+        }
     }
 }
 
@@ -4,6 +4,8 @@
 
 #[macro_use]
 extern crate log;
+#[cfg(feature = "copy-from")]
+extern crate alloc;
 
 mod arch;
 #[cfg(any(target_pointer_width = "32", doc, docsrs))]
@@ -16,7 +18,7 @@ use core::fmt::Debug;
 use arrayvec::ArrayVec;
 use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr};
 #[doc(no_inline)]
-pub use page_table_entry::{GenericPTE, MappingFlags};
+pub use page_table_entry::{AccessState, GenericLargePTE, GenericPTE, MappingFlags, PhysAddr64};
 
 #[cfg(any(target_pointer_width = "32", doc, docsrs))]
 pub use self::{
@@ -29,6 +31,16 @@ pub use self::{
     bits64::{PageTable64, PageTable64Cursor},
 };
 
+memory_addr::def_usize_addr! {
+    /// A guest physical address, which is translated by the stage-2 or nested
+    /// page tables of hypervisors.
+    pub type GuestPhysAddr;
+}
+
+memory_addr::def_usize_addr_formatter! {
+    GuestPhysAddr = "GPA:{}";
+}
+
 /// The error type for page table operation failures.
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum PagingError {
@@ -43,6 +55,14 @@ pub enum PagingError {
     /// The page table entry represents a huge page, but the target physical
     /// frame is 4K in size.
     MappedToHugePage,
+    /// The page size is not supported by the hardware page table.
+    UnsupportedPageSize,
+    /// The virtual address can not be translated by the page table, see
+    /// [`PagingMetaData::vaddr_is_valid`].
+    InvalidVirtAddr,
+    /// The physical address can not be referenced by the page table, see
+    /// [`PagingMetaData::paddr_is_valid`].
+    InvalidPhysAddr,
 }
 
 #[cfg(feature = "axerrno")]
@@ -69,7 +89,33 @@ pub trait PagingMetaData: Sync + Send {
     const VA_MAX_BITS: usize;
 
     /// The maximum physical address.
-    const PA_MAX_ADDR: usize = (1 << Self::PA_MAX_BITS) - 1;
+    const PA_MAX_ADDR: usize = usize::MAX >> (usize::BITS as usize - Self::PA_MAX_BITS);
+
+    /// The number of bits of the base page size (i.e., the translation
+    /// granule), e.g., `12` for 4K pages.
+    const PAGE_SHIFT: usize = 12;
+
+    /// The number of virtual address bits used to index a non-root table.
+    ///
+    /// The root table is indexed by all the remaining bits below
+    /// [`VA_MAX_BITS`](Self::VA_MAX_BITS), so its size may differ from the
+    /// other tables.
+    const INDEX_BITS: usize = 9;
+
+    /// The page sizes that can be mapped by a leaf entry, in descending order.
+    ///
+    /// The last one must be the base page size (`1 << PAGE_SHIFT`).
+    /// [`PageTable64Cursor::map_region`] tries them in order when huge pages
+    /// are allowed.
+    const PAGE_SIZES: &'static [PageSize] = &[PageSize::Size1G, PageSize::Size2M, PageSize::Size4K];
+
+    /// Whether the root entries are cached by the hardware when the root table
+    /// is installed, e.g., the four PDPTEs of x86 PAE paging that are loaded
+    /// on `CR3` writes.
+    ///
+    /// If so, the next level tables of all root entries are allocated with the
+    /// page table, and the root entries are never changed afterwards.
+    const FIXED_ROOT_ENTRIES: bool = false;
 
     /// The virtual address to be translated in this page table.
     ///
@@ -79,12 +125,18 @@ pub trait PagingMetaData: Sync + Send {
     // (^)it can be converted from/to usize and it's trivially copyable
 
     /// Whether a given physical address is valid.
+    ///
+    /// It is checked for the mapped frames and the allocated tables, the
+    /// operations return [`PagingError::InvalidPhysAddr`] if not.
     #[inline]
     fn paddr_is_valid(paddr: usize) -> bool {
         paddr <= Self::PA_MAX_ADDR // default
     }
 
     /// Whether a given virtual address is valid.
+    ///
+    /// It is checked for the virtual addresses passed to the page table, the
+    /// operations return [`PagingError::InvalidVirtAddr`] if not.
     #[inline]
     fn vaddr_is_valid(vaddr: usize) -> bool {
         // default: top bits sign extended
@@ -125,23 +177,43 @@ pub trait PagingHandler: Sized {
 }
 
 /// The page sizes supported by the hardware page table.
+#[allow(clippy::enum_clike_unportable_variant)] // `Size512G` is 64-bit only
 #[repr(usize)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum PageSize {
     /// Size of 4 kilobytes (2<sup>12</sup> bytes).
-    Size4K = 0x1000,
+    Size4K   = 0x1000,
+    /// Size of 16 kilobytes (2<sup>14</sup> bytes).
+    Size16K  = 0x4000,
+    /// Size of 64 kilobytes (2<sup>16</sup> bytes).
+    Size64K  = 0x1_0000,
     /// Size of 1 megabytes (2<sup>20</sup> bytes).
-    Size1M = 0x10_0000,
+    Size1M   = 0x10_0000,
     /// Size of 2 megabytes (2<sup>21</sup> bytes).
-    Size2M = 0x20_0000,
+    Size2M   = 0x20_0000,
+    /// Size of 4 megabytes (2<sup>22</sup> bytes).
+    Size4M   = 0x40_0000,
+    /// Size of 16 megabytes (2<sup>24</sup> bytes).
+    Size16M  = 0x100_0000,
+    /// Size of 32 megabytes (2<sup>25</sup> bytes).
+    Size32M  = 0x200_0000,
+    /// Size of 512 megabytes (2<sup>29</sup> bytes).
+    Size512M = 0x2000_0000,
     /// Size of 1 gigabytes (2<sup>30</sup> bytes).
-    Size1G = 0x4000_0000,
+    Size1G   = 0x4000_0000,
+    /// Size of 512 gigabytes (2<sup>39</sup> bytes).
+    #[cfg(target_pointer_width = "64")]
+    Size512G = 0x80_0000_0000,
 }
 
 impl PageSize {
     /// Whether this page size is considered huge (larger than 4K).
+    ///
+    /// Note that on page tables with a larger translation granule (e.g.,
+    /// AArch64 with 16K or 64K granules), the base page is also larger than
+    /// 4K.
     pub const fn is_huge(self) -> bool {
-        matches!(self, Self::Size1G | Self::Size2M | Self::Size1M)
+        !matches!(self, Self::Size4K)
     }
 
     /// Checks whether a given address or size is aligned to the page size.
@@ -162,9 +234,51 @@ impl From<PageSize> for usize {
     }
 }
 
+/// Checks whether `vaddr` can be translated by the page table of `M`.
+fn check_vaddr<M: PagingMetaData>(vaddr: usize) -> PagingResult {
+    if M::vaddr_is_valid(vaddr) {
+        Ok(())
+    } else {
+        Err(PagingError::InvalidVirtAddr)
+    }
+}
+
+/// Checks whether the physical region `[paddr, paddr + size)` can be
+/// referenced by the page table of `M`.
+fn check_paddr<M: PagingMetaData>(paddr: PhysAddr, size: usize) -> PagingResult {
+    let start = paddr.as_usize();
+    match start.checked_add(size - 1) {
+        Some(end) if M::paddr_is_valid(start) && M::paddr_is_valid(end) => Ok(()),
+        _ => Err(PagingError::InvalidPhysAddr),
+    }
+}
+
+/// Sets the bits in the dirty `bitmap` of the region `[vaddr, vaddr + size)`
+/// for the base pages that are covered by both the region and the page of
+/// `page_size` at `page`.
+fn mark_dirty(
+    bitmap: &mut [usize],
+    vaddr: usize,
+    size: usize,
+    base_page_size: PageSize,
+    page: usize,
+    page_size: PageSize,
+) {
+    let start = page.max(vaddr);
+    let len = (page_size as usize - (start - page)).min(size - (start - vaddr));
+    let first = (start - vaddr) / base_page_size as usize;
+    for i in first..first + len / base_page_size as usize {
+        bitmap[i / usize::BITS as usize] |= 1 << (i % usize::BITS as usize);
+    }
+}
+
 // TODO: tune threshold; employ a more advanced data structure
 const SMALL_FLUSH_THRESHOLD: usize = 32;
 
+/// The maximum number of unlinked tables whose deallocation is deferred until
+/// the TLB is flushed.
+const FREED_TABLE_THRESHOLD: usize = 16;
+
 enum TlbFlusher<M: PagingMetaData> {
     None,
     Array(ArrayVec<M::VirtAddr, SMALL_FLUSH_THRESHOLD>),
@@ -5,8 +5,8 @@ use std::{
     marker::PhantomData,
 };
 
-use memory_addr::{PhysAddr, VirtAddr};
-use page_table_entry::{GenericPTE, MappingFlags};
+use memory_addr::{AddrRange, MemoryAddr, PhysAddr, VirtAddr};
+use page_table_entry::{AccessState, GenericPTE, MappingFlags};
 use page_table_multiarch::{PageSize, PageTable64, PagingHandler, PagingMetaData, PagingResult};
 use rand::{RngExt, SeedableRng, rngs::SmallRng};
 
@@ -34,10 +34,6 @@ struct TrackPagingHandler<M: PagingMetaData>(PhantomData<M>);
 impl<M: PagingMetaData> PagingHandler for TrackPagingHandler<M> {
     fn alloc_frame() -> Option<PhysAddr> {
         let ptr = unsafe { alloc::alloc(PAGE_LAYOUT) } as usize;
-        assert!(
-            ptr <= M::PA_MAX_ADDR,
-            "allocated frame address exceeds PA_MAX_ADDR"
-        );
         ALLOCATED.with_borrow_mut(|it| it.insert(ptr));
         Some(PhysAddr::from_usize(ptr))
     }
@@ -45,10 +41,6 @@ impl<M: PagingMetaData> PagingHandler for TrackPagingHandler<M> {
     fn alloc_frames(num: usize, align: usize) -> Option<PhysAddr> {
         let layout = pages_layout(num, align);
         let ptr = unsafe { alloc::alloc(layout) } as usize;
-        assert!(
-            ptr <= M::PA_MAX_ADDR,
-            "allocated frame address exceeds PA_MAX_ADDR"
-        );
         ALLOCATED.with_borrow_mut(|it| {
             for i in 0..num {
                 it.insert(ptr + i * 4096);
@@ -94,12 +86,14 @@ impl<M: PagingMetaData> PagingHandler for TrackPagingHandler<M> {
     }
 }
 
-fn run_test_for<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>() -> PagingResult<()> {
+fn run_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
     ALLOCATED.with_borrow_mut(|it| {
         it.clear();
     });
 
-    let vaddr_mask = ((1u64 << M::VA_MAX_BITS) - 1) & !0xfff;
+    let base_page_size: PageSize = *M::PAGE_SIZES.last().unwrap();
+    let vaddr_mask = ((1u64 << M::VA_MAX_BITS) - 1) & !(base_page_size as u64 - 1);
+    let paddr_mask = ((1u64 << M::PA_MAX_BITS) - 1) & !(base_page_size as u64 - 1);
 
     let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
     let mut pages = HashSet::new();
@@ -111,20 +105,20 @@ fn run_test_for<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>() -> Pa
             // insert a mapping
             let addr = loop {
                 let addr = rng.random::<u64>() & vaddr_mask;
-                if pages.insert(addr) {
+                if M::vaddr_is_valid(addr as usize) && pages.insert(addr) {
                     break addr;
                 }
             };
             cursor.map(
-                VirtAddr::from_usize(addr as usize),
-                PhysAddr::from_usize((rng.random::<u64>() & vaddr_mask) as usize),
-                PageSize::Size4K,
+                M::VirtAddr::from(addr as usize),
+                PhysAddr::from_usize((rng.random::<u64>() & paddr_mask) as usize),
+                base_page_size,
                 MappingFlags::READ | MappingFlags::WRITE,
             )?;
         } else {
             // remove a mapping
             let addr = *pages.iter().next().unwrap();
-            cursor.unmap(VirtAddr::from_usize(addr as usize))?;
+            cursor.unmap(M::VirtAddr::from(addr as usize))?;
             pages.remove(&addr);
         }
     }
@@ -139,9 +133,554 @@ fn run_test_for<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>() -> Pa
     Ok(())
 }
 
-#[cfg(target_pointer_width = "32")]
-fn run_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>() -> PagingResult<()>
-{
+fn run_huge_page_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+
+    // map every supported page size at an address with the highest VA bit
+    // below the sign bit set, so all levels of the table are exercised.
+    let base = 1usize << (M::VA_MAX_BITS - 2);
+    let mut vaddr = base;
+    for &page_size in M::PAGE_SIZES {
+        let paddr = PhysAddr::from_usize(page_size as usize);
+        let mut cursor = table.cursor();
+        cursor.map_region(
+            M::VirtAddr::from(vaddr),
+            |_| paddr,
+            page_size as usize,
+            flags,
+            true,
+        )?;
+        drop(cursor);
+        let off = (page_size as usize / 2) & !0xfff;
+        assert_eq!(
+            table.query(M::VirtAddr::from(vaddr + off)),
+            Ok((paddr.add(off), flags, page_size))
+        );
+        vaddr += page_size as usize;
+    }
+
+    let mut cursor = table.cursor();
+    cursor.unmap_region(M::VirtAddr::from(base), vaddr - base)?;
+    drop(cursor);
+    assert_eq!(
+        table.query(M::VirtAddr::from(base)),
+        Err(page_table_multiarch::PagingError::NotMapped)
+    );
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+/// Maps a 2M huge page and a 4K page and harvests them, the pages are
+/// reported if their entries are created with the accessed or dirty state.
+fn run_harvest_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let size = 0x20_0000 + 0x1000;
+    let created = PTE::new_page(PhysAddr::from_usize(paddr), flags, false).access_state();
+
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        M::VirtAddr::from(vaddr),
+        |va| PhysAddr::from_usize(va.into() - vaddr + paddr),
+        size,
+        flags,
+        true,
+    )?;
+    assert_eq!(
+        cursor.harvest(M::VirtAddr::from(vaddr + size), AccessState::all()),
+        Err(page_table_multiarch::PagingError::NotMapped)
+    );
+
+    // the region is not aligned, the pages are reported as a whole
+    let mut harvested = Vec::new();
+    cursor.harvest_region(
+        M::VirtAddr::from(vaddr + 0x1000),
+        size + 0x1000,
+        AccessState::all(),
+        |va, page_size, state| harvested.push((va.into(), page_size, state)),
+    )?;
+    let expected = [
+        (vaddr, PageSize::Size2M, created),
+        (vaddr + 0x20_0000, PageSize::Size4K, created),
+    ];
+    assert_eq!(
+        harvested,
+        if created.is_empty() {
+            &[][..]
+        } else {
+            &expected
+        }
+    );
+    harvested.clear();
+    cursor.harvest_region(
+        M::VirtAddr::from(vaddr),
+        size,
+        AccessState::all(),
+        |va, page_size, state| harvested.push((va.into(), page_size, state)),
+    )?;
+    assert!(harvested.is_empty());
+    drop(cursor);
+
+    // clearing the state keeps the mapping flags
+    assert_eq!(
+        table.query(M::VirtAddr::from(vaddr + 0x20_0000)),
+        Ok((
+            PhysAddr::from_usize(paddr + 0x20_0000),
+            flags,
+            PageSize::Size4K
+        ))
+    );
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+/// Protects and unmaps 4K pages in the middle of the largest huge page up to
+/// 1G, which is split into smaller pages.
+fn run_split_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    use page_table_multiarch::PagingError;
+
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let huge = *M::PAGE_SIZES
+        .iter()
+        .find(|&&size| size as usize <= 0x4000_0000)
+        .unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| M::VirtAddr::from(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        va(0),
+        |va| pa(va.into() - vaddr),
+        huge as usize,
+        flags,
+        true,
+    )?;
+    assert_eq!(cursor.query(va(0))?.2, huge);
+
+    let off = huge as usize / 2 + 0x3000;
+    cursor.protect_region(va(off), 0x1000, ro_flags)?;
+    cursor.unmap_region(va(off + 0x1000), 0x1000)?;
+    drop(cursor);
+    assert_eq!(
+        table.query(va(off)),
+        Ok((pa(off), ro_flags, PageSize::Size4K))
+    );
+    assert_eq!(table.query(va(off + 0x1000)), Err(PagingError::NotMapped));
+    assert_eq!(
+        table.query(va(off + 0x2000)),
+        Ok((pa(off + 0x2000), flags, PageSize::Size4K))
+    );
+    // the pages far from the change are still mapped by huge pages
+    let (query_paddr, query_flags, query_size) = table.query(va(0x1234))?;
+    assert_eq!((query_paddr, query_flags), (pa(0x1234), flags));
+    assert!((query_size as usize) < huge as usize);
+
+    let mut cursor = table.cursor();
+    cursor.unmap_region(va(0), off + 0x1000)?;
+    cursor.unmap_region(va(off + 0x2000), huge as usize - off - 0x2000)?;
+    drop(cursor);
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+/// Maps a 1G region by 2M pages and base pages, and some 2M regions by base
+/// pages, which are collapsed into huge pages if they are physically
+/// contiguous, have identical flags and are fully covered by the region.
+fn run_collapse_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    const HUGE: usize = 0x20_0000;
+    const GIANT: usize = 0x4000_0000;
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| M::VirtAddr::from(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+    let get_paddr = |va: M::VirtAddr| pa(va.into() - vaddr);
+
+    let mut cursor = table.cursor();
+    for off in (0..GIANT - HUGE).step_by(HUGE) {
+        cursor.map(va(off), pa(off), PageSize::Size2M, flags)?;
+    }
+    cursor.map_region(va(GIANT - HUGE), get_paddr, HUGE, flags, false)?;
+    // different flags
+    cursor.map_region(va(GIANT), get_paddr, HUGE, flags, false)?;
+    cursor.protect(va(GIANT + 0x5000), MappingFlags::READ)?;
+    // not aligned physically
+    cursor.map_region(
+        va(GIANT + HUGE),
+        |va| pa(va.into() - vaddr + 0x1000),
+        HUGE,
+        flags,
+        false,
+    )?;
+    cursor.map_region(va(GIANT + 2 * HUGE), get_paddr, HUGE, flags, false)?;
+    // partially covered by the region
+    cursor.map_region(va(GIANT + 3 * HUGE), get_paddr, HUGE, flags, false)?;
+
+    let allocated = ALLOCATED.with_borrow(|it| it.len());
+    let mut collapsed = Vec::new();
+    cursor.collapse_region(va(0), GIANT + 3 * HUGE + HUGE / 2, |va, size| {
+        collapsed.push((va.into(), size))
+    })?;
+    drop(cursor);
+    assert_eq!(
+        collapsed,
+        [
+            (vaddr + GIANT - HUGE, PageSize::Size2M),
+            (vaddr, PageSize::Size1G),
+            (vaddr + GIANT + 2 * HUGE, PageSize::Size2M),
+        ]
+    );
+    assert_eq!(ALLOCATED.with_borrow(|it| it.len()), allocated - 3);
+    assert_eq!(
+        table.query(va(0x1234)),
+        Ok((pa(0x1234), flags, PageSize::Size1G))
+    );
+    assert_eq!(
+        table.query(va(GIANT - 0x1000)),
+        Ok((pa(GIANT - 0x1000), flags, PageSize::Size1G))
+    );
+    for off in [GIANT, GIANT + HUGE, GIANT + 3 * HUGE] {
+        assert_eq!(table.query(va(off + 0x1234))?.2, PageSize::Size4K);
+    }
+    assert_eq!(
+        table.query(va(GIANT + 2 * HUGE + 0x1234)),
+        Ok((pa(GIANT + 2 * HUGE + 0x1234), flags, PageSize::Size2M))
+    );
+
+    table.cursor().unmap_region(va(0), GIANT + 4 * HUGE)?;
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+/// Unmaps 4K pages in two last level tables and a 2M huge page, and checks
+/// that the tables are deallocated once they become empty and the TLB is
+/// flushed.
+fn run_reclaim_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    const HUGE: usize = 0x20_0000;
+    const GIANT: usize = 0x4000_0000;
+    let allocated = || ALLOCATED.with_borrow(|it| it.len());
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let root = allocated();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| M::VirtAddr::from(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+
+    let mut cursor = table.cursor();
+    cursor.map(va(0), pa(0), PageSize::Size4K, flags)?;
+    cursor.map(va(0x1000), pa(0x1000), PageSize::Size4K, flags)?;
+    let full = allocated();
+    cursor.unmap(va(0))?;
+    cursor.flush();
+    assert_eq!(allocated(), full);
+    // deallocated after the flush
+    cursor.unmap(va(0x1000))?;
+    assert_eq!(allocated(), full);
+    cursor.flush();
+    assert_eq!(allocated(), root);
+
+    cursor.map_region(
+        va(HUGE - 0x3000),
+        |va| pa(va.into() - vaddr),
+        0x6000,
+        flags,
+        false,
+    )?;
+    cursor.map(va(GIANT), pa(GIANT), PageSize::Size2M, flags)?;
+    let full = allocated();
+    cursor.unmap_region(va(HUGE - 0x2000), 0x4000)?;
+    cursor.flush();
+    assert_eq!(allocated(), full);
+    cursor.unmap_region(va(HUGE - 0x3000), 0x1000)?;
+    cursor.unmap(va(HUGE + 0x2000))?;
+    cursor.flush();
+    assert!(allocated() < full);
+    assert_eq!(
+        cursor.query(va(GIANT)),
+        Ok((pa(GIANT), flags, PageSize::Size2M))
+    );
+    cursor.unmap_region(va(GIANT), HUGE)?;
+    drop(cursor);
+    assert_eq!(allocated(), root);
+
+    // the tables are created again
+    let mut cursor = table.cursor();
+    cursor.map(va(0), pa(0), PageSize::Size4K, flags)?;
+    assert_eq!(cursor.query(va(0)), Ok((pa(0), flags, PageSize::Size4K)));
+    cursor.unmap(va(0))?;
+    drop(cursor);
+    assert_eq!(allocated(), root);
+
+    drop(table);
+    assert_eq!(allocated(), 0, "Some frames were not deallocated");
+
+    Ok(())
+}
+
+/// Maps 4K, 2M and 1G pages sparsely, and iterates over the mappings in
+/// ranges that cover them fully or partially.
+fn run_mappings_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    const HUGE: usize = 0x20_0000;
+    const GIANT: usize = 0x4000_0000;
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| M::VirtAddr::from(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+    let range = |start: usize, end: usize| AddrRange::new(va(start), va(end));
+
+    let mut cursor = table.cursor();
+    cursor.map(va(0x1000), pa(0x1000), PageSize::Size4K, flags)?;
+    cursor.map(va(0x3000), pa(0x3000), PageSize::Size4K, ro_flags)?;
+    cursor.map(va(HUGE), pa(HUGE), PageSize::Size2M, flags)?;
+    cursor.map(va(GIANT), pa(GIANT), PageSize::Size1G, flags)?;
+    cursor.map(va(3 * GIANT + 0x5000), pa(0), PageSize::Size4K, flags)?;
+    drop(cursor);
+
+    let mappings = |range| -> PagingResult<Vec<_>> {
+        Ok(table
+            .mappings(range)?
+            .map(|(va, pa, flags, size)| (va.into() - vaddr, pa, flags, size))
+            .collect())
+    };
+    assert_eq!(
+        mappings(range(0, 4 * GIANT))?,
+        [
+            (0x1000, pa(0x1000), flags, PageSize::Size4K),
+            (0x3000, pa(0x3000), ro_flags, PageSize::Size4K),
+            (HUGE, pa(HUGE), flags, PageSize::Size2M),
+            (GIANT, pa(GIANT), flags, PageSize::Size1G),
+            (3 * GIANT + 0x5000, pa(0), flags, PageSize::Size4K),
+        ]
+    );
+    assert_eq!(
+        mappings(range(0x2000, HUGE + 0x1000))?,
+        [
+            (0x3000, pa(0x3000), ro_flags, PageSize::Size4K),
+            (HUGE, pa(HUGE), flags, PageSize::Size2M),
+        ]
+    );
+    assert_eq!(
+        mappings(range(GIANT + HUGE, GIANT + 2 * HUGE))?,
+        [(GIANT, pa(GIANT), flags, PageSize::Size1G)]
+    );
+    assert_eq!(mappings(range(2 * HUGE, GIANT))?, []);
+    assert_eq!(mappings(range(0x1000, 0x1000))?, []);
+    // stops early
+    let (va, ..) = table.mappings(range(0, 4 * GIANT))?.nth(1).unwrap();
+    assert_eq!(va.into(), vaddr + 0x3000);
+
+    // skips the non-canonical hole of sign-extended addresses
+    let upper = usize::MAX << (M::VA_MAX_BITS - 1);
+    if M::vaddr_is_valid(upper) && !M::vaddr_is_valid(1 << (M::VA_MAX_BITS - 1)) {
+        let base = *M::PAGE_SIZES.last().unwrap();
+        let lower = (1 << (M::VA_MAX_BITS - 1)) - base as usize;
+        let mut cursor = table.cursor();
+        cursor.map(M::VirtAddr::from(lower), pa(0), base, flags)?;
+        cursor.map(M::VirtAddr::from(upper), pa(0), base, flags)?;
+        drop(cursor);
+        let range = AddrRange::new(M::VirtAddr::from(lower), M::VirtAddr::from(usize::MAX));
+        let mappings: Vec<usize> = table.mappings(range)?.map(|(va, ..)| va.into()).collect();
+        assert_eq!(mappings, [lower, upper]);
+    }
+
+    Ok(())
+}
+
+/// Returns the indices of the set bits in a dirty-page bitmap.
+#[cfg(any(
+    target_arch = "riscv64",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    docsrs
+))]
+fn dirty_pages(bitmap: &[usize]) -> Vec<usize> {
+    let bits = usize::BITS as usize;
+    (0..bitmap.len() * bits)
+        .filter(|&i| bitmap[i / bits] & (1 << (i % bits)) != 0)
+        .collect()
+}
+
+/// Logs the dirty pages of a 2M huge page and four 4K pages, whose entries
+/// are made dirty by
+/// [`protect`](page_table_multiarch::PageTable64Cursor::protect),
+/// as in the page fault handler.
+#[cfg(any(target_arch = "riscv64", target_arch = "aarch64", docsrs))]
+fn run_dirty_log_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let vaddr = 0x4000_0000;
+    let size: usize = 0x20_0000 + 0x4000;
+    let va = |off: usize| M::VirtAddr::from(vaddr + off);
+    let mut bitmap = vec![0; (size / 0x1000).div_ceil(usize::BITS as usize)];
+
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        va(0),
+        |va| PhysAddr::from_usize(va.into() - vaddr + 0x8000_0000),
+        size,
+        flags,
+        true,
+    )?;
+    assert_eq!(
+        cursor.start_dirty_log(va(0x800), size),
+        Err(page_table_multiarch::PagingError::NotAligned)
+    );
+    cursor.start_dirty_log(va(0), size)?;
+    cursor.fetch_dirty_log(va(0), size, &mut bitmap)?;
+    assert_eq!(dirty_pages(&bitmap), []);
+
+    cursor.protect(va(0x12_3000), flags)?;
+    cursor.protect(va(0x20_2000), flags)?;
+    cursor.fetch_dirty_log(va(0), size, &mut bitmap)?;
+    assert_eq!(
+        dirty_pages(&bitmap),
+        (0..0x200).chain([0x202]).collect::<Vec<_>>()
+    );
+
+    // the dirty state is reset, and the fetched region may be a part
+    bitmap.fill(0);
+    cursor.protect(va(0x20_1000), flags)?;
+    cursor.fetch_dirty_log(va(0x20_0000), 0x4000, &mut bitmap)?;
+    assert_eq!(dirty_pages(&bitmap), [1]);
+    cursor.stop_dirty_log(va(0), size, flags)?;
+    drop(cursor);
+    assert_eq!(table.query(va(0x20_3000))?.1, flags);
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+/// Base physical address of the frames handed out by [`LowMemPagingHandler`].
+#[cfg(any(target_pointer_width = "32", docsrs))]
+const LOW_MEM_BASE: usize = 0x10_0000;
+/// Size of the arena backing [`LowMemPagingHandler`].
+#[cfg(any(target_pointer_width = "32", docsrs))]
+const LOW_MEM_SIZE: usize = 0x100_0000;
+
+#[cfg(any(target_pointer_width = "32", docsrs))]
+thread_local! {
+    // (arena base pointer, offset of the next free byte)
+    static LOW_MEM: RefCell<(usize, usize)> = const { RefCell::new((0, 0)) };
+}
+
+/// A paging handler that hands out frames from an arena at low physical
+/// addresses, so that they can be referenced by 32-bit page table entries on
+/// any host.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+struct LowMemPagingHandler;
+
+#[cfg(any(target_pointer_width = "32", docsrs))]
+impl PagingHandler for LowMemPagingHandler {
+    fn alloc_frames(num: usize, align: usize) -> Option<PhysAddr> {
+        let paddr = LOW_MEM.with_borrow_mut(|(base, next)| {
+            if *base == 0 {
+                let layout = Layout::from_size_align(LOW_MEM_SIZE, LOW_MEM_BASE).unwrap();
+                *base = unsafe { alloc::alloc(layout) } as usize;
+            }
+            let paddr = (LOW_MEM_BASE + *next).next_multiple_of(align);
+            *next = paddr + num * 4096 - LOW_MEM_BASE;
+            assert!(*next <= LOW_MEM_SIZE, "low memory arena exhausted");
+            paddr
+        });
+        ALLOCATED.with_borrow_mut(|it| {
+            for i in 0..num {
+                it.insert(paddr + i * 4096);
+            }
+        });
+        Some(PhysAddr::from_usize(paddr))
+    }
+
+    fn dealloc_frames(paddr: PhysAddr, num: usize) {
+        ALLOCATED.with_borrow_mut(|it| {
+            for i in 0..num {
+                let addr = paddr.as_usize() + i * 4096;
+                assert!(it.remove(&addr), "dealloc a frame that was not allocated");
+            }
+        });
+    }
+
+    fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+        assert!(paddr.as_usize() >= LOW_MEM_BASE);
+        let base = LOW_MEM.with_borrow(|(base, _)| *base);
+        VirtAddr::from_usize(base + paddr.as_usize() - LOW_MEM_BASE)
+    }
+}
+
+/// The mappings are queried back with `expected` flags, as some formats can
+/// not encode all the flags, e.g. x86 without PAE is always executable.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+fn run_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>(
+    expected: MappingFlags,
+) -> PagingResult<()> {
     use page_table_multiarch::PageTable32;
     ALLOCATED.with_borrow_mut(|it| {
         it.clear();
@@ -149,11 +688,12 @@ fn run_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
 
     let vaddr_mask = ((1u64 << M::VA_MAX_BITS) - 1) & !0xfff;
 
-    let mut table = PageTable32::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
     let mut pages = HashSet::new();
     let mut rng = SmallRng::seed_from_u64(5678);
     for _ in 0..512 {
         // Fewer iterations for 32-bit to avoid address space exhaustion
+        let mut cursor = table.cursor();
         if rng.random_ratio(3, 4) || pages.is_empty() {
             // insert a mapping
             let addr = loop {
@@ -162,18 +702,16 @@ fn run_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
                     break addr as u64;
                 }
             };
-            table
-                .map(
-                    VirtAddr::from_usize(addr as usize),
-                    PhysAddr::from_usize((rng.random::<u32>() & (vaddr_mask as u32)) as usize),
-                    PageSize::Size4K,
-                    MappingFlags::READ | MappingFlags::WRITE,
-                )?
-                .ignore();
+            cursor.map(
+                VirtAddr::from_usize(addr as usize),
+                PhysAddr::from_usize((rng.random::<u32>() & (vaddr_mask as u32)) as usize),
+                PageSize::Size4K,
+                MappingFlags::READ | MappingFlags::WRITE,
+            )?;
         } else {
             // remove a mapping
             let addr = *pages.iter().next().unwrap();
-            table.unmap(VirtAddr::from_usize(addr as usize))?.2.ignore();
+            cursor.unmap(VirtAddr::from_usize(addr as usize))?;
             pages.remove(&addr);
         }
     }
@@ -185,32 +723,954 @@ fn run_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
         "Some frames were not deallocated"
     );
 
+    // map every supported page size, then query and unmap them
+    let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let base = 1usize << (M::VA_MAX_BITS - 1);
+    let mut vaddr = base;
+    for &page_size in M::PAGE_SIZES {
+        let paddr = PhysAddr::from_usize(page_size as usize);
+        let mut cursor = table.cursor();
+        cursor.map_region(
+            VirtAddr::from_usize(vaddr),
+            |_| paddr,
+            page_size as usize,
+            flags,
+            true,
+        )?;
+        drop(cursor);
+        let off = (page_size as usize / 2) & !0xfff;
+        let (query_paddr, query_flags, query_size) =
+            table.query(VirtAddr::from_usize(vaddr + off))?;
+        assert_eq!((query_paddr, query_size), (paddr.add(off), page_size));
+        assert_eq!(query_flags, expected);
+        vaddr += page_size as usize;
+    }
+    table
+        .cursor()
+        .unmap_region(VirtAddr::from_usize(base), vaddr - base)?;
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
     Ok(())
 }
 
-#[test]
-#[cfg(any(target_arch = "arm", docsrs))]
-#[cfg(target_pointer_width = "32")]
-fn test_dealloc_arm32() -> PagingResult<()> {
-    run_test_for_32bit::<
-        page_table_multiarch::arm::A32PagingMetaData,
-        page_table_entry::arm::A32PTE,
-    >()?;
+/// Protects and unmaps 4K pages in the middle of the largest huge page, which
+/// is split into smaller pages, with and without executable mappings. The
+/// flags are compared with the pages mapped directly, as some formats can not
+/// encode all the flags.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+fn run_split_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
+-> PagingResult<()> {
+    use page_table_multiarch::{PageTable32, PagingError};
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let huge = M::PAGE_SIZES[0];
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+
+    let rw = MappingFlags::READ | MappingFlags::WRITE;
+    for flags in [rw, rw | MappingFlags::EXECUTE] {
+        let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
+        let mut cursor = table.cursor();
+        let reference = va(huge as usize);
+        cursor.map(reference, pa(0), PageSize::Size4K, flags)?;
+        cursor.map(
+            reference.add(0x1000),
+            pa(0),
+            PageSize::Size4K,
+            flags - MappingFlags::WRITE,
+        )?;
+        let rw_flags = cursor.query(reference)?.1;
+        let ro_flags = cursor.query(reference.add(0x1000))?.1;
+
+        cursor.map_region(
+            va(0),
+            |addr| pa(addr.as_usize() - vaddr),
+            huge as usize,
+            flags,
+            true,
+        )?;
+        assert_eq!(cursor.query(va(0))?.2, huge);
+        let off = huge as usize / 2 + 0x3000;
+        cursor.protect_region(va(off), 0x1000, flags - MappingFlags::WRITE)?;
+        cursor.unmap_region(va(off + 0x1000), 0x1000)?;
+        drop(cursor);
+        assert_eq!(
+            table.query(va(off)),
+            Ok((pa(off), ro_flags, PageSize::Size4K))
+        );
+        assert_eq!(table.query(va(off + 0x1000)), Err(PagingError::NotMapped));
+        assert_eq!(
+            table.query(va(off + 0x2000)),
+            Ok((pa(off + 0x2000), rw_flags, PageSize::Size4K))
+        );
+        let (query_paddr, _, query_size) = table.query(va(0x1234))?;
+        assert_eq!(query_paddr, pa(0x1234));
+        assert!((query_size as usize) < huge as usize);
+
+        let mut cursor = table.cursor();
+        cursor.unmap_region(va(0), off + 0x1000)?;
+        cursor.unmap_region(va(off + 0x2000), huge as usize - off - 0x2000)?;
+        cursor.unmap_region(reference, 0x2000)?;
+        drop(cursor);
+
+        drop(table);
+        assert_eq!(
+            ALLOCATED.with_borrow(|it| it.len()),
+            0,
+            "Some frames were not deallocated"
+        );
+    }
+
     Ok(())
 }
 
-#[test]
-#[cfg(any(target_arch = "x86_64", docsrs))]
-fn test_dealloc_x86() -> PagingResult<()> {
-    run_test_for::<
-        page_table_multiarch::x86_64::X64PagingMetaData,
-        page_table_entry::x86_64::X64PTE,
-    >()?;
+/// Maps three regions of the smallest huge page size by 4K pages, and
+/// collapses the one that has identical flags and is fully covered by the
+/// region into a huge page.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+fn run_collapse_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
+-> PagingResult<()> {
+    use page_table_multiarch::PageTable32;
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let huge = *M::PAGE_SIZES
+        .iter()
+        .rev()
+        .find(|&&size| size as usize >= 0x10_0000)
+        .unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        va(0),
+        |addr| pa(addr.as_usize() - vaddr),
+        3 * huge as usize,
+        flags,
+        false,
+    )?;
+    cursor.protect(va(huge as usize + 0x5000), MappingFlags::READ)?;
+    // some formats can not encode all the flags
+    let flags = cursor.query(va(0))?.1;
+
+    let allocated = ALLOCATED.with_borrow(|it| it.len());
+    let mut collapsed = Vec::new();
+    cursor.collapse_region(va(0), 2 * huge as usize + 0x1000, |va, size| {
+        collapsed.push((va, size))
+    })?;
+    drop(cursor);
+    assert_eq!(collapsed, [(va(0), huge)]);
+    assert_eq!(ALLOCATED.with_borrow(|it| it.len()), allocated - 1);
+    assert_eq!(table.query(va(0x1234)), Ok((pa(0x1234), flags, huge)));
+    for off in [huge as usize, 2 * huge as usize] {
+        assert_eq!(
+            table.query(va(off + 0x1234)),
+            Ok((pa(off + 0x1234), flags, PageSize::Size4K))
+        );
+    }
+
+    table.cursor().unmap_region(va(0), 3 * huge as usize)?;
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
     Ok(())
 }
 
-#[test]
-#[cfg(any(target_arch = "riscv32", target_arch = "riscv64", docsrs))]
+/// Unmaps 4K pages in two last level tables, and checks that the tables are
+/// deallocated once they become empty and the TLB is flushed.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+fn run_reclaim_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
+-> PagingResult<()> {
+    use page_table_multiarch::PageTable32;
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let allocated = || ALLOCATED.with_borrow(|it| it.len());
+    let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
+    let root = allocated();
+    if M::FIXED_ROOT_ENTRIES {
+        // The next level tables of the root entries are kept until drop
+        assert!(root > 1);
+    }
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let huge = *M::PAGE_SIZES
+        .iter()
+        .rev()
+        .find(|&&size| size as usize >= 0x10_0000)
+        .unwrap() as usize;
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+
+    let mut cursor = table.cursor();
+    cursor.map(va(0), pa(0), PageSize::Size4K, flags)?;
+    cursor.map(va(0x1000), pa(0x1000), PageSize::Size4K, flags)?;
+    let full = allocated();
+    cursor.unmap(va(0))?;
+    cursor.flush();
+    assert_eq!(allocated(), full);
+    // deallocated after the flush
+    cursor.unmap(va(0x1000))?;
+    assert_eq!(allocated(), full);
+    cursor.flush();
+    assert_eq!(allocated(), root);
+
+    cursor.map_region(
+        va(huge - 0x3000),
+        |addr| pa(addr.as_usize() - vaddr),
+        0x6000,
+        flags,
+        false,
+    )?;
+    let full = allocated();
+    cursor.unmap_region(va(huge - 0x2000), 0x4000)?;
+    cursor.flush();
+    assert_eq!(allocated(), full);
+    cursor.unmap_region(va(huge - 0x3000), 0x1000)?;
+    cursor.unmap(va(huge + 0x2000))?;
+    drop(cursor);
+    assert_eq!(allocated(), root);
+
+    drop(table);
+    assert_eq!(allocated(), 0, "Some frames were not deallocated");
+
+    Ok(())
+}
+
+/// Maps 4K pages and a huge page of the smallest size sparsely, and iterates
+/// over the mappings in ranges that cover them fully or partially.
+#[cfg(any(target_pointer_width = "32", docsrs))]
+fn run_mappings_test_for_32bit<M: PagingMetaData<VirtAddr = VirtAddr>, PTE: GenericPTE>()
+-> PagingResult<()> {
+    use page_table_multiarch::PageTable32;
+
+    let mut table = PageTable32::<M, PTE, LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let huge = *M::PAGE_SIZES
+        .iter()
+        .rev()
+        .find(|&&size| size as usize >= 0x10_0000)
+        .unwrap();
+    let vaddr = 0x4000_0000;
+    let paddr = 0x8000_0000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    let pa = |off: usize| PhysAddr::from_usize(paddr + off);
+    let range = |start: usize, end: usize| AddrRange::new(va(start), va(end));
+    let offsets = [0x1000, 0x3000, huge as usize, 3 * huge as usize + 0x5000];
+
+    let mut cursor = table.cursor();
+    for off in offsets {
+        let page_size = if off == huge as usize {
+            huge
+        } else {
+            PageSize::Size4K
+        };
+        cursor.map(va(off), pa(off), page_size, flags)?;
+    }
+    drop(cursor);
+
+    let mappings = table
+        .mappings(range(0, 4 * huge as usize))?
+        .collect::<Vec<_>>();
+    assert_eq!(
+        mappings.iter().map(|it| it.0).collect::<Vec<_>>(),
+        offsets.map(va)
+    );
+    for (vaddr, paddr, flags, size) in mappings {
+        assert_eq!(table.query(vaddr), Ok((paddr, flags, size)));
+    }
+    let starts =
+        |range| -> PagingResult<Vec<_>> { Ok(table.mappings(range)?.map(|it| it.0).collect()) };
+    assert_eq!(
+        starts(range(0x2000, huge as usize + 0x1000))?,
+        [va(0x3000), va(huge as usize)]
+    );
+    assert_eq!(
+        starts(range(huge as usize + 0x1000, huge as usize + 0x2000))?,
+        [va(huge as usize)]
+    );
+    assert_eq!(starts(range(2 * huge as usize, 3 * huge as usize))?, []);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_dealloc_arm32() -> PagingResult<()> {
+    run_test_for_32bit::<
+        page_table_multiarch::arm::A32PagingMetaData,
+        page_table_entry::arm::A32PTE,
+    >(MappingFlags::READ | MappingFlags::WRITE)?;
+    run_test_for_32bit::<
+        page_table_multiarch::arm::A32LpaePagingMetaData,
+        page_table_entry::arm::A32LpaePTE,
+    >(MappingFlags::READ | MappingFlags::WRITE)?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_split_huge_arm32() -> PagingResult<()> {
+    run_split_test_for_32bit::<
+        page_table_multiarch::arm::A32PagingMetaData,
+        page_table_entry::arm::A32PTE,
+    >()?;
+    run_split_test_for_32bit::<
+        page_table_multiarch::arm::A32LpaePagingMetaData,
+        page_table_entry::arm::A32LpaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_collapse_arm32() -> PagingResult<()> {
+    run_collapse_test_for_32bit::<
+        page_table_multiarch::arm::A32PagingMetaData,
+        page_table_entry::arm::A32PTE,
+    >()?;
+    run_collapse_test_for_32bit::<
+        page_table_multiarch::arm::A32LpaePagingMetaData,
+        page_table_entry::arm::A32LpaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_reclaim_arm32() -> PagingResult<()> {
+    run_reclaim_test_for_32bit::<
+        page_table_multiarch::arm::A32PagingMetaData,
+        page_table_entry::arm::A32PTE,
+    >()?;
+    run_reclaim_test_for_32bit::<
+        page_table_multiarch::arm::A32LpaePagingMetaData,
+        page_table_entry::arm::A32LpaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_mappings_arm32() -> PagingResult<()> {
+    run_mappings_test_for_32bit::<
+        page_table_multiarch::arm::A32PagingMetaData,
+        page_table_entry::arm::A32PTE,
+    >()?;
+    run_mappings_test_for_32bit::<
+        page_table_multiarch::arm::A32LpaePagingMetaData,
+        page_table_entry::arm::A32LpaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_contiguous_arm32() -> PagingResult<()> {
+    use page_table_multiarch::{PagingError, arm::A32PageTable};
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let mut table = A32PageTable::<LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+
+    // a 16M supersection and a 64K large page, 16 entries each
+    for (vaddr, paddr, page_size) in [
+        (0x4000_0000, 0x8100_0000, PageSize::Size16M),
+        (0x5001_0000, 0x9003_0000, PageSize::Size64K),
+    ] {
+        let last = vaddr + page_size as usize - 0x1000;
+        let mut cursor = table.cursor();
+        cursor.map_region(
+            VirtAddr::from_usize(vaddr),
+            |va| PhysAddr::from_usize(va.as_usize() - vaddr + paddr),
+            page_size as usize,
+            flags,
+            true,
+        )?;
+        assert_eq!(
+            cursor.map(
+                VirtAddr::from_usize(last),
+                PhysAddr::from_usize(0),
+                PageSize::Size4K,
+                flags
+            ),
+            Err(PagingError::AlreadyMapped)
+        );
+        // every replicated entry maps the whole page
+        assert_eq!(
+            cursor.protect(VirtAddr::from_usize(last), ro_flags)?,
+            page_size
+        );
+        drop(cursor);
+        for off in [0, page_size as usize / 2, page_size as usize - 0x1000] {
+            assert_eq!(
+                table.query(VirtAddr::from_usize(vaddr + off))?,
+                (PhysAddr::from_usize(paddr + off), ro_flags, page_size)
+            );
+        }
+
+        let mut cursor = table.cursor();
+        assert_eq!(
+            cursor.unmap(VirtAddr::from_usize(last))?,
+            (PhysAddr::from_usize(paddr), ro_flags, page_size)
+        );
+        drop(cursor);
+        assert_eq!(
+            table.query(VirtAddr::from_usize(vaddr)),
+            Err(PagingError::NotMapped)
+        );
+    }
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_dirty_log_arm32() -> PagingResult<()> {
+    use page_table_multiarch::arm::A32LpaePageTable;
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let mut table = A32LpaePageTable::<LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+    let vaddr = 0x4000_0000;
+    let size = 0x4000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    let mut bitmap = [0];
+
+    // the dirty state is not recorded by ARMv7, the pages are write-protected
+    // instead
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        va(0),
+        |va| PhysAddr::from_usize(va.as_usize() - vaddr + 0x8000_0000),
+        size,
+        flags,
+        false,
+    )?;
+    cursor.start_dirty_log(va(0), size)?;
+    assert_eq!(cursor.query(va(0x1000))?.1, ro_flags);
+
+    cursor.protect(va(0x1000), flags)?;
+    cursor.fetch_dirty_log(va(0), size, &mut bitmap)?;
+    assert_eq!(dirty_pages(&bitmap), [1]);
+    assert_eq!(cursor.query(va(0x1000))?.1, ro_flags);
+
+    bitmap.fill(0);
+    cursor.fetch_dirty_log(va(0), size, &mut bitmap)?;
+    assert_eq!(dirty_pages(&bitmap), []);
+    cursor.stop_dirty_log(va(0), size, flags)?;
+    drop(cursor);
+    for off in (0..size).step_by(0x1000) {
+        assert_eq!(table.query(va(off))?.1, flags);
+    }
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "arm", docsrs))]
+fn test_lpae_arm32() -> PagingResult<()> {
+    use page_table_multiarch::{PagingError, PhysAddr64, arm::A32LpaePageTable};
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let mut table = A32LpaePageTable::<LowMemPagingHandler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+
+    // a 2M block and a 4K page above 4GB
+    let block = PhysAddr64::from_u64(0xab_4020_0000);
+    let page = PhysAddr64::from_u64(0xff_ffff_f000);
+    let mut cursor = table.cursor();
+    cursor.map_region_large(
+        VirtAddr::from_usize(0x4000_0000),
+        |vaddr| block.add(vaddr.as_usize() - 0x4000_0000),
+        0x20_0000,
+        flags,
+        true,
+    )?;
+    cursor.map_large(
+        VirtAddr::from_usize(0x8000_0000),
+        page,
+        PageSize::Size4K,
+        flags,
+    )?;
+    drop(cursor);
+
+    assert_eq!(
+        table.query_large(VirtAddr::from_usize(0x4000_1234))?,
+        (block.add(0x1234), flags, PageSize::Size2M)
+    );
+    assert_eq!(
+        table.query_large(VirtAddr::from_usize(0x8000_0010))?,
+        (page.add(0x10), flags, PageSize::Size4K)
+    );
+    let mut cursor = table.cursor();
+    assert_eq!(
+        cursor.unmap_large(VirtAddr::from_usize(0x8000_0000))?,
+        (page, flags, PageSize::Size4K)
+    );
+
+    // split the block by unmapping a 4K page in it
+    cursor.unmap_region(VirtAddr::from_usize(0x4000_1000), 0x1000)?;
+    assert_eq!(
+        cursor.query_large(VirtAddr::from_usize(0x4000_2234))?,
+        (block.add(0x2234), flags, PageSize::Size4K)
+    );
+    assert_eq!(
+        cursor.query_large(VirtAddr::from_usize(0x4000_1000)),
+        Err(PagingError::NotMapped)
+    );
+
+    // collapse it back
+    cursor.map_large(
+        VirtAddr::from_usize(0x4000_1000),
+        block.add(0x1000),
+        PageSize::Size4K,
+        flags,
+    )?;
+    cursor.collapse_region(VirtAddr::from_usize(0x4000_0000), 0x20_0000, |_, _| {})?;
+    assert_eq!(
+        cursor.query_large(VirtAddr::from_usize(0x4000_2234))?,
+        (block.add(0x2234), flags, PageSize::Size2M)
+    );
+
+    // above the 40-bit output address
+    let vaddr = VirtAddr::from_usize(0x8000_0000);
+    assert_eq!(
+        cursor.map_large(
+            vaddr,
+            PhysAddr64::from_u64(1 << 40),
+            PageSize::Size4K,
+            flags
+        ),
+        Err(PagingError::InvalidPhysAddr)
+    );
+    assert_eq!(
+        cursor.map_region_large(
+            vaddr,
+            |vaddr| PhysAddr64::from_u64(0xff_ffe0_0000).add(vaddr.as_usize() - 0x8000_0000),
+            0x40_0000,
+            flags,
+            true,
+        ),
+        Err(PagingError::InvalidPhysAddr)
+    );
+    cursor.unmap_region(vaddr, 0x20_0000)?;
+    assert_eq!(cursor.query(vaddr), Err(PagingError::NotMapped));
+    drop(cursor);
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    // contiguous pages, which are rejected before creating any tables
+    struct ContiguousMetaData;
+
+    impl PagingMetaData for ContiguousMetaData {
+        const LEVELS: usize = 3;
+        const PA_MAX_BITS: usize = 32;
+        const VA_MAX_BITS: usize = 32;
+        const PAGE_SIZES: &'static [PageSize] =
+            &[PageSize::Size2M, PageSize::Size64K, PageSize::Size4K];
+        type VirtAddr = VirtAddr;
+
+        fn vaddr_is_valid(_vaddr: usize) -> bool {
+            true
+        }
+
+        fn flush_tlb(_vaddr: Option<VirtAddr>) {}
+    }
+
+    let mut table = page_table_multiarch::PageTable32::<
+        ContiguousMetaData,
+        page_table_entry::arm::A32LpaePTE,
+        LowMemPagingHandler,
+    >::try_new()?;
+    let root = ALLOCATED.with_borrow(|it| it.len());
+    assert_eq!(
+        table.cursor().map_large(
+            VirtAddr::from_usize(0x4000_0000),
+            PhysAddr64::from_u64(0xab_0000_0000),
+            PageSize::Size64K,
+            flags,
+        ),
+        Err(PagingError::UnsupportedPageSize)
+    );
+    assert_eq!(ALLOCATED.with_borrow(|it| it.len()), root);
+    drop(table);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", docsrs))]
+fn test_dealloc_x86_32() -> PagingResult<()> {
+    run_test_for_32bit::<
+        page_table_multiarch::x86::X86PagingMetaData,
+        page_table_entry::x86::X86PTE,
+    >(MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE)?;
+    run_test_for_32bit::<
+        page_table_multiarch::x86::X86PaePagingMetaData,
+        page_table_entry::x86::X86PaePTE,
+    >(MappingFlags::READ | MappingFlags::WRITE)?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", docsrs))]
+fn test_split_huge_x86_32() -> PagingResult<()> {
+    run_split_test_for_32bit::<
+        page_table_multiarch::x86::X86PagingMetaData,
+        page_table_entry::x86::X86PTE,
+    >()?;
+    run_split_test_for_32bit::<
+        page_table_multiarch::x86::X86PaePagingMetaData,
+        page_table_entry::x86::X86PaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", docsrs))]
+fn test_collapse_x86_32() -> PagingResult<()> {
+    run_collapse_test_for_32bit::<
+        page_table_multiarch::x86::X86PagingMetaData,
+        page_table_entry::x86::X86PTE,
+    >()?;
+    run_collapse_test_for_32bit::<
+        page_table_multiarch::x86::X86PaePagingMetaData,
+        page_table_entry::x86::X86PaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", docsrs))]
+fn test_reclaim_x86_32() -> PagingResult<()> {
+    run_reclaim_test_for_32bit::<
+        page_table_multiarch::x86::X86PagingMetaData,
+        page_table_entry::x86::X86PTE,
+    >()?;
+    run_reclaim_test_for_32bit::<
+        page_table_multiarch::x86::X86PaePagingMetaData,
+        page_table_entry::x86::X86PaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", docsrs))]
+fn test_mappings_x86_32() -> PagingResult<()> {
+    run_mappings_test_for_32bit::<
+        page_table_multiarch::x86::X86PagingMetaData,
+        page_table_entry::x86::X86PTE,
+    >()?;
+    run_mappings_test_for_32bit::<
+        page_table_multiarch::x86::X86PaePagingMetaData,
+        page_table_entry::x86::X86PaePTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_dealloc_x86() -> PagingResult<()> {
+    run_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()?;
+    run_test_for::<
+        page_table_multiarch::x86_64::X64La57PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_split_huge_x86() -> PagingResult<()> {
+    run_split_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_collapse_x86() -> PagingResult<()> {
+    run_collapse_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_reclaim_x86() -> PagingResult<()> {
+    run_reclaim_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_mappings_x86() -> PagingResult<()> {
+    run_mappings_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_harvest_x86() -> PagingResult<()> {
+    run_harvest_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_huge_page_x86() -> PagingResult<()> {
+    run_huge_page_test_for::<
+        page_table_multiarch::x86_64::X64PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()?;
+    run_huge_page_test_for::<
+        page_table_multiarch::x86_64::X64La57PagingMetaData,
+        page_table_entry::x86_64::X64PTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_invalid_addr_x86() -> PagingResult<()> {
+    use page_table_entry::x86_64::X64PTE;
+    use page_table_multiarch::{PagingError, x86_64::X64PagingMetaData};
+
+    type Handler = TrackPagingHandler<X64PagingMetaData>;
+    type PageTable = PageTable64<X64PagingMetaData, X64PTE, Handler>;
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let mut table = PageTable::try_new()?;
+    let mut cursor = table.cursor();
+
+    // non-canonical addresses
+    let vaddr = VirtAddr::from_usize(0x8000_0000_0000);
+    let paddr = PhysAddr::from_usize(0x1000);
+    assert_eq!(
+        cursor.map(vaddr, paddr, PageSize::Size4K, flags),
+        Err(PagingError::InvalidVirtAddr)
+    );
+    assert_eq!(
+        cursor.map_region(vaddr, |_| paddr, 0x2000, flags, false),
+        Err(PagingError::InvalidVirtAddr)
+    );
+    assert_eq!(cursor.query(vaddr), Err(PagingError::InvalidVirtAddr));
+
+    // physical addresses above 52 bits
+    let vaddr = VirtAddr::from_usize(0xffff_8000_0000_0000);
+    let bad_paddr = PhysAddr::from_usize(1 << 52);
+    assert_eq!(
+        cursor.map(vaddr, bad_paddr, PageSize::Size4K, flags),
+        Err(PagingError::InvalidPhysAddr)
+    );
+    cursor.map(vaddr, paddr, PageSize::Size4K, flags)?;
+    assert_eq!(
+        cursor.remap(vaddr, bad_paddr, flags),
+        Err(PagingError::InvalidPhysAddr)
+    );
+    drop(cursor);
+    assert_eq!(table.query(vaddr), Ok((paddr, flags, PageSize::Size4K)));
+    drop(table);
+
+    // table frames above `PA_MAX_ADDR`
+    struct NarrowPaMetaData;
+
+    impl PagingMetaData for NarrowPaMetaData {
+        const LEVELS: usize = 4;
+        const PA_MAX_BITS: usize = 12;
+        const VA_MAX_BITS: usize = 48;
+        type VirtAddr = VirtAddr;
+
+        fn flush_tlb(_vaddr: Option<VirtAddr>) {}
+    }
+
+    assert_eq!(
+        PageTable64::<NarrowPaMetaData, X64PTE, Handler>::try_new().err(),
+        Some(PagingError::InvalidPhysAddr)
+    );
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_pat_mem_type_x86() -> PagingResult<()> {
+    use page_table_entry::x86_64::{PatMemType, X64PTE};
+    use page_table_multiarch::x86_64::X64PagingMetaData;
+
+    type Handler = TrackPagingHandler<X64PagingMetaData>;
+    type PageTable = PageTable64<X64PagingMetaData, X64PTE, Handler>;
+
+    // The entry at `level` that translates `vaddr`, to set the memory types
+    // with the PAT bit, which are not reported by the mapping flags.
+    fn entry<'a>(root: PhysAddr, vaddr: usize, level: usize) -> &'a mut X64PTE {
+        let mut table = root.as_usize() as *mut X64PTE;
+        for l in 0..level {
+            let pte = unsafe { &*table.add((vaddr >> (39 - 9 * l)) & 511) };
+            table = pte.paddr().as_usize() as *mut X64PTE;
+        }
+        unsafe { &mut *table.add((vaddr >> (39 - 9 * level)) & 511) }
+    }
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let vaddr = 0x4000_0000;
+    let va = |off: usize| VirtAddr::from_usize(vaddr + off);
+    for mem_type in [PatMemType::WriteProtected, PatMemType::UncachedMinus] {
+        let mut table = PageTable::try_new()?;
+        let root = table.root_paddr();
+        let mut cursor = table.cursor();
+        cursor.map(
+            va(0),
+            PhysAddr::from_usize(0x8000_0000),
+            PageSize::Size2M,
+            flags,
+        )?;
+        entry(root, vaddr, 2).set_mem_type(mem_type, true);
+
+        // protect the huge page, with the flags reported by itself
+        let (_, huge_flags, _) = cursor.query(va(0))?;
+        cursor.protect_region(va(0), PageSize::Size2M as usize, huge_flags)?;
+        assert_eq!(entry(root, vaddr, 2).mem_type(true), mem_type);
+
+        // split it by protecting a 4K page
+        let ro_flags = huge_flags - MappingFlags::WRITE;
+        cursor.protect_region(va(0x1000), 0x1000, ro_flags)?;
+        assert_eq!(entry(root, vaddr, 3).mem_type(false), mem_type);
+        assert_eq!(entry(root, vaddr + 0x1000, 3).mem_type(false), mem_type);
+        assert_eq!(cursor.query(va(0x1000))?.1, ro_flags);
+
+        // collapse it back
+        cursor.protect_region(va(0x1000), 0x1000, huge_flags)?;
+        cursor.collapse_region(va(0), PageSize::Size2M as usize, |_, _| {})?;
+        assert!(entry(root, vaddr, 2).is_huge());
+        assert_eq!(entry(root, vaddr, 2).mem_type(true), mem_type);
+
+        // remap it to another huge page
+        let paddr = PhysAddr::from_usize(0x8020_0000);
+        cursor.remap(va(0), paddr, huge_flags)?;
+        assert_eq!(entry(root, vaddr, 2).mem_type(true), mem_type);
+        assert_eq!(
+            cursor.query(va(0x1000))?,
+            (paddr.add(0x1000), huge_flags, PageSize::Size2M)
+        );
+        drop(cursor);
+        drop(table);
+    }
+
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_ept() -> PagingResult<()> {
+    use page_table_entry::x86_64::EptPTE;
+    use page_table_multiarch::x86_64::EptMetaData;
+
+    assert_eq!(
+        EptMetaData::eptp(PhysAddr::from_usize(0x1234_5000), true),
+        0x1234_505e
+    );
+
+    run_test_for::<EptMetaData, EptPTE>()?;
+    run_huge_page_test_for::<EptMetaData, EptPTE>()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", docsrs))]
+fn test_npt() -> PagingResult<()> {
+    use page_table_entry::x86_64::X64PTE;
+    use page_table_multiarch::x86_64::{NptFlushHandler, NptMetaData, NptTlbControl};
+
+    thread_local! {
+        static TLB_CONTROL: std::cell::Cell<NptTlbControl> =
+            const { std::cell::Cell::new(NptTlbControl::DoNothing) };
+    }
+
+    struct NptFlushHandlerImpl;
+
+    impl NptFlushHandler for NptFlushHandlerImpl {
+        fn request_flush(control: NptTlbControl) {
+            TLB_CONTROL.set(control);
+        }
+    }
+
+    type M = NptMetaData<NptFlushHandlerImpl>;
+    M::flush_tlb(Some(0x1000.into()));
+    assert_eq!(TLB_CONTROL.get(), NptTlbControl::FlushGuest);
+
+    run_test_for::<M, X64PTE>()?;
+    run_huge_page_test_for::<M, X64PTE>()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64", docsrs))]
 fn test_dealloc_riscv() -> PagingResult<()> {
     run_test_for::<
         page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
@@ -220,6 +1680,232 @@ fn test_dealloc_riscv() -> PagingResult<()> {
         page_table_multiarch::riscv::Sv48MetaData<VirtAddr>,
         page_table_entry::riscv::Rv64PTE,
     >()?;
+    run_test_for::<
+        page_table_multiarch::riscv::Sv57MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_g_stage_riscv() -> PagingResult<()> {
+    use page_table_entry::riscv::Rv64PTE;
+    use page_table_multiarch::riscv::{Sv39x4MetaData, Sv48x4MetaData};
+
+    run_test_for::<Sv39x4MetaData, Rv64PTE>()?;
+    run_test_for::<Sv48x4MetaData, Rv64PTE>()?;
+    run_huge_page_test_for::<Sv39x4MetaData, Rv64PTE>()?;
+    run_huge_page_test_for::<Sv48x4MetaData, Rv64PTE>()?;
+
+    // the root table occupies 4 frames aligned to 16K
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let table =
+        PageTable64::<Sv39x4MetaData, Rv64PTE, TrackPagingHandler<Sv39x4MetaData>>::try_new()?;
+    assert!(table.root_paddr().is_aligned(0x4000usize));
+    assert_eq!(ALLOCATED.with_borrow(|it| it.len()), 4);
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv32", docsrs))]
+fn test_dealloc_riscv32() -> PagingResult<()> {
+    run_test_for_32bit::<
+        page_table_multiarch::riscv::Sv32MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv32PTE,
+    >(MappingFlags::READ | MappingFlags::WRITE)?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_huge_page_riscv() -> PagingResult<()> {
+    run_huge_page_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()?;
+    run_huge_page_test_for::<
+        page_table_multiarch::riscv::Sv48MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()?;
+    run_huge_page_test_for::<
+        page_table_multiarch::riscv::Sv57MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_harvest_riscv() -> PagingResult<()> {
+    run_harvest_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_dirty_log_riscv() -> PagingResult<()> {
+    run_dirty_log_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_split_huge_riscv() -> PagingResult<()> {
+    run_split_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_collapse_riscv() -> PagingResult<()> {
+    run_collapse_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_reclaim_riscv() -> PagingResult<()> {
+    run_reclaim_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_mappings_riscv() -> PagingResult<()> {
+    run_mappings_test_for::<
+        page_table_multiarch::riscv::Sv39MetaData<VirtAddr>,
+        page_table_entry::riscv::Rv64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "riscv64", docsrs))]
+fn test_napot_riscv() -> PagingResult<()> {
+    use page_table_entry::riscv::Rv64SvpbmtPTE;
+    use page_table_multiarch::riscv::{Sv39MetaData, Sv39NapotPageTable};
+
+    type Handler = TrackPagingHandler<Sv39MetaData<VirtAddr, true>>;
+    type PageTable = Sv39NapotPageTable<Handler, Rv64SvpbmtPTE>;
+    const NAPOT: usize = 1 << 63;
+
+    fn napot_entries(table: &PageTable) -> usize {
+        let count = std::cell::Cell::new(0);
+        table.walk(
+            usize::MAX,
+            Some(&|_, _, _, entry: &Rv64SvpbmtPTE| {
+                if entry.bits() & NAPOT != 0 {
+                    count.set(count.get() + 1);
+                }
+            }),
+            None,
+        );
+        count.get()
+    }
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+    run_huge_page_test_for::<Sv39MetaData<VirtAddr, true>, Rv64SvpbmtPTE>()?;
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let mut table = PageTable::try_new().unwrap();
+
+    // 4K + 64K, the 64K-aligned part is mapped by a NAPOT page
+    let vaddr = 0x8000_f000;
+    let paddr = 0x4000_f000;
+    let size = 0x1000 + 0x1_0000;
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        VirtAddr::from_usize(vaddr),
+        |va| PhysAddr::from_usize(va.as_usize() - vaddr + paddr),
+        size,
+        flags,
+        true,
+    )?;
+    drop(cursor);
+    assert_eq!(napot_entries(&table), 16);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(vaddr))?,
+        (PhysAddr::from_usize(paddr), flags, PageSize::Size4K)
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(vaddr + 0x5678))?,
+        (
+            PhysAddr::from_usize(paddr + 0x5678),
+            flags,
+            PageSize::Size64K
+        )
+    );
+
+    // protecting a single page splits the NAPOT page into 4K pages
+    let page = vaddr + 0x4000;
+    let mut cursor = table.cursor();
+    cursor.protect_region(VirtAddr::from_usize(page), 0x1000, ro_flags)?;
+    drop(cursor);
+    assert_eq!(napot_entries(&table), 0);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page))?,
+        (
+            PhysAddr::from_usize(page - vaddr + paddr),
+            ro_flags,
+            PageSize::Size4K
+        )
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page + 0x1000))?,
+        (
+            PhysAddr::from_usize(page + 0x1000 - vaddr + paddr),
+            flags,
+            PageSize::Size4K
+        )
+    );
+
+    // remapping the whole range restores the NAPOT page, and a partial unmap
+    // splits it again
+    let mut cursor = table.cursor();
+    cursor.unmap_region(VirtAddr::from_usize(vaddr), size)?;
+    cursor.map_region(
+        VirtAddr::from_usize(vaddr),
+        |va| PhysAddr::from_usize(va.as_usize() - vaddr + paddr),
+        size,
+        flags,
+        true,
+    )?;
+    drop(cursor);
+    assert_eq!(napot_entries(&table), 16);
+    let mut cursor = table.cursor();
+    cursor.unmap_region(VirtAddr::from_usize(page), 0x1000)?;
+    drop(cursor);
+    assert_eq!(napot_entries(&table), 0);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page)),
+        Err(page_table_multiarch::PagingError::NotMapped)
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page + 0x1000))?,
+        (
+            PhysAddr::from_usize(page + 0x1000 - vaddr + paddr),
+            flags,
+            PageSize::Size4K
+        )
+    );
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
     Ok(())
 }
 
@@ -230,6 +1916,319 @@ fn test_dealloc_aarch64() -> PagingResult<()> {
         page_table_multiarch::aarch64::A64PagingMetaData,
         page_table_entry::aarch64::A64PTE,
     >()?;
+    run_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData<page_table_multiarch::aarch64::Granule16K>,
+        page_table_entry::aarch64::A64PTE,
+    >()?;
+    run_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData<page_table_multiarch::aarch64::Granule64K>,
+        page_table_entry::aarch64::A64PTE,
+    >()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_huge_page_aarch64() -> PagingResult<()> {
+    use page_table_multiarch::aarch64::{A64PagingMetaData, Granule16K, Granule64K};
+    run_huge_page_test_for::<A64PagingMetaData, page_table_entry::aarch64::A64PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule16K>, page_table_entry::aarch64::A64PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule64K>, page_table_entry::aarch64::A64PTE>()?;
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_harvest_aarch64() -> PagingResult<()> {
+    run_harvest_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_dirty_log_aarch64() -> PagingResult<()> {
+    run_dirty_log_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_split_huge_aarch64() -> PagingResult<()> {
+    run_split_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_collapse_aarch64() -> PagingResult<()> {
+    run_collapse_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_reclaim_aarch64() -> PagingResult<()> {
+    run_reclaim_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_mappings_aarch64() -> PagingResult<()> {
+    run_mappings_test_for::<
+        page_table_multiarch::aarch64::A64PagingMetaData,
+        page_table_entry::aarch64::A64PTE,
+    >()
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_contiguous_aarch64() -> PagingResult<()> {
+    use page_table_entry::aarch64::A64PTE;
+    use page_table_multiarch::aarch64::{A64PageTable, A64PagingMetaData};
+
+    type Handler = TrackPagingHandler<A64PagingMetaData>;
+    const CONTIGUOUS: usize = 1 << 52;
+
+    fn contiguous_entries(table: &A64PageTable<Handler>) -> usize {
+        let count = std::cell::Cell::new(0);
+        table.walk(
+            usize::MAX,
+            Some(&|_, _, _, entry: &A64PTE| {
+                if entry.bits() & CONTIGUOUS != 0 {
+                    count.set(count.get() + 1);
+                }
+            }),
+            None,
+        );
+        count.get()
+    }
+
+    ALLOCATED.with_borrow_mut(|it| it.clear());
+    let mut table = A64PageTable::<Handler>::try_new().unwrap();
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let ro_flags = MappingFlags::READ;
+
+    // 32M + 64K, mapped by 16 contiguous 2M blocks and 16 contiguous 4K pages
+    let vaddr = 0x8000_0000;
+    let paddr = 0x4000_0000;
+    let size = 0x200_0000 + 0x1_0000;
+    let mut cursor = table.cursor();
+    cursor.map_region(
+        VirtAddr::from_usize(vaddr),
+        |va| PhysAddr::from_usize(va.as_usize() - vaddr + paddr),
+        size,
+        flags,
+        true,
+    )?;
+    drop(cursor);
+    assert_eq!(contiguous_entries(&table), 32);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(vaddr + 0x123_4567))?,
+        (
+            PhysAddr::from_usize(paddr + 0x123_4567),
+            flags,
+            PageSize::Size32M
+        )
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(vaddr + 0x200_5000))?,
+        (
+            PhysAddr::from_usize(paddr + 0x200_5000),
+            flags,
+            PageSize::Size64K
+        )
+    );
+
+    // protecting a single page breaks the 64K run into individual pages
+    let page = vaddr + 0x200_3000;
+    let mut cursor = table.cursor();
+    cursor.protect_region(VirtAddr::from_usize(page), 0x1000, ro_flags)?;
+    drop(cursor);
+    assert_eq!(contiguous_entries(&table), 16);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page))?,
+        (
+            PhysAddr::from_usize(page - vaddr + paddr),
+            ro_flags,
+            PageSize::Size4K
+        )
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(page + 0x1000))?,
+        (
+            PhysAddr::from_usize(page + 0x1000 - vaddr + paddr),
+            flags,
+            PageSize::Size4K
+        )
+    );
+
+    // unmapping a 2M block breaks the 32M run into individual blocks
+    let block = vaddr + 0x40_0000;
+    let mut cursor = table.cursor();
+    cursor.unmap_region(VirtAddr::from_usize(block), 0x20_0000)?;
+    drop(cursor);
+    assert_eq!(contiguous_entries(&table), 0);
+    assert_eq!(
+        table.query(VirtAddr::from_usize(block)),
+        Err(page_table_multiarch::PagingError::NotMapped)
+    );
+    assert_eq!(
+        table.query(VirtAddr::from_usize(block + 0x20_0000))?,
+        (
+            PhysAddr::from_usize(block + 0x20_0000 - vaddr + paddr),
+            flags,
+            PageSize::Size2M
+        )
+    );
+
+    drop(table);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_va_size_aarch64() -> PagingResult<()> {
+    use page_table_entry::aarch64::A64PTE;
+    use page_table_multiarch::aarch64::{A64PagingMetaData, Granule4K, Granule16K, Granule64K};
+
+    assert_eq!(A64PagingMetaData::<Granule4K>::START_LEVEL, 0);
+    assert_eq!(A64PagingMetaData::<Granule4K, 39>::START_LEVEL, 1);
+    assert_eq!(A64PagingMetaData::<Granule64K, 42>::START_LEVEL, 2);
+    assert_eq!(A64PagingMetaData::<Granule4K>::TCR_EL1_VALUE, 0x5_8010_0010);
+    assert_eq!(
+        A64PagingMetaData::<Granule64K, 52>::TCR_EL1_VALUE,
+        0x5_c00c_400c
+    );
+
+    run_test_for::<A64PagingMetaData<Granule4K, 39>, A64PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule4K, 42>, A64PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule16K, 36>, A64PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule64K, 42>, A64PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule64K, 52>, A64PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule4K, 39>, A64PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule64K, 42>, A64PTE>()?;
+    Ok(())
+}
+
+/// Copies all root entries from another page table, including the last one of
+/// a root table that may be larger than a page (e.g., concatenated tables).
+#[cfg(all(feature = "copy-from", any(target_arch = "aarch64", docsrs)))]
+fn run_copy_from_test_for<M: PagingMetaData, PTE: GenericPTE>() -> PagingResult<()> {
+    ALLOCATED.with_borrow_mut(|it| {
+        it.clear();
+    });
+
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    let page_size = *M::PAGE_SIZES.last().unwrap();
+    let last = (1usize << M::VA_MAX_BITS) - page_size as usize;
+    let paddr = PhysAddr::from_usize(0x8000_0000);
+    let mut src = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let mut dst = PageTable64::<M, PTE, TrackPagingHandler<M>>::try_new().unwrap();
+    let mut cursor = src.cursor();
+    cursor.map(M::VirtAddr::from(0), paddr, page_size, flags)?;
+    cursor.map(M::VirtAddr::from(last), paddr, page_size, flags)?;
+    drop(cursor);
+
+    // the tables of `dst` are replaced
+    let mut cursor = dst.cursor();
+    cursor.map(
+        M::VirtAddr::from(last),
+        PhysAddr::from_usize(0),
+        page_size,
+        flags,
+    )?;
+    cursor.copy_from(&src, M::VirtAddr::from(0), 1 << M::VA_MAX_BITS)?;
+    assert_eq!(
+        cursor.copy_from(&src, M::VirtAddr::from(last), usize::MAX),
+        Err(page_table_multiarch::PagingError::InvalidVirtAddr)
+    );
+    drop(cursor);
+    for vaddr in [0, last] {
+        assert_eq!(
+            dst.query(M::VirtAddr::from(vaddr)),
+            Ok((paddr, flags, page_size))
+        );
+    }
+
+    drop(dst);
+    assert_eq!(
+        src.query(M::VirtAddr::from(last)),
+        Ok((paddr, flags, page_size))
+    );
+    drop(src);
+    assert_eq!(
+        ALLOCATED.with_borrow(|it| it.len()),
+        0,
+        "Some frames were not deallocated"
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_stage2_aarch64() -> PagingResult<()> {
+    use page_table_entry::aarch64::A64S2PTE;
+    use page_table_multiarch::aarch64::{A64Stage2PagingMetaData, Granule4K, Granule64K};
+
+    // 2 concatenated tables at level 1
+    assert_eq!(A64Stage2PagingMetaData::<Granule4K, 40>::START_LEVEL, 1);
+    assert_eq!(
+        A64Stage2PagingMetaData::<Granule4K, 40>::VTCR_EL2_VALUE,
+        0x5_0058
+    );
+    // 8 concatenated tables at level 2
+    assert_eq!(A64Stage2PagingMetaData::<Granule64K, 45>::START_LEVEL, 2);
+
+    run_test_for::<A64Stage2PagingMetaData, A64S2PTE<false>>()?;
+    run_test_for::<A64Stage2PagingMetaData<Granule4K, 42>, A64S2PTE<true>>()?;
+    run_test_for::<A64Stage2PagingMetaData<Granule4K, 48>, A64S2PTE<false>>()?;
+    run_test_for::<A64Stage2PagingMetaData<Granule64K, 45>, A64S2PTE<false>>()?;
+    run_huge_page_test_for::<A64Stage2PagingMetaData, A64S2PTE<false>>()?;
+    run_huge_page_test_for::<A64Stage2PagingMetaData<Granule64K, 45>, A64S2PTE<true>>()?;
+    #[cfg(feature = "copy-from")]
+    {
+        run_copy_from_test_for::<A64Stage2PagingMetaData, A64S2PTE<false>>()?;
+        // 65536 entries in the root table
+        run_copy_from_test_for::<A64Stage2PagingMetaData<Granule64K, 45>, A64S2PTE<false>>()?;
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(any(target_arch = "aarch64", docsrs))]
+fn test_lpa_aarch64() -> PagingResult<()> {
+    use page_table_entry::aarch64::{A64Lpa2PTE, A64LpaPTE};
+    use page_table_multiarch::aarch64::{A64PagingMetaData, Granule4K, Granule16K, Granule64K};
+
+    assert_eq!(A64PagingMetaData::<Granule4K, 52, 52>::START_LEVEL, -1);
+    assert_eq!(
+        A64PagingMetaData::<Granule4K, 48, 52>::TCR_EL1_VALUE,
+        0x800_0006_8010_0010
+    );
+
+    run_test_for::<A64PagingMetaData<Granule4K, 52, 52>, A64Lpa2PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule16K, 52, 52>, A64Lpa2PTE>()?;
+    run_test_for::<A64PagingMetaData<Granule64K, 52, 52>, A64LpaPTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule4K, 52, 52>, A64Lpa2PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule16K, 52, 52>, A64Lpa2PTE>()?;
+    run_huge_page_test_for::<A64PagingMetaData<Granule64K, 52, 52>, A64LpaPTE>()?;
     Ok(())
 }
 